@@ -35,6 +35,8 @@ C system::_raw_eh_nao
 C system::_raw_eh_invalid
 C system::_raw_eh_enve
 C system::_raw_eh_nap
+C system::_raw_eh_oom
+C system::_raw_eh_deadlock
 C system::_manual_eh
 r
 <|
@@ -113,6 +115,18 @@ v 101 U f"Attempted to invoke a feature that requires a virtual pointer integer,
 C raw::int::abort
 r
 <|
+|>system::_raw_eh_oom
+v 100 U f"raw::fatal::out_of_memory"
+v 101 U f"The memory usage exceeded the memory limit."
+C raw::int::abort
+r
+<|
+|>system::_raw_eh_deadlock
+v 100 U f"raw::coro::deadlock"
+v 101 U f"All coroutines are waiting for channels."
+C raw::int::abort
+r
+<|
 |>system::_env_init
 v 100 U f"3.14159265358979323846264338327950288"
 C raw::f64::from<str>
@@ -151,6 +165,19 @@ v 100 U f"system::coro::_coro_body"
 ~ raw::coro::spawn
 r
 <|
+|>system::coro::spawn_joinable
+d 0 v n
+D 101 1
+D 100 101
+D 0 100
+C raw::vec::push
+D 1 101
+C raw::vec::push
+D 0 101
+v 100 U f"system::coro::_coro_body"
+~ raw::coro::spawn<joinable>
+r
+<|
 |>system::coro::_coro_body
 D 100 0
 D 101 1
@@ -158,7 +185,12 @@ C system::coro::_init
 D 1 100
 C raw::vhw::expand<topsil>
 c 0
-C system::coro::_fini
+C raw::vhw::collect<topsil>
+D 100 2
+C system::coro::_cleanup
+D 2 100
+C raw::vhw::expand<topsil>
+~ raw::coro::exit
 <|
 |>system::coro::kill
 D 100 0
@@ -167,6 +199,14 @@ D 0 100
 ~ raw::coro::kill
 r
 <|
+|>system::coro::join
+~ raw::coro::join
+r
+<|
+|>system::coro::detach
+~ raw::coro::detach
+r
+<|
 |>system::cls::open
 C raw::hashmap::new
 D 100 0
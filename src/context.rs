@@ -4,14 +4,21 @@
 //!
 
 use crate::{
+    error::VmError,
     executor,
-    isa::{FuncPtr, Instruction, InterruptHandler, NativeFuncPtr, VirtFuncPtr},
-    vmem::Var,
+    isa::{FuncPtr, Instruction, IntAction, InterruptHandler, NativeFuncPtr, VirtFuncPtr},
+    vmem::{CreateNull, ObjectRef, StringRef, Var},
 };
-use anyhow::anyhow;
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
-use std::{cell::RefCell, collections::HashMap};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 /// Collection of functions. The performance of calling a function is important, so this have
 /// a thread-local cache.
@@ -26,23 +33,40 @@ static INTERRUPTIONS: Lazy<DashMap<Box<str>, InterruptHandler, ahash::RandomStat
 static VM_STATIC: Lazy<DashMap<Box<str>, Var, ahash::RandomState>> =
     Lazy::new(|| DashMap::with_capacity_and_hasher(16, ahash::RandomState::default()));
 
+/// Generator of owner tokens of threads.
+static OWNERS: AtomicU64 = AtomicU64::new(1);
+
 std::thread_local! {
     /// Thread-local cache of `FUNCTIONS`.
     static FUNCTIONS_CACHE: RefCell<HashMap<Box<str>, FuncPtr, ahash::RandomState>> = RefCell::new(sync_cache());
+    /// Owner token of the thread executing on this OS thread, or 0.
+    static OWNER: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Get the owner token of the thread executing on this OS thread, or 0 if no thread is executing.
+#[inline]
+pub fn owner() -> u64 {
+    OWNER.with(Cell::get)
+}
+
+/// Set the owner token of the thread executing on this OS thread. Returns the previous one.
+#[inline]
+pub fn set_owner(token: u64) -> u64 {
+    OWNER.with(|x| x.replace(token))
 }
 
 /// Patch functions.
 #[inline(always)]
-pub fn fpatch(origin: &str, patched: &str) -> Result<(), anyhow::Error> {
+pub fn fpatch(origin: &str, patched: &str) -> Result<(), VmError> {
     let origin_newname = format!("{}@origin", origin);
     let oon = FUNCTIONS
         .get(origin)
-        .ok_or_else(|| anyhow!("raw::fatal::no_such_func"))?
+        .ok_or_else(|| VmError::new("raw::fatal::no_such_func"))?
         .to_owned();
     FUNCTIONS.insert(origin_newname.into_boxed_str(), oon);
     let pon = FUNCTIONS
         .get(patched)
-        .ok_or_else(|| anyhow!("raw::fatal::no_such_func"))?
+        .ok_or_else(|| VmError::new("raw::fatal::no_such_func"))?
         .to_owned();
     FUNCTIONS.insert(Box::from(origin), pon);
     Ok(())
@@ -116,6 +140,37 @@ pub fn getstatic(name: &str) -> Option<Var> {
     VM_STATIC.get(name).map(|x| x.to_owned())
 }
 
+/// Update a static atomically. `f` gets the value, which is `None` if the static is not set, and
+/// may modify it. The static is locked while `f` is running, so `f` must not access statics.
+#[inline]
+pub fn updstatic<T>(name: &str, f: impl FnOnce(&mut Option<Var>) -> T) -> T {
+    use dashmap::mapref::entry::Entry;
+
+    match VM_STATIC.entry(Box::from(name)) {
+        Entry::Occupied(mut x) => {
+            let mut val = Some(x.get().to_owned());
+            let result = f(&mut val);
+            match val {
+                Some(y) => {
+                    x.insert(y);
+                }
+                None => {
+                    x.remove();
+                }
+            }
+            result
+        }
+        Entry::Vacant(x) => {
+            let mut val = None;
+            let result = f(&mut val);
+            if let Some(y) = val {
+                x.insert(y);
+            }
+            result
+        }
+    }
+}
+
 /// Make an interruption abort.
 #[inline(always)]
 pub fn intabort(name: &str, msg: Option<String>) {
@@ -131,34 +186,77 @@ pub fn intignore(name: &str) {
 pub fn intcatch(name: &str, fp: VirtFuncPtr) {
     INTERRUPTIONS.insert(Box::from(name), InterruptHandler::Handler(fp));
 }
-/// Perform an interruption. This will return if the handler is `Ignore`, or never return if the
-/// handler is `Abort`.
-pub fn int(name: &str) -> bool {
-    if let Some(x) = INTERRUPTIONS.get(name) {
-        match &*x {
-            InterruptHandler::Abort(y) => {
-                use ansi_term::{Color::Red, Style};
+/// Judge if an interruption has a handler, which may also ignore it or make it abort.
+#[inline(always)]
+pub fn inthandled(name: &str) -> bool {
+    INTERRUPTIONS.contains_key(name)
+}
+/// Print the message when an interruption makes the program abort. The caller should print the
+/// world view after this.
+pub fn abort_banner(name: &str, msg: Option<&str>) {
+    use ansi_term::{Color::Red, Style};
 
-                eprintln!(
-                    "{}",
-                    Style::new().underline().fg(Red).paint(" !!! ABORTED !!! ")
-                );
-                eprintln!("This unit aborted due to an fatal interruption.");
-                eprintln!("Interruption: {}", Red.paint(name));
-                if let Some(z) = y {
-                    eprintln!("     Message: {}\n", z);
-                }
-                eprint!("  World View: ");
+    eprintln!(
+        "{}",
+        Style::new().underline().fg(Red).paint(" !!! ABORTED !!! ")
+    );
+    eprintln!("This unit aborted due to an fatal interruption.");
+    eprintln!("Interruption: {}", Red.paint(name));
+    if let Some(z) = msg {
+        eprintln!("     Message: {}\n", z);
+    }
+    eprint!("  World View: ");
+}
 
-                return true;
-            }
-            InterruptHandler::Handler(y) => {
-                executor::start(Thread::new(y.clone()));
+/// Perform an interruption on a thread. This returns how the interrupted thread should continue.
+/// If `IntAction::Abort` is returned, the abort message has been printed.
+pub fn int(ctx: &Thread, intr: &VmError) -> IntAction {
+    // The handler is cloned out, because a handler may set interruption handlers itself.
+    let handler = match INTERRUPTIONS.get(intr.id()) {
+        Some(x) => x.to_owned(),
+        None => return IntAction::Resume,
+    };
+    match handler {
+        InterruptHandler::Ignore => IntAction::Resume,
+        InterruptHandler::Abort(y) => {
+            abort_banner(intr.id(), y.as_deref().or_else(|| intr.message()));
+            IntAction::Abort
+        }
+        InterruptHandler::Handler(y) => {
+            let (func, index) = ctx.fault_site();
+            let mut t = Thread::new(y);
+            // The handler acts on behalf of the interrupted thread, so it may unlock its locks.
+            t.owner = ctx.owner;
+            t.topsil[0] = Var::UString(StringRef::from(intr.id()));
+            t.topsil[1] = Var::UString(match intr.message() {
+                Some(z) => StringRef::from(z),
+                None => StringRef::null(),
+            });
+            t.topsil[2] = Var::UString(match func {
+                Some(z) => StringRef::from(&z[..]),
+                None => StringRef::null(),
+            });
+            t.topsil[3] = Var::U64(index as u64);
+            t.topsil[4] = match intr.payload() {
+                Some(z) => z.to_owned(),
+                None => Var::Object(ObjectRef::null()),
+            };
+            executor::start_noo(&mut t);
+            let action = IntAction::from_code(t.topsil[0].as_u8());
+            if action == IntAction::Abort {
+                abort_banner(intr.id(), intr.message());
             }
-            _ => (),
+            action
         }
     }
-    false
+}
+
+/// Get the name of a virtual function. This is slow, so it should only be used on cold paths.
+pub fn fname(fp: &VirtFuncPtr) -> Option<Box<str>> {
+    FUNCTIONS.iter().find_map(|x| match x.value() {
+        FuncPtr::Virtual(y) if Arc::ptr_eq(y, fp) => Some(x.key().to_owned()),
+        _ => None,
+    })
 }
 
 /// A function's context. This is private, because all operations should be through `Thread`, not
@@ -204,16 +302,16 @@ pub trait ExecUnit {
     fn jmp(&mut self, _: usize);
 
     /// Call another function. Fail if a native function is called and an interruption was thrown.
-    fn call(&mut self, _: FuncPtr) -> Result<(), anyhow::Error>;
+    fn call(&mut self, _: FuncPtr) -> Result<(), VmError>;
 
     /// Make this function return. Returns false if it fails.
     fn ret(&mut self) -> bool;
 
     /// Get a value from SIL.
-    fn sget(&self, _: usize) -> Result<&Var, anyhow::Error>;
+    fn sget(&self, _: usize) -> Result<&Var, VmError>;
 
     /// Set a value to SIL.
-    fn sset(&mut self, _: usize, _: Var) -> Result<(), anyhow::Error>;
+    fn sset(&mut self, _: usize, _: Var) -> Result<(), VmError>;
 }
 
 /// A thread's context that implements `ExecUnit`.
@@ -225,6 +323,8 @@ pub struct Thread {
     callstack: Vec<FnContext>,
     /// Top of SIL for argument passing. The size should be 50.
     topsil: Box<[Var]>,
+    /// Token which identifies this thread as the owner of locks.
+    owner: u64,
 }
 impl Thread {
     /// Create a new `Thread` with specified function pointer.
@@ -234,8 +334,38 @@ impl Thread {
             current: Some(FnContext::new(fp)),
             callstack: Vec::with_capacity(12),
             topsil: vec![Var::U8(0); 50].into_boxed_slice(),
+            owner: OWNERS.fetch_add(1, Ordering::Relaxed),
         }
     }
+    /// Get the token which identifies this thread as the owner of locks.
+    #[inline]
+    pub fn owner(&self) -> u64 {
+        self.owner
+    }
+    /// Get the name of the current function and the index of the last executed instruction.
+    #[inline]
+    pub fn fault_site(&self) -> (Option<Box<str>>, usize) {
+        match &self.current {
+            Some(x) => (fname(&x.fp), x.fc.saturating_sub(1)),
+            None => (None, 0),
+        }
+    }
+    /// Make the last executed instruction execute again. Returns false if the thread has finished.
+    #[inline]
+    pub fn retry(&mut self) -> bool {
+        match &mut self.current {
+            Some(x) => {
+                x.fc = x.fc.saturating_sub(1);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Judge if the thread has finished, so it cannot execute any more instructions.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.current.is_none()
+    }
 }
 impl ExecUnit for Thread {
     #[allow(clippy::should_implement_trait)]
@@ -248,7 +378,7 @@ impl ExecUnit for Thread {
         self.current.as_mut().unwrap().jmp(t)
     }
     #[inline]
-    fn call(&mut self, fp: FuncPtr) -> Result<(), anyhow::Error> {
+    fn call(&mut self, fp: FuncPtr) -> Result<(), VmError> {
         match fp {
             FuncPtr::Virtual(x) => {
                 let swps = self.current.take().unwrap();
@@ -274,17 +404,17 @@ impl ExecUnit for Thread {
         }
     }
     #[inline]
-    fn sget(&self, addr: usize) -> Result<&Var, anyhow::Error> {
+    fn sget(&self, addr: usize) -> Result<&Var, VmError> {
         if (0..100).contains(&addr) {
             Ok(unsafe { self.current.as_ref().unwrap().sil.get_unchecked(addr) })
         } else if (100..150).contains(&addr) {
             Ok(unsafe { self.topsil.get_unchecked(addr - 100) })
         } else {
-            Err(anyhow!("raw::fatal::segfault"))
+            Err(VmError::new("raw::fatal::segfault"))
         }
     }
     #[inline]
-    fn sset(&mut self, addr: usize, val: Var) -> Result<(), anyhow::Error> {
+    fn sset(&mut self, addr: usize, val: Var) -> Result<(), VmError> {
         if (0..100).contains(&addr) {
             unsafe {
                 *self.current.as_mut().unwrap().sil.get_unchecked_mut(addr) = val;
@@ -296,7 +426,7 @@ impl ExecUnit for Thread {
             }
             Ok(())
         } else {
-            Err(anyhow!("raw::fatal::segfault"))
+            Err(VmError::new("raw::fatal::segfault"))
         }
     }
 }
@@ -0,0 +1,118 @@
+//!
+//! The error type of euolaVM. All errors thrown by the executing engine and native functions are
+//! `VmError`s, and every `VmError` is performed as an interruption.
+//!
+
+use crate::vmem::Var;
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    io,
+};
+
+/// An error thrown in euolaVM. This is boxed, so passing it around is as cheap as a pointer.
+///
+/// Any host error can be converted to a `VmError` with `?`. I/O errors are thrown as
+/// `raw::fatal::io_error`, and others are thrown as `raw::fatal::host_error`. The host error is
+/// kept as the source, and its description is used as the message.
+pub struct VmError(Box<VmErrorInner>);
+
+/// Content of a `VmError`.
+struct VmErrorInner {
+    /// ID of the interruption.
+    id: Cow<'static, str>,
+    /// Human-readable message.
+    message: Option<String>,
+    /// The error that caused this error.
+    source: Option<Box<dyn Error + Send + Sync>>,
+    /// Payload of the interruption, such as the payload register of `Int`.
+    payload: Option<Var>,
+}
+
+impl VmError {
+    /// Create an error with specified interruption ID.
+    #[inline]
+    pub fn new(id: impl Into<Cow<'static, str>>) -> Self {
+        Self(Box::new(VmErrorInner {
+            id: id.into(),
+            message: None,
+            source: None,
+            payload: None,
+        }))
+    }
+    /// Create an error from an error of the host that is not a `std::error::Error`, such as an
+    /// `anyhow::Error`.
+    #[inline]
+    pub fn host(e: impl Display) -> Self {
+        Self::new("raw::fatal::host_error").with_message(e.to_string())
+    }
+    /// Set the message.
+    #[inline]
+    pub fn with_message(mut self, msg: impl Into<String>) -> Self {
+        self.0.message = Some(msg.into());
+        self
+    }
+    /// Set the source error.
+    #[inline]
+    pub fn with_source(mut self, e: impl Error + Send + Sync + 'static) -> Self {
+        self.0.source = Some(Box::new(e));
+        self
+    }
+    /// Set the payload.
+    #[inline]
+    pub fn with_payload(mut self, v: Var) -> Self {
+        self.0.payload = Some(v);
+        self
+    }
+    /// Get the interruption ID.
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+    /// Get the message.
+    #[inline]
+    pub fn message(&self) -> Option<&str> {
+        self.0.message.as_deref()
+    }
+    /// Get the source error.
+    #[inline]
+    pub fn source(&self) -> Option<&(dyn Error + Send + Sync + 'static)> {
+        self.0.source.as_deref()
+    }
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> Option<&Var> {
+        self.0.payload.as_ref()
+    }
+}
+impl<E: Error + Send + Sync + 'static> From<E> for VmError {
+    #[inline]
+    fn from(e: E) -> Self {
+        let id = if (&e as &dyn Error).is::<io::Error>() {
+            "raw::fatal::io_error"
+        } else {
+            "raw::fatal::host_error"
+        };
+        Self::new(id).with_message(e.to_string()).with_source(e)
+    }
+}
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.id)?;
+        if let Some(x) = &self.0.message {
+            write!(f, ": {}", x)?;
+        }
+        Ok(())
+    }
+}
+impl Debug for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VmError")
+            .field("id", &self.0.id)
+            .field("message", &self.0.message)
+            .field("source", &self.0.source)
+            .field("payload", &self.0.payload)
+            .finish()
+    }
+}
@@ -1,12 +1,25 @@
+pub mod green;
+pub mod reactor;
+
 use crate::{
-    context::{getfp, getstatic, int, putstatic, ExecUnit, Thread},
-    isa::{FuncPtr, Instruction},
-    libraw::iohmgr::{CeIdGen, FakeHasher},
+    context::{self, getfp, getstatic, int, inthandled, putstatic, ExecUnit, Thread},
+    error::VmError,
+    isa::{FuncPtr, Instruction, IntAction},
+    libraw::{
+        iohmgr::{CeIdGen, FakeHasher},
+        thread::sync,
+    },
     resolver::ins,
-    vmem::Var,
+    vmem::{mem, CreateNull, ObjectRef, Var},
+};
+use reactor::Reactor;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicBool, Ordering},
 };
-use anyhow::anyhow;
-use std::collections::HashMap;
+
+/// If an abort in a spawned thread exits the process.
+static ABORT_PROCESS: AtomicBool = AtomicBool::new(false);
 
 macro_rules! impl_vmb {
     ($a: expr, $b: tt, $c: expr) => {
@@ -14,44 +27,44 @@ macro_rules! impl_vmb {
             Var::I8(x) => match $c {
                 Var::I8(y) => Ok(Var::I8(*x $b *y)),
                 Var::U8(y) => Ok(Var::I8(*x $b (*y as i8))),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::U8(x) => match $c {
 				Var::I8(y) => Ok(Var::U8(*x $b (*y as u8))),
                 Var::U8(y) => Ok(Var::U8(*x $b *y)),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::I16(x) => match $c {
 				Var::I16(y) => Ok(Var::I16(*x $b *y)),
                 Var::U16(y) => Ok(Var::I16(*x $b (*y as i16))),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::U16(x) => match $c {
 				Var::I16(y) => Ok(Var::U16(*x $b (*y as u16))),
                 Var::U16(y) => Ok(Var::U16(*x $b *y)),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::I32(x) => match $c {
 				Var::I32(y) => Ok(Var::I32(*x $b *y)),
                 Var::U32(y) => Ok(Var::I32(*x $b (*y as i32))),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::U32(x) => match $c {
 				Var::I32(y) => Ok(Var::U32(*x $b (*y as u32))),
                 Var::U32(y) => Ok(Var::U32(*x $b *y)),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::I64(x) => match $c {
 				Var::I64(y) => Ok(Var::I64(*x $b *y)),
                 Var::U64(y) => Ok(Var::I64(*x $b (*y as i64))),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::U64(x) => match $c {
 				Var::I64(y) => Ok(Var::U64(*x $b (*y as u64))),
                 Var::U64(y) => Ok(Var::U64(*x $b *y)),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
-            _ => Err(anyhow!("raw::fatal::not_an_integer")),
+            _ => Err(VmError::new("raw::fatal::not_an_integer")),
         }
     }
 }
@@ -61,44 +74,44 @@ macro_rules! impl_vbe {
             Var::I8(x) => match $c {
                 Var::I8(y) => Ok(*x $b *y),
                 Var::U8(y) => Ok(*x $b (*y as i8)),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::U8(x) => match $c {
 				Var::I8(y) => Ok(*x $b (*y as u8)),
                 Var::U8(y) => Ok(*x $b *y),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::I16(x) => match $c {
 				Var::I16(y) => Ok(*x $b *y),
                 Var::U16(y) => Ok(*x $b (*y as i16)),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::U16(x) => match $c {
 				Var::I16(y) => Ok(*x $b (*y as u16)),
                 Var::U16(y) => Ok(*x $b *y),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::I32(x) => match $c {
 				Var::I32(y) => Ok(*x $b *y),
                 Var::U32(y) => Ok(*x $b (*y as i32)),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::U32(x) => match $c {
 				Var::I32(y) => Ok(*x $b (*y as u32)),
                 Var::U32(y) => Ok(*x $b *y),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::I64(x) => match $c {
 				Var::I64(y) => Ok(*x $b *y),
                 Var::U64(y) => Ok(*x $b (*y as i64)),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
             Var::U64(x) => match $c {
 				Var::I64(y) => Ok(*x $b (*y as u64)),
                 Var::U64(y) => Ok(*x $b *y),
-                _ => Err(anyhow!("raw::fatal::math_type_error")),
+                _ => Err(VmError::new("raw::fatal::math_type_error")),
             },
-            _ => Err(anyhow!("raw::fatal::not_an_integer")),
+            _ => Err(VmError::new("raw::fatal::not_an_integer")),
         }
     }
 }
@@ -111,40 +124,42 @@ macro_rules! core_inner {
                 a,
                 match ins(&*b, &*c) {
                     Ok(x) => x,
-                    Err(_) => return Err(anyhow!("raw::fatal::dynset_error")),
+                    Err(x) => {
+                        return Err(VmError::new("raw::fatal::dynset_error").with_message(x.to_string()))
+                    }
                 },
             )?,
             Instruction::IsNull(a, b) => $b.sset(b, Var::U8($b.sget(a)?.is_null()? as u8))?,
             Instruction::GetStatic(a, b) => $b.sset(
                 b,
-                getstatic(&*a).ok_or_else(|| anyhow!("raw::fatal::static_not_found"))?,
+                getstatic(&*a).ok_or_else(|| VmError::new("raw::fatal::static_not_found"))?,
             )?,
             Instruction::SetStatic(a, b) => putstatic(&*a, $b.sget(b)?.to_owned()),
             Instruction::GetField(a, b, c) => $b.sset(
                 c,
                 $b.sget(a)?
                     .as_objref()
-                    .ok_or_else(|| anyhow!("raw::fatal::not_an_object"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::not_an_object"))?
                     .get(&*b)?
                     .to_owned(),
             )?,
             Instruction::SetField(a, b, c) => $b
                 .sget(a)?
                 .as_objref()
-                .ok_or_else(|| anyhow!("raw::fatal::not_an_object"))?
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_object"))?
                 .set(&*b, $b.sget(c)?.to_owned())?,
             Instruction::OffsetGet(a, b, c) => $b.sset(
                 c,
                 $b.sget(a)?.offset_get(
                     $b.sget(b)?
                         .as_usize()
-                        .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))?,
+                        .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))?,
                 )?,
             )?,
             Instruction::OffsetSet(a, b, c) => $b.sget(a)?.offset_set(
                 $b.sget(b)?
                     .as_usize()
-                    .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))?,
+                    .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))?,
                 $b.sget(c)?.to_owned(),
             )?,
             Instruction::GetTypeId(a, b) => $b.sset(b, Var::UString($b.sget(a)?.typeid()?.into()))?,
@@ -158,9 +173,9 @@ macro_rules! core_inner {
                 if !$b
                     .sget(b)?
                     .is_not_zero()
-                    .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?
                 {
-                    return Err(anyhow!("raw::fatal::divide_zero"));
+                    return Err(VmError::new("raw::fatal::divide_zero"));
                 }
                 $b.sset(c, impl_vmb!($b.sget(a)?, /, $b.sget(b)?)?)?
             }
@@ -168,9 +183,9 @@ macro_rules! core_inner {
                 if !$b
                     .sget(b)?
                     .is_not_zero()
-                    .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?
                 {
-                    return Err(anyhow!("raw::fatal::divide_zero"));
+                    return Err(VmError::new("raw::fatal::divide_zero"));
                 }
                 $b.sset(c, impl_vmb!($b.sget(a)?, %, $b.sget(b)?)?)?
             }
@@ -178,7 +193,7 @@ macro_rules! core_inner {
             Instruction::Or(a, b, c) => $b.sset(c, impl_vmb!($b.sget(a)?, |, $b.sget(b)?)?)?,
             Instruction::Not(a, b) => $b.sset(
                 b,
-                bnot($b.sget(a)?).ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                bnot($b.sget(a)?).ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
             )?,
             Instruction::Xor(a, b, c) => $b.sset(c, impl_vmb!($b.sget(a)?, ^, $b.sget(b)?)?)?,
             Instruction::Shl(a, b, c) => $b.sset(c, impl_vmb!($b.sget(a)?, <<, $b.sget(b)?)?)?,
@@ -197,25 +212,30 @@ macro_rules! core_inner {
                 if $b
                     .sget(a)?
                     .is_not_zero()
-                    .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?
                 {
                     $b.jmp(b)
                 }
             }
             Instruction::Call(a) => {
-                $b.call(getfp(&*a).ok_or_else(|| anyhow!("raw::fatal::no_such_func"))?)?
+                $b.call(getfp(&*a).ok_or_else(|| VmError::new("raw::fatal::no_such_func"))?)?
             }
             Instruction::CallPtr(a) => $b.call(
                 getfp(
                     &*$b
                         .sget(a)?
                         .as_sr()
-                        .ok_or_else(|| anyhow!("raw::fatal::segfault"))?
+                        .ok_or_else(|| VmError::new("raw::fatal::segfault"))?
                         .borrow()?,
                 )
-                .ok_or_else(|| anyhow!("raw::fatal::no_such_func"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::no_such_func"))?,
             )?,
-            Instruction::Int(a) => return Err(anyhow!("{}", &*a)),
+            Instruction::Int(a, b) => {
+                return Err(match b {
+                    Some(b) => VmError::new(String::from(a)).with_payload($b.sget(b)?.to_owned()),
+                    None => VmError::new(String::from(a)),
+                })
+            }
             Instruction::Ret => {
                 if !$b.ret() {
                     return Ok(());
@@ -227,16 +247,32 @@ macro_rules! core_inner {
 }
 
 /// Core executing engine.
-pub fn core(ctx: &mut Thread) -> Result<(), anyhow::Error> {
+pub fn core(ctx: &mut Thread) -> Result<(), VmError> {
+    // Natives find the owner of locks through this, because a task may be resumed by any OS thread.
+    let owner = context::set_owner(ctx.owner());
+    let result = core_loop(ctx);
+    context::set_owner(owner);
+    result
+}
+
+/// The loop of `core`.
+#[inline(always)]
+fn core_loop(ctx: &mut Thread) -> Result<(), VmError> {
+    let mut budget = reactor::budget();
     loop {
+        mem::check()?;
+        if budget == 0 {
+            return Err(VmError::new("raw::coro::yield"));
+        }
+        budget -= 1;
         let cur = match ctx.next() {
             Some(x) => x,
             None => {
                 let stat = ctx.ret();
                 if !stat {
-                    return Err(anyhow!("raw::fatal::early_eof"));
+                    return Err(VmError::new("raw::fatal::early_eof"));
                 } else {
-                    return Err(anyhow!("raw::fatal::func_not_returned"));
+                    return Err(VmError::new("raw::fatal::func_not_returned"));
                 }
             }
         };
@@ -244,125 +280,356 @@ pub fn core(ctx: &mut Thread) -> Result<(), anyhow::Error> {
     }
 }
 
+/// Handle an error thrown by `core`. Returns `true` if the thread should continue executing.
+fn fault(ctx: &mut Thread, x: VmError) -> bool {
+    match int(ctx, &x) {
+        IntAction::Resume => !ctx.is_finished(),
+        IntAction::Retry => ctx.retry(),
+        IntAction::Unwind => false,
+        IntAction::Abort => {
+            eprintln!("{:?}", ctx);
+            eprintln!("\nAborting...");
+            std::process::exit(-1);
+        }
+    }
+}
+
 /// Start a thread.
 pub fn start(mut ctx: Thread) {
-    loop {
-        let val = core(&mut ctx);
-        let tmp;
-        match val {
-            Ok(()) => break,
-            Err(x) => {
-                if int(match x.downcast::<&str>() {
-                    Ok(x) => x,
-                    Err(x) => {
-                        tmp = x.downcast::<String>().unwrap();
-                        &tmp
-                    }
-                }) {
-                    eprintln!("{:?}", ctx);
-                    eprintln!("\nAborting...");
-                    std::process::exit(-1);
-                }
+    start_noo(&mut ctx);
+    sync::release(ctx.owner());
+}
+
+/// Start a thread with NO OWNERSHIP.
+pub fn start_noo(ctx: &mut Thread) {
+    reactor::with_async(false, || {
+        while let Err(x) = core(ctx) {
+            if !fault(ctx, x) {
+                break;
             }
         }
+    })
+}
+
+/// Start a spawned thread. Returns its return registers, or the interruption that terminated it.
+/// An abort only ends this thread, unless `set_abort_process(true)` is called.
+pub fn start_thread(mut ctx: Thread) -> Result<Vec<Var>, VmError> {
+    let owner = ctx.owner();
+    let result = reactor::with_async(false, || loop {
+        let x = match core(&mut ctx) {
+            Ok(()) => return Ok(returns(&ctx)),
+            Err(x) => x,
+        };
+        match int(&ctx, &x) {
+            IntAction::Resume if !ctx.is_finished() => (),
+            IntAction::Retry if ctx.retry() => (),
+            IntAction::Abort => {
+                terminate(&ctx);
+                return Err(x);
+            }
+            _ => return Err(x),
+        }
+    });
+    sync::release(owner);
+    result
+}
+
+/// Report an aborted thread, and exit the process if aborts are configured to do so.
+pub(super) fn terminate(ctx: &Thread) {
+    eprintln!("{:?}", ctx);
+    if ABORT_PROCESS.load(Ordering::Relaxed) {
+        eprintln!("\nAborting...");
+        std::process::exit(-1);
     }
+    eprintln!("\nTerminating the thread...");
 }
 
-/// Start a thread with NO OWNERSHIP.
-pub fn start_noo(ctx: &mut Thread) {
-    loop {
-        let val = core(ctx);
-        let tmp;
-        match val {
-            Ok(()) => break,
-            Err(x) => {
-                if int(match x.downcast::<&str>() {
-                    Ok(x) => x,
-                    Err(x) => {
-                        tmp = x.downcast::<String>().unwrap();
-                        &tmp
-                    }
-                }) {
-                    eprintln!("{:?}", ctx);
-                    eprintln!("\nAborting...");
-                    std::process::exit(-1);
-                }
+/// Set if an abort in a spawned thread exits the process.
+#[inline]
+pub fn set_abort_process(on: bool) {
+    ABORT_PROCESS.store(on, Ordering::Relaxed);
+}
+
+/// Resume a generator until it yields a value with `raw::gen::yield`, which is returned, or it
+/// finishes, then `None` is returned. If an interruption isn't resumed by its handler, the generator
+/// finishes and the interruption is returned. Like spawned threads, an abort only finishes the
+/// generator, unless aborts are configured to exit the process. The generator is not in coroutine
+/// mode.
+pub fn start_gen(ctx: &mut Thread) -> Result<Option<Var>, VmError> {
+    let result = reactor::with_async(false, || loop {
+        let x = match core(ctx) {
+            Ok(()) => return Ok(None),
+            Err(x) => x,
+        };
+        if x.id() == "raw::gen::yield" {
+            return Ok(Some(match x.payload() {
+                Some(z) => z.to_owned(),
+                None => Var::Object(ObjectRef::null()),
+            }));
+        }
+        match int(ctx, &x) {
+            IntAction::Resume if !ctx.is_finished() => (),
+            IntAction::Retry if ctx.retry() => (),
+            IntAction::Abort => {
+                terminate(ctx);
+                return Err(x);
             }
+            _ => return Err(x),
         }
+    });
+    if !matches!(result, Ok(Some(_))) {
+        sync::release(ctx.owner());
     }
+    result
 }
 
-/// Start a thread with coroutines.
+/// Outcomes of finished coroutines, which are kept until they are joined. An outcome is only kept
+/// if the coroutine is spawned as joinable, or another coroutine is joining it when it finishes.
+#[derive(Default)]
+struct Outcomes {
+    /// Return registers of coroutines which returned, or interruptions which killed coroutines.
+    finished: HashMap<u64, Result<Vec<Var>, VmError>, FakeHasher>,
+    /// Coroutines spawned as joinable.
+    joinable: HashSet<u64, FakeHasher>,
+    /// Coroutines whose outcomes are not kept.
+    detached: HashSet<u64, FakeHasher>,
+    /// Coroutines which are cancelled, but haven't observed the cancellation.
+    cancelled: HashSet<u64, FakeHasher>,
+}
+
+/// Highest priority of a corotine.
+const MAX_PRIORITY: u64 = 64;
+
+/// Start a thread with coroutines. This returns when all coroutines have finished.
+///
+/// Every round, each runnable corotine is resumed as many times as its priority, which is 1 by
+/// default. Corotines with higher priorities are resumed first.
 pub fn start_coro(ctx: Thread) {
     let mut idgen = CeIdGen::new();
     let mut table: HashMap<_, _, FakeHasher> = HashMap::default();
+    let mut reactor = Reactor::new();
+    let mut outcomes = Outcomes::default();
+    let mut priorities: HashMap<u64, u64, FakeHasher> = HashMap::default();
     idgen.next();
     table.insert(idgen.next(), ctx);
-    loop {
-        let mut cache = Vec::with_capacity(table.len());
-        table.keys().for_each(|x| cache.push(*x));
+    while !table.is_empty() {
+        priorities.retain(|x, _| table.contains_key(x));
+        let mut cache: Vec<(u64, u64)> = table
+            .keys()
+            .filter(|x| !reactor.is_waiting(**x))
+            .map(|x| (*x, priorities.get(x).copied().unwrap_or(1)))
+            .collect();
+        cache.sort_unstable_by_key(|x| std::cmp::Reverse(x.1));
+        let passes = cache.first().map_or(0, |x| x.1);
+        let cache: Vec<u64> = (0..passes)
+            .flat_map(|x| cache.iter().filter(move |y| y.1 > x).map(|y| y.0))
+            .collect();
         'world: for i in cache {
+            if reactor.is_waiting(i) {
+                continue;
+            }
+            if outcomes.cancelled.remove(&i) {
+                let x = VmError::new("raw::coro::cancelled");
+                let coro = match table.get_mut(&i) {
+                    Some(z) => z,
+                    None => continue,
+                };
+                // Without a handler, the cancellation unwinds the corotine.
+                let action = match inthandled(x.id()) {
+                    true => int(coro, &x),
+                    false => IntAction::Unwind,
+                };
+                let alive = match action {
+                    IntAction::Resume => !coro.is_finished(),
+                    IntAction::Retry => coro.retry(),
+                    IntAction::Unwind => false,
+                    IntAction::Abort => {
+                        eprintln!("{:?}", table);
+                        false
+                    }
+                };
+                if !alive {
+                    coro_finish(
+                        &mut table,
+                        i,
+                        &mut idgen,
+                        &mut reactor,
+                        &mut outcomes,
+                        Err(x),
+                    );
+                    continue;
+                }
+            }
+            let mut fault = None;
             'coro: while let Some(x) = table.get_mut(&i) {
-                match core(x) {
+                let result = match fault.take() {
+                    Some(z) => Err(z),
+                    None => reactor.run(i, || core(x)),
+                };
+                match result {
                     Ok(()) => {
-                        table.remove(&i);
-                        idgen.free(i);
+                        let returns = returns(x);
+                        coro_finish(
+                            &mut table,
+                            i,
+                            &mut idgen,
+                            &mut reactor,
+                            &mut outcomes,
+                            Ok(returns),
+                        );
                         break 'coro;
                     }
-                    Err(x) => match x.downcast::<String>() {
-                        Ok(y) => match &y[..] {
-                            "raw::coro::yield" => break 'coro,
-                            "raw::coro::getcid" => {
-                                table.get_mut(&i).unwrap().sset(100, Var::U64(i)).unwrap();
-                                break 'coro;
-                            }
-                            "raw::coro::exit" => {
-                                table.remove(&i);
-                                idgen.free(i);
-                                break 'world;
+                    Err(x) => match x.id() {
+                        "raw::coro::yield" => break 'coro,
+                        "raw::coro::wait_io" => {
+                            let coro = table.get_mut(&i).unwrap();
+                            if coro.retry() {
+                                reactor.register(i, x.payload());
                             }
-                            "raw::coro::kill" => {
-                                coro_kill(&mut table, i, &mut idgen);
-                                break 'world;
-                            }
-                            "raw::coro::spawn" => {
-                                coro_spawn(&mut table, i, &mut idgen);
-                                break 'coro;
+                            break 'coro;
+                        }
+                        "raw::coro::wait_chan" => {
+                            let coro = table.get_mut(&i).unwrap();
+                            if coro.retry() {
+                                reactor.register_chan(i, x.payload());
                             }
-                            "raw::coro::is_alive" => {
-                                coro_isalive(&mut table, i, &mut idgen);
+                            break 'coro;
+                        }
+                        "raw::coro::sleep" => {
+                            reactor.register_sleep(i, x.payload());
+                            break 'coro;
+                        }
+                        "raw::coro::getcid" => {
+                            table.get_mut(&i).unwrap().sset(100, Var::U64(i)).unwrap();
+                            break 'coro;
+                        }
+                        "raw::coro::exit" => {
+                            let returns = returns(table.get(&i).unwrap());
+                            coro_finish(
+                                &mut table,
+                                i,
+                                &mut idgen,
+                                &mut reactor,
+                                &mut outcomes,
+                                Ok(returns),
+                            );
+                            break 'world;
+                        }
+                        "raw::coro::kill" => {
+                            coro_kill(&mut table, i, &mut idgen, &mut reactor, &mut outcomes);
+                            break 'world;
+                        }
+                        "raw::coro::join" => {
+                            fault = coro_join(&mut table, i, &mut reactor, &mut outcomes);
+                            if fault.is_none() && reactor.is_waiting(i) {
                                 break 'coro;
                             }
-                            "raw::coro::dump" => {
-                                let dumped = format!("{{ Table {:?}, IdGen {:?} }}", table, idgen);
-                                table
-                                    .get_mut(&i)
-                                    .unwrap()
-                                    .sset(100, Var::UString(dumped.into()))
-                                    .unwrap();
-                                break 'coro;
+                        }
+                        "raw::coro::detach" => {
+                            coro_detach(&mut table, i, &mut outcomes);
+                            break 'coro;
+                        }
+                        "raw::coro::spawn" => {
+                            coro_spawn(&mut table, i, &mut idgen);
+                            break 'coro;
+                        }
+                        "raw::coro::spawn<joinable>" => {
+                            if let Some(z) = coro_spawn(&mut table, i, &mut idgen) {
+                                outcomes.joinable.insert(z);
                             }
-                            _ => {
-                                if int(&y[..]) {
+                            break 'coro;
+                        }
+                        "raw::coro::set_priority" => {
+                            coro_setpriority(&mut table, i, &mut priorities);
+                            break 'coro;
+                        }
+                        "raw::coro::get_priority" => {
+                            coro_getpriority(&mut table, i, &priorities);
+                            break 'coro;
+                        }
+                        "raw::coro::is_alive" => {
+                            coro_isalive(&mut table, i, &mut idgen);
+                            break 'coro;
+                        }
+                        "raw::coro::dump" => {
+                            let dumped = format!("{{ Table {:?}, IdGen {:?} }}", table, idgen);
+                            table
+                                .get_mut(&i)
+                                .unwrap()
+                                .sset(100, Var::UString(dumped.into()))
+                                .unwrap();
+                            break 'coro;
+                        }
+                        _ => {
+                            let coro = table.get_mut(&i).unwrap();
+                            let alive = match int(coro, &x) {
+                                IntAction::Resume => !coro.is_finished(),
+                                IntAction::Retry => coro.retry(),
+                                IntAction::Unwind => false,
+                                IntAction::Abort => {
                                     eprintln!("{:?}", table);
-                                    table.remove(&i);
-                                    idgen.free(i);
+                                    coro_finish(
+                                        &mut table,
+                                        i,
+                                        &mut idgen,
+                                        &mut reactor,
+                                        &mut outcomes,
+                                        Err(x),
+                                    );
                                     break 'world;
                                 }
-                            }
-                        },
-                        Err(x) => {
-                            if int(x.downcast::<&str>().unwrap()) {
-                                eprintln!("{:?}", table);
-                                table.remove(&i);
-                                idgen.free(i);
-                                break 'world;
+                            };
+                            if !alive {
+                                let outcome = match table.get(&i).unwrap().is_finished() {
+                                    true => Ok(returns(table.get(&i).unwrap())),
+                                    false => Err(x),
+                                };
+                                coro_finish(
+                                    &mut table,
+                                    i,
+                                    &mut idgen,
+                                    &mut reactor,
+                                    &mut outcomes,
+                                    outcome,
+                                );
+                                break 'coro;
                             }
                         }
                     },
                 };
             }
         }
+        reactor.poll(reactor.len() == table.len());
+    }
+}
+
+/// Get the return registers of a thread.
+#[inline]
+fn returns(ctx: &Thread) -> Vec<Var> {
+    (100..150)
+        .map(|x| ctx.sget(x).unwrap().to_owned())
+        .collect()
+}
+
+/// Remove a finished corotine, keep its outcome, and wake corotines joining it.
+#[inline]
+fn coro_finish(
+    table: &mut HashMap<u64, Thread, FakeHasher>,
+    i: u64,
+    idgen: &mut CeIdGen,
+    reactor: &mut Reactor,
+    outcomes: &mut Outcomes,
+    outcome: Result<Vec<Var>, VmError>,
+) {
+    if let Some(x) = table.remove(&i) {
+        sync::release(x.owner());
+    }
+    idgen.free(i);
+    let joined = reactor.finish(i);
+    let joinable = outcomes.joinable.remove(&i);
+    outcomes.cancelled.remove(&i);
+    if !outcomes.detached.remove(&i) && (joined || joinable) {
+        outcomes.finished.insert(i, outcome);
     }
 }
 
@@ -372,7 +639,9 @@ fn coro_isalive(table: &mut HashMap<u64, Thread, FakeHasher>, i: u64, idgen: &mu
     let id = match table.get(&i).unwrap().sget(100).unwrap().as_u64_strict() {
         Some(z) => z,
         None => {
-            table.remove(&i);
+            if let Some(x) = table.remove(&i) {
+                sync::release(x.owner());
+            }
             idgen.free(i);
             return;
         }
@@ -385,56 +654,149 @@ fn coro_isalive(table: &mut HashMap<u64, Thread, FakeHasher>, i: u64, idgen: &mu
         .unwrap();
 }
 
-/// Kill a corotine.
+/// Cancel a corotine. The corotine is interrupted with `raw::coro::cancelled` before it executes
+/// again, and a wait it's blocked in is cancelled. If there's no handler, or the handler unwinds or
+/// aborts, the corotine finishes with the interruption. If the handler resumes or retries, the
+/// corotine continues, so it can clean up and return by itself.
 #[inline]
-fn coro_kill(table: &mut HashMap<u64, Thread, FakeHasher>, i: u64, idgen: &mut CeIdGen) {
+fn coro_kill(
+    table: &mut HashMap<u64, Thread, FakeHasher>,
+    i: u64,
+    idgen: &mut CeIdGen,
+    reactor: &mut Reactor,
+    outcomes: &mut Outcomes,
+) {
     let id = match table.get(&i).unwrap().sget(100).unwrap().as_u64_strict() {
         Some(z) => z,
         None => {
-            table.remove(&i);
+            if let Some(x) = table.remove(&i) {
+                sync::release(x.owner());
+            }
             idgen.free(i);
             return;
         }
     };
     if table.get(&id).is_some() {
-        table.remove(&id);
-        idgen.free(id);
+        reactor.cancel(id);
+        outcomes.cancelled.insert(id);
     }
 }
 
-/// Start a corotine.
+/// Join a corotine. If the target has finished, its return registers are copied to the joining
+/// corotine, or the interruption that killed it is returned to be thrown again. Otherwise, the
+/// joining corotine waits until the target finishes. The outcome of a corotine which is not
+/// spawned as joinable is dropped if it finishes before being joined.
 #[inline]
-fn coro_spawn(table: &mut HashMap<u64, Thread, FakeHasher>, i: u64, idgen: &mut CeIdGen) {
-    let a = match table.get_mut(&i) {
+fn coro_join(
+    table: &mut HashMap<u64, Thread, FakeHasher>,
+    i: u64,
+    reactor: &mut Reactor,
+    outcomes: &mut Outcomes,
+) -> Option<VmError> {
+    let coro = table.get(&i).unwrap();
+    let id = match coro.sget(100).unwrap().as_u64_strict() {
         Some(z) => z,
-        None => return,
+        None => return Some(VmError::new("raw::fatal::not_an_integer")),
+    };
+    if reactor.take_deadlock() || id == i {
+        return Some(
+            VmError::new("raw::coro::deadlock")
+                .with_message("all coroutines are waiting for each other"),
+        );
+    }
+    match outcomes.finished.remove(&id) {
+        Some(Ok(x)) => {
+            let coro = table.get_mut(&i).unwrap();
+            for (c, v) in x.into_iter().enumerate() {
+                coro.sset(100 + c, v).unwrap();
+            }
+            None
+        }
+        Some(Err(x)) => Some(x),
+        None if table.contains_key(&id) && !outcomes.detached.contains(&id) => {
+            if table.get_mut(&i).unwrap().retry() {
+                reactor.register_join(i, id);
+            }
+            None
+        }
+        None => Some(
+            VmError::new("raw::coro::not_joinable")
+                .with_message("the coroutine doesn't exist, is detached or has been joined"),
+        ),
+    }
+}
+
+/// Set the priority of a corotine. The priority is clamped to `1..=MAX_PRIORITY`.
+#[inline]
+fn coro_setpriority(
+    table: &mut HashMap<u64, Thread, FakeHasher>,
+    i: u64,
+    priorities: &mut HashMap<u64, u64, FakeHasher>,
+) {
+    let coro = table.get(&i).unwrap();
+    let (id, priority) = match (
+        coro.sget(100).unwrap().as_u64_strict(),
+        coro.sget(101).unwrap().as_u64_strict(),
+    ) {
+        (Some(x), Some(y)) => (x, y.clamp(1, MAX_PRIORITY)),
+        _ => return,
     };
-    let fp = match a.sget(100).unwrap().as_sr() {
+    if table.contains_key(&id) {
+        priorities.insert(id, priority);
+    }
+}
+
+/// Get the priority of a corotine, or 0 if it doesn't exist.
+#[inline]
+fn coro_getpriority(
+    table: &mut HashMap<u64, Thread, FakeHasher>,
+    i: u64,
+    priorities: &HashMap<u64, u64, FakeHasher>,
+) {
+    let priority = match table.get(&i).unwrap().sget(100).unwrap().as_u64_strict() {
+        Some(x) if table.contains_key(&x) => priorities.get(&x).copied().unwrap_or(1),
+        _ => 0,
+    };
+    table
+        .get_mut(&i)
+        .unwrap()
+        .sset(100, Var::U64(priority))
+        .unwrap();
+}
+
+/// Detach a corotine, so its outcome is not kept.
+#[inline]
+fn coro_detach(table: &mut HashMap<u64, Thread, FakeHasher>, i: u64, outcomes: &mut Outcomes) {
+    let id = match table.get(&i).unwrap().sget(100).unwrap().as_u64_strict() {
         Some(z) => z,
         None => return,
     };
-    let fp = match fp.borrow() {
-        Ok(z) => z,
-        Err(_) => return,
-    };
+    outcomes.joinable.remove(&id);
+    if outcomes.finished.remove(&id).is_none() && table.contains_key(&id) {
+        outcomes.detached.insert(id);
+    }
+}
+
+/// Start a corotine. Returns its ID.
+#[inline]
+fn coro_spawn(
+    table: &mut HashMap<u64, Thread, FakeHasher>,
+    i: u64,
+    idgen: &mut CeIdGen,
+) -> Option<u64> {
+    let a = table.get_mut(&i)?;
+    let fp = a.sget(100).unwrap().as_sr()?;
+    let fp = fp.borrow().ok()?;
     let args = match a.sget(101).unwrap() {
-        Var::Vector(x) => match x.borrow() {
-            Ok(z) => z,
-            Err(_) => return,
-        },
-        _ => return,
+        Var::Vector(x) => x.borrow().ok()?,
+        _ => return None,
     };
-    let mut new_coro = Thread::new(
-        match match getfp(&fp) {
-            Some(x) => x,
-            None => return,
-        } {
-            FuncPtr::Virtual(x) => x,
-            _ => return,
-        },
-    );
+    let mut new_coro = Thread::new(match getfp(&fp)? {
+        FuncPtr::Virtual(x) => x,
+        _ => return None,
+    });
     if args.len() > 50 {
-        return;
+        return None;
     }
     for i in 0..args.len() {
         new_coro
@@ -445,6 +807,7 @@ fn coro_spawn(table: &mut HashMap<u64, Thread, FakeHasher>, i: u64, idgen: &mut
     let id = idgen.next();
     a.sset(100, Var::U64(id)).unwrap();
     table.insert(id, new_coro);
+    Some(id)
 }
 
 macro_rules! impl_itc {
@@ -458,12 +821,12 @@ macro_rules! impl_itc {
             Var::U32(x) => Ok(x as $b),
             Var::I64(x) => Ok(x as $b),
             Var::U64(x) => Ok(x as $b),
-            _ => Err(anyhow!("raw::fatal::transmute_np")),
+            _ => Err(VmError::new("raw::fatal::transmute_np")),
         }
     };
 }
 /// Integer type convertion.
-fn itc(a: &Var, b: usize) -> Result<Var, anyhow::Error> {
+fn itc(a: &Var, b: usize) -> Result<Var, VmError> {
     let a = a.to_owned();
     match b {
         8 => Ok(Var::I8(impl_itc!(a, i8)?)),
@@ -474,7 +837,7 @@ fn itc(a: &Var, b: usize) -> Result<Var, anyhow::Error> {
         33 => Ok(Var::U32(impl_itc!(a, u32)?)),
         64 => Ok(Var::I64(impl_itc!(a, i64)?)),
         65 => Ok(Var::U64(impl_itc!(a, u64)?)),
-        _ => Err(anyhow!("raw::fatal::transmute_te")),
+        _ => Err(VmError::new("raw::fatal::transmute_te")),
     }
 }
 
@@ -0,0 +1,352 @@
+//!
+//! M:N green threads.
+//!
+//! Tasks are lightweight VM threads which are executed by a work-stealing pool of `num_cpus` OS
+//! threads. A task runs on a worker until it yields, its quantum runs out, or it has to wait, then
+//! the worker picks another task, and the task may be resumed by any worker later.
+//!
+//! Tasks are in coroutine mode, so I/O natives throw `raw::coro::wait_io` instead of blocking.
+//! The waiting task is parked in a poller, which is an OS thread waiting with `epoll`, until its
+//! file descriptor is ready. A task joining another task is parked in the record of the target, and
+//! is woken when the target finishes. Channels between coroutines can be used by tasks too, but a
+//! task waiting for a channel just yields, and deadlocks are not detected. A sleeping task is woken
+//! by the timer thread.
+//!
+//! An aborted task finishes with the interruption as its outcome, and the process only exits if
+//! aborts are configured to do so.
+//!
+
+use super::{core, reactor, returns, terminate};
+use crate::{
+    context::{int, Thread},
+    error::VmError,
+    isa::IntAction,
+    libraw::{
+        iohmgr::{FakeHasher, IdGen},
+        thread::sync,
+        time::timer,
+    },
+    vmem::Var,
+};
+use dashmap::DashMap;
+use libc::c_int;
+use once_cell::sync::Lazy;
+use parking_lot::{const_mutex, Condvar, Mutex};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::{cell::Cell, collections::HashMap, sync::Arc, time::Duration};
+
+/// A task which can be executed.
+struct Green {
+    id: u64,
+    thread: Box<Thread>,
+}
+
+/// State of a task.
+#[derive(Default)]
+struct State {
+    /// The outcome, which is `Some` if the task has finished and hasn't been joined.
+    outcome: Option<Result<Vec<Var>, VmError>>,
+    /// Tasks joining this task.
+    joiners: Vec<Green>,
+    /// If the outcome is not kept.
+    detached: bool,
+}
+
+/// Record of a task.
+#[derive(Default)]
+struct Record {
+    state: Mutex<State>,
+    finished: Condvar,
+}
+
+/// ID Generator for tasks.
+static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
+/// Records of tasks which haven't been joined or detached.
+static TASKS: Lazy<DashMap<u64, Arc<Record>, FakeHasher>> = Lazy::new(DashMap::default);
+/// The worker pool.
+static POOL: Lazy<ThreadPool> = Lazy::new(|| {
+    ThreadPoolBuilder::new()
+        .num_threads(num_cpus::get())
+        .thread_name(|x| format!("task-{}", x))
+        .build()
+        .expect("failed to create the task pool")
+});
+/// The poller of tasks waiting for I/O.
+static POLLER: Lazy<Poller> = Lazy::new(Poller::new);
+
+std::thread_local! {
+    /// ID of the task being executed on this OS thread, or 0 if it's not a task.
+    static CURRENT: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Initialize the scheduler.
+#[inline(always)]
+pub fn init() {
+    IDGEN.lock().next();
+}
+
+/// Get the ID of the task being executed, or 0 if this is not a task.
+#[inline]
+pub fn current() -> u64 {
+    CURRENT.with(Cell::get)
+}
+
+/// Spawn a task. Returns the task ID.
+pub fn spawn(thread: Thread) -> u64 {
+    let id = IDGEN.lock().next();
+    TASKS.insert(id, Arc::default());
+    schedule(Green {
+        id,
+        thread: Box::new(thread),
+    });
+    id
+}
+
+/// Put a task into the pool.
+#[inline]
+fn schedule(task: Green) {
+    POOL.spawn_fifo(move || run(task));
+}
+
+/// Run a task until it yields, waits or finishes.
+fn run(mut task: Green) {
+    CURRENT.with(|x| x.set(task.id));
+    let result = reactor::with_async(true, || loop {
+        let x = match core(&mut task.thread) {
+            Ok(()) => return Some(Ok(())),
+            Err(x) => x,
+        };
+        match x.id() {
+            "raw::coro::yield" | "raw::task::yield" | "raw::coro::wait_chan" => {
+                if x.id() == "raw::coro::wait_chan" {
+                    task.thread.retry();
+                }
+                return None;
+            }
+            "raw::coro::sleep" => return Some(Err(x)),
+            "raw::coro::wait_io" | "raw::task::wait_join" => {
+                task.thread.retry();
+                return Some(Err(x));
+            }
+            _ => (),
+        }
+        match int(&task.thread, &x) {
+            IntAction::Resume if task.thread.is_finished() => return Some(Ok(())),
+            IntAction::Resume => (),
+            IntAction::Retry if task.thread.retry() => (),
+            IntAction::Abort => {
+                terminate(&task.thread);
+                return Some(Err(x));
+            }
+            _ => return Some(Err(x)),
+        }
+    });
+    CURRENT.with(|x| x.set(0));
+    match result {
+        None => schedule(task),
+        Some(Ok(())) => {
+            let outcome = Ok(returns(&task.thread));
+            finish(task, outcome);
+        }
+        Some(Err(x)) => match x.id() {
+            "raw::coro::wait_io" => POLLER.park(task, x.payload()),
+            "raw::task::wait_join" => park_join(task, x.payload()),
+            "raw::coro::sleep" => {
+                let ms = x.payload().and_then(Var::as_u64).unwrap_or(0);
+                timer::wake_after(Duration::from_millis(ms), move || schedule(task));
+            }
+            _ => finish(task, Err(x)),
+        },
+    }
+}
+
+/// Release locks held by a finished task, keep its outcome, and wake tasks joining it.
+fn finish(task: Green, outcome: Result<Vec<Var>, VmError>) {
+    sync::release(task.thread.owner());
+    let id = task.id;
+    let record = match TASKS.get(&id) {
+        Some(x) => x.clone(),
+        None => return,
+    };
+    let mut state = record.state.lock();
+    state.joiners.drain(..).for_each(schedule);
+    if state.detached {
+        drop(state);
+        TASKS.remove(&id);
+        return;
+    }
+    state.outcome = Some(outcome);
+    record.finished.notify_all();
+}
+
+/// Park a task until the task in the payload of `raw::task::wait_join` finishes. If the target has
+/// finished, the task is scheduled again immediately.
+fn park_join(task: Green, payload: Option<&Var>) {
+    let record = match payload.and_then(Var::as_u64).and_then(|x| TASKS.get(&x)) {
+        Some(x) => x.clone(),
+        None => return schedule(task),
+    };
+    let mut state = record.state.lock();
+    if state.outcome.is_some() {
+        drop(state);
+        schedule(task);
+    } else {
+        state.joiners.push(task);
+    }
+}
+
+/// Join a task. If the task has finished, its return registers or the interruption that killed it
+/// are returned, and the task is forgotten. Otherwise, in a task, `raw::task::wait_join` is thrown
+/// to make the scheduler park the current task; outside tasks, this blocks until the task finishes.
+pub fn join(id: u64) -> Result<Result<Vec<Var>, VmError>, VmError> {
+    if id == current() {
+        return Err(VmError::new("raw::task::deadlock").with_message("a task can't join itself"));
+    }
+    let record = match TASKS.get(&id) {
+        Some(x) => x.clone(),
+        None => return Err(not_joinable()),
+    };
+    let mut state = record.state.lock();
+    if state.outcome.is_none() {
+        if state.detached {
+            return Err(not_joinable());
+        }
+        if current() != 0 {
+            return Err(VmError::new("raw::task::wait_join").with_payload(Var::U64(id)));
+        }
+        while state.outcome.is_none() {
+            record.finished.wait(&mut state);
+        }
+    }
+    let outcome = state.outcome.take().ok_or_else(not_joinable)?;
+    drop(state);
+    TASKS.remove(&id);
+    Ok(outcome)
+}
+
+/// Detach a task, so its outcome is not kept.
+pub fn detach(id: u64) {
+    let record = match TASKS.get(&id) {
+        Some(x) => x.clone(),
+        None => return,
+    };
+    let mut state = record.state.lock();
+    if state.outcome.is_some() {
+        drop(state);
+        TASKS.remove(&id);
+    } else {
+        state.detached = true;
+    }
+}
+
+/// Judge if a task is alive.
+#[inline]
+pub fn is_alive(id: u64) -> bool {
+    match TASKS.get(&id) {
+        Some(x) => x.state.lock().outcome.is_none(),
+        None => false,
+    }
+}
+
+/// Create the error of joining a task which can't be joined.
+#[inline]
+fn not_joinable() -> VmError {
+    VmError::new("raw::task::not_joinable")
+        .with_message("the task doesn't exist, is detached or has been joined")
+}
+
+/// The poller of tasks waiting for I/O.
+struct Poller {
+    /// The `epoll` instance, or -1 if it's unavailable.
+    epfd: c_int,
+    /// Parked tasks, and duplicates of the file descriptors they are waiting for.
+    parked: Mutex<HashMap<u64, (c_int, Green), FakeHasher>>,
+}
+impl Poller {
+    /// Create the poller, and start its OS thread.
+    fn new() -> Self {
+        #[cfg(target_os = "linux")]
+        let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        #[cfg(not(target_os = "linux"))]
+        let epfd = -1;
+        if epfd >= 0 {
+            std::thread::Builder::new()
+                .name("task-poller".to_owned())
+                .spawn(move || Self::poll(epfd))
+                .expect("failed to start the task poller");
+        }
+        Self {
+            epfd,
+            parked: Mutex::new(HashMap::default()),
+        }
+    }
+    /// Park a task with the payload of `raw::coro::wait_io`. If the file descriptor can't be
+    /// waited, the task is scheduled again immediately.
+    #[cfg(target_os = "linux")]
+    fn park(&self, task: Green, payload: Option<&Var>) {
+        let (fd, interest) = match payload {
+            Some(Var::Vector(x)) => match (
+                x.get(0).ok().and_then(|y| y.as_u64()),
+                x.get(1).ok().and_then(|y| y.as_u64()),
+            ) {
+                (Some(y), Some(z)) => (y as c_int, z),
+                _ => return schedule(task),
+            },
+            _ => return schedule(task),
+        };
+        if self.epfd < 0 {
+            return schedule(task);
+        }
+        let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+        if dup < 0 {
+            return schedule(task);
+        }
+        let mut events = libc::EPOLLONESHOT as u32;
+        if interest & reactor::READABLE != 0 {
+            events |= libc::EPOLLIN as u32;
+        }
+        if interest & reactor::WRITABLE != 0 {
+            events |= libc::EPOLLOUT as u32;
+        }
+        let id = task.id;
+        // The task is parked before it's registered, because the poller may wake it at once.
+        let mut parked = self.parked.lock();
+        parked.insert(id, (dup, task));
+        let mut event = libc::epoll_event { events, u64: id };
+        if unsafe { libc::epoll_ctl(self.epfd, libc::EPOLL_CTL_ADD, dup, &mut event) } < 0 {
+            let (_, task) = parked.remove(&id).unwrap();
+            drop(parked);
+            unsafe { libc::close(dup) };
+            schedule(task);
+        }
+    }
+    /// Park a task with the payload of `raw::coro::wait_io`, which is never thrown on this
+    /// platform.
+    #[cfg(not(target_os = "linux"))]
+    fn park(&self, task: Green, _: Option<&Var>) {
+        schedule(task);
+    }
+    /// Wake tasks whose file descriptors are ready, forever.
+    #[cfg(target_os = "linux")]
+    fn poll(epfd: c_int) {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 64];
+        loop {
+            let n =
+                unsafe { libc::epoll_wait(epfd, events.as_mut_ptr(), events.len() as c_int, -1) };
+            for i in events.iter().take(n.max(0) as usize) {
+                let id = i.u64;
+                let woken = POLLER.parked.lock().remove(&id);
+                if let Some((dup, task)) = woken {
+                    unsafe {
+                        libc::epoll_ctl(epfd, libc::EPOLL_CTL_DEL, dup, std::ptr::null_mut());
+                        libc::close(dup);
+                    }
+                    schedule(task);
+                }
+            }
+        }
+    }
+    /// Wake tasks whose file descriptors are ready, which is unavailable on this platform.
+    #[cfg(not(target_os = "linux"))]
+    fn poll(_: c_int) {}
+}
@@ -0,0 +1,411 @@
+//!
+//! I/O reactor of the coroutine scheduler.
+//!
+//! In coroutine mode, an I/O native checks if its file descriptor is ready before it does the
+//! operation. If it's not ready, the native throws `raw::coro::wait_io` whose payload is a vector
+//! of the file descriptor and the interest, which is `READABLE`, `WRITABLE` or both of them. The
+//! scheduler registers the file descriptor to `epoll`, runs other coroutines, and makes the native
+//! call execute again once the file descriptor is ready. When every coroutine is waiting, the OS
+//! thread is parked in `epoll_wait`.
+//!
+//! Only readiness is checked, and the operation itself is still blocking, so a read returns what
+//! is available, and a write may block if the content is larger than the free buffer. The reactor
+//! is only available on Linux. On other platforms, I/O natives always block.
+//!
+//! Channels between coroutines work in the same way with `raw::coro::wait_chan`, whose payload is
+//! a vector of the channel ID and the interest. Readiness of channels is checked every round of the
+//! scheduler. If every coroutine is waiting for a channel, nothing can make them ready, so the
+//! waiting coroutines are woken, and their channel calls throw `raw::coro::deadlock`.
+//!
+//! A coroutine joining another one waits until the scheduler reports that the target has finished.
+//! Joins take part in deadlock detection like channels.
+//!
+//! A sleeping coroutine throws `raw::coro::sleep` whose payload is the duration in milliseconds.
+//! The coroutine is suspended until the deadline, while other coroutines keep running. Sleeping
+//! coroutines are never considered deadlocked, and the OS thread is parked until the earliest
+//! deadline if nothing else can be woken.
+//!
+//! If a quantum is set, a coroutine which has executed that many instructions since it was resumed
+//! yields automatically, so a coroutine that never yields can't monopolize the scheduler.
+//!
+
+use crate::{
+    error::VmError,
+    libraw::{corochan, iohmgr::FakeHasher},
+    vmem::Var,
+};
+use libc::c_int;
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Interest of waiting until a file descriptor is readable.
+pub const READABLE: u64 = 1;
+/// Interest of waiting until a file descriptor is writable.
+pub const WRITABLE: u64 = 2;
+
+/// Count of instructions a coroutine executes before it yields automatically. 0 means disabled.
+static QUANTUM: AtomicU64 = AtomicU64::new(0);
+
+std::thread_local! {
+    /// If the thread being executed on this OS thread is a coroutine.
+    static ASYNC: Cell<bool> = const { Cell::new(false) };
+    /// If the coroutine being executed is woken from a deadlock.
+    static DEADLOCK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f` with coroutine mode turned on or off, and restore the mode after `f` returns.
+#[inline]
+pub fn with_async<T>(on: bool, f: impl FnOnce() -> T) -> T {
+    let old = ASYNC.with(|x| x.replace(on));
+    let result = f();
+    ASYNC.with(|x| x.set(old));
+    result
+}
+
+/// Set the quantum. 0 means disabled.
+#[inline]
+pub fn set_quantum(n: u64) {
+    QUANTUM.store(n, Ordering::Relaxed);
+}
+
+/// Get the quantum. 0 means disabled.
+#[inline]
+pub fn quantum() -> u64 {
+    QUANTUM.load(Ordering::Relaxed)
+}
+
+/// Get the count of instructions the thread being executed may execute before it yields, which is
+/// `u64::MAX` outside coroutine mode or if the quantum is disabled.
+#[inline]
+pub fn budget() -> u64 {
+    match quantum() {
+        0 => u64::MAX,
+        x if ASYNC.with(Cell::get) => x,
+        _ => u64::MAX,
+    }
+}
+
+/// Throw `raw::coro::wait_io` if this is in coroutine mode, and `fd` is not ready for `interest`.
+#[cfg(target_os = "linux")]
+#[inline]
+pub fn wait(fd: c_int, interest: u64) -> Result<(), VmError> {
+    if !ASYNC.with(Cell::get) {
+        return Ok(());
+    }
+    let mut events = 0;
+    if interest & READABLE != 0 {
+        events |= libc::POLLIN;
+    }
+    if interest & WRITABLE != 0 {
+        events |= libc::POLLOUT;
+    }
+    let mut pollfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    // Errors are not handled here, because the operation will report them.
+    if unsafe { libc::poll(&mut pollfd, 1, 0) } != 0 {
+        return Ok(());
+    }
+    Err(VmError::new("raw::coro::wait_io").with_payload(Var::Vector(
+        vec![Var::U64(fd as u64), Var::U64(interest)].into(),
+    )))
+}
+
+/// Throw `raw::coro::wait_io` if `fd` is not ready, which is unavailable on this platform, so the
+/// operation blocks.
+#[cfg(not(target_os = "linux"))]
+#[inline]
+pub fn wait(_: c_int, _: u64) -> Result<(), VmError> {
+    Ok(())
+}
+
+/// Throw `raw::coro::wait_chan` if this is in coroutine mode, or `raw::coro::deadlock` if the
+/// coroutine is woken from a deadlock. Outside coroutine mode this does nothing, and the caller
+/// shouldn't block.
+#[inline]
+pub fn wait_chan(id: u64, interest: u64) -> Result<(), VmError> {
+    if !ASYNC.with(Cell::get) {
+        return Ok(());
+    }
+    if DEADLOCK.with(|x| x.replace(false)) {
+        return Err(VmError::new("raw::coro::deadlock")
+            .with_message("all coroutines are waiting for channels"));
+    }
+    Err(VmError::new("raw::coro::wait_chan")
+        .with_payload(Var::Vector(vec![Var::U64(id), Var::U64(interest)].into())))
+}
+
+/// Throw `raw::coro::sleep` if this is in coroutine mode. Outside coroutine mode this does nothing,
+/// and the caller should block.
+#[inline]
+pub fn sleep(ms: u64) -> Result<(), VmError> {
+    if !ASYNC.with(Cell::get) {
+        return Ok(());
+    }
+    Err(VmError::new("raw::coro::sleep").with_payload(Var::U64(ms)))
+}
+
+/// Decode the payload of `raw::coro::wait_io` or `raw::coro::wait_chan`.
+#[inline]
+fn decode(payload: Option<&Var>) -> Option<(u64, u64)> {
+    match payload {
+        Some(Var::Vector(x)) => Some((x.get(0).ok()?.as_u64()?, x.get(1).ok()?.as_u64()?)),
+        _ => None,
+    }
+}
+
+/// The reactor of a coroutine scheduler.
+pub struct Reactor {
+    /// The `epoll` instance, or -1 if it's unavailable.
+    epfd: c_int,
+    /// Waiting coroutines, and duplicates of the file descriptors they are waiting for. Each
+    /// registration uses a duplicate, so coroutines waiting for a same file descriptor don't
+    /// conflict, and the registration won't outlive the wait.
+    waiting: HashMap<u64, c_int, FakeHasher>,
+    /// Coroutines waiting for channels, and the channels and interests they are waiting for.
+    channels: HashMap<u64, (u64, u64), FakeHasher>,
+    /// Coroutines joining other coroutines, and the coroutines they are joining.
+    joins: HashMap<u64, u64, FakeHasher>,
+    /// Sleeping coroutines, and their deadlines.
+    sleeping: HashMap<u64, Instant, FakeHasher>,
+    /// Coroutines woken from a deadlock.
+    deadlocked: HashSet<u64, FakeHasher>,
+    /// If the last coroutine executed was woken from a deadlock, but didn't call a channel native.
+    unobserved: bool,
+}
+impl Reactor {
+    /// Create a reactor.
+    #[inline]
+    pub fn new() -> Self {
+        #[cfg(target_os = "linux")]
+        let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        #[cfg(not(target_os = "linux"))]
+        let epfd = -1;
+        Self {
+            epfd,
+            waiting: HashMap::default(),
+            channels: HashMap::default(),
+            joins: HashMap::default(),
+            sleeping: HashMap::default(),
+            deadlocked: HashSet::default(),
+            unobserved: false,
+        }
+    }
+    /// Judge if a coroutine is waiting.
+    #[inline]
+    pub fn is_waiting(&self, id: u64) -> bool {
+        self.waiting.contains_key(&id)
+            || self.channels.contains_key(&id)
+            || self.joins.contains_key(&id)
+            || self.sleeping.contains_key(&id)
+    }
+    /// Count of waiting coroutines.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.waiting.len() + self.channels.len() + self.joins.len() + self.sleeping.len()
+    }
+    /// Judge if no coroutine is waiting.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+            && self.channels.is_empty()
+            && self.joins.is_empty()
+            && self.sleeping.is_empty()
+    }
+    /// Run `f`, which executes coroutine `id`, in coroutine mode.
+    #[inline]
+    pub fn run<T>(&mut self, id: u64, f: impl FnOnce() -> T) -> T {
+        let deadlock = self.deadlocked.remove(&id);
+        DEADLOCK.with(|x| x.set(deadlock));
+        let result = with_async(true, f);
+        self.unobserved = DEADLOCK.with(|x| x.replace(false));
+        result
+    }
+    /// Judge if the last coroutine executed was woken from a deadlock, but didn't observe it, such
+    /// as when it's joining. The deadlock is observed after this is called.
+    #[inline]
+    pub fn take_deadlock(&mut self) -> bool {
+        std::mem::replace(&mut self.unobserved, false)
+    }
+    /// Make a coroutine wait with the payload of `raw::coro::wait_io`. Returns `false` if the
+    /// payload is invalid or the file descriptor can't be waited, then the coroutine should stay
+    /// runnable.
+    pub fn register(&mut self, id: u64, payload: Option<&Var>) -> bool {
+        let (fd, interest) = match decode(payload) {
+            Some(x) => x,
+            None => return false,
+        };
+        if self.epfd < 0 {
+            return false;
+        }
+        self.cancel(id);
+        self.add(id, fd as c_int, interest)
+    }
+    /// Make a coroutine wait with the payload of `raw::coro::wait_chan`. Returns `false` if the
+    /// payload is invalid, then the coroutine should stay runnable.
+    pub fn register_chan(&mut self, id: u64, payload: Option<&Var>) -> bool {
+        match decode(payload) {
+            Some(x) => {
+                self.cancel(id);
+                self.channels.insert(id, x);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Make a coroutine wait until coroutine `target` has finished.
+    #[inline]
+    pub fn register_join(&mut self, id: u64, target: u64) {
+        self.cancel(id);
+        self.joins.insert(id, target);
+    }
+    /// Make a coroutine sleep with the payload of `raw::coro::sleep`. Returns `false` if the payload
+    /// is invalid, then the coroutine should stay runnable.
+    pub fn register_sleep(&mut self, id: u64, payload: Option<&Var>) -> bool {
+        match payload.and_then(Var::as_u64) {
+            Some(x) => {
+                self.cancel(id);
+                self.sleeping
+                    .insert(id, Instant::now() + Duration::from_millis(x));
+                true
+            }
+            None => false,
+        }
+    }
+    /// Wake coroutines joining coroutine `target`, which has finished. Returns `true` if any
+    /// coroutine is woken.
+    #[inline]
+    pub fn finish(&mut self, target: u64) -> bool {
+        self.cancel(target);
+        let joins = self.joins.len();
+        self.joins.retain(|_, x| *x != target);
+        self.joins.len() != joins
+    }
+    /// Register a duplicate of `fd` to `epoll`.
+    #[cfg(target_os = "linux")]
+    fn add(&mut self, id: u64, fd: c_int, interest: u64) -> bool {
+        let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+        if dup < 0 {
+            return false;
+        }
+        let mut events = libc::EPOLLONESHOT as u32;
+        if interest & READABLE != 0 {
+            events |= libc::EPOLLIN as u32;
+        }
+        if interest & WRITABLE != 0 {
+            events |= libc::EPOLLOUT as u32;
+        }
+        let mut event = libc::epoll_event { events, u64: id };
+        if unsafe { libc::epoll_ctl(self.epfd, libc::EPOLL_CTL_ADD, dup, &mut event) } < 0 {
+            unsafe { libc::close(dup) };
+            return false;
+        }
+        self.waiting.insert(id, dup);
+        true
+    }
+    /// Register a duplicate of `fd` to `epoll`, which is unavailable on this platform.
+    #[cfg(not(target_os = "linux"))]
+    fn add(&mut self, _: u64, _: c_int, _: u64) -> bool {
+        false
+    }
+    /// Stop a coroutine from waiting.
+    #[inline]
+    pub fn cancel(&mut self, id: u64) {
+        self.channels.remove(&id);
+        self.joins.remove(&id);
+        self.sleeping.remove(&id);
+        self.deadlocked.remove(&id);
+        if let Some(x) = self.waiting.remove(&id) {
+            #[cfg(target_os = "linux")]
+            unsafe {
+                libc::epoll_ctl(self.epfd, libc::EPOLL_CTL_DEL, x, std::ptr::null_mut());
+            }
+            unsafe { libc::close(x) };
+        }
+    }
+    /// Wake coroutines whose file descriptors, channels or deadlines are ready. If `block` is
+    /// `true`, this parks the OS thread until at least one coroutine is woken, or wakes every
+    /// coroutine waiting for a channel or a join from a deadlock if no coroutine is waiting for I/O
+    /// or sleeping.
+    pub fn poll(&mut self, block: bool) {
+        let now = Instant::now();
+        let ready: Vec<u64> = self
+            .channels
+            .iter()
+            .filter(|(_, (x, y))| corochan::ready(*x, *y))
+            .map(|(x, _)| *x)
+            .chain(
+                self.sleeping
+                    .iter()
+                    .filter(|(_, x)| **x <= now)
+                    .map(|(x, _)| *x),
+            )
+            .collect();
+        let block = block && ready.is_empty();
+        ready.into_iter().for_each(|x| self.cancel(x));
+        let sleep = self
+            .sleeping
+            .values()
+            .min()
+            .map(|x| x.saturating_duration_since(now));
+        if self.waiting.is_empty() {
+            if block {
+                match sleep {
+                    Some(x) => std::thread::sleep(x),
+                    None => {
+                        self.deadlocked
+                            .extend(self.channels.drain().map(|(x, _)| x));
+                        self.deadlocked.extend(self.joins.drain().map(|(x, _)| x));
+                    }
+                }
+            }
+            return;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let mut events = [libc::epoll_event { events: 0, u64: 0 }; 64];
+            let timeout = match (block, sleep) {
+                (false, _) => 0,
+                (true, None) => -1,
+                // Rounded up, so the coroutine is not woken before its deadline.
+                (true, Some(x)) => {
+                    x.as_nanos().div_ceil(1_000_000).min(c_int::MAX as u128) as c_int
+                }
+            };
+            let n = unsafe {
+                libc::epoll_wait(
+                    self.epfd,
+                    events.as_mut_ptr(),
+                    events.len() as c_int,
+                    timeout,
+                )
+            };
+            for i in events.iter().take(n.max(0) as usize) {
+                self.cancel(i.u64);
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = block;
+    }
+}
+impl Default for Reactor {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for Reactor {
+    fn drop(&mut self) {
+        let ids: Vec<u64> = self.waiting.keys().copied().collect();
+        ids.into_iter().for_each(|x| self.cancel(x));
+        if self.epfd >= 0 {
+            unsafe { libc::close(self.epfd) };
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::vmem::Var;
+use crate::{error::VmError, vmem::Var};
 use std::{
     fmt::{self, Debug, Formatter},
     sync::Arc,
@@ -93,8 +93,9 @@ pub enum Instruction {
     Call(Box<str>),
     /// Call a function through a `function pointer`(UString).
     CallPtr(usize),
-    /// Interrupt.
-    Int(Box<str>),
+    /// Interrupt. A is const, and B is the optional address of a payload which is passed to the
+    /// interruption handler.
+    Int(Box<str>, Option<usize>),
     /// Make this function return.
     Ret,
     /// No-op.
@@ -104,7 +105,7 @@ pub enum Instruction {
 /// The virtual function pointer.
 pub type VirtFuncPtr = Arc<[Instruction]>;
 /// The native function pointer with VM-friendly ABI.
-pub type NativeFuncPtr = fn(&mut [Var]) -> Result<(), anyhow::Error>;
+pub type NativeFuncPtr = fn(&mut [Var]) -> Result<(), VmError>;
 
 /// A function pointer both virtual and native.
 #[derive(Clone)]
@@ -131,6 +132,34 @@ pub enum InterruptHandler {
     Ignore,
     /// Abort the program. If the argument is `Some`, print a message.
     Abort(Option<String>),
-    /// Catched by VM function pointer.
+    /// Catched by VM function pointer. The handler is called with the interruption name at 100,
+    /// the message at 101, the faulting function name at 102, the faulting instruction index at
+    /// 103 and the payload at 104. Strings and the payload are null if they are not available.
+    /// The handler returns an `IntAction` code at 100.
     Handler(VirtFuncPtr),
 }
+
+/// The decision of an interruption handler about how the interrupted thread continues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntAction {
+    /// Resume after the faulting instruction. This is code 0.
+    Resume,
+    /// Execute the faulting instruction again. This is code 1.
+    Retry,
+    /// Unwind the thread, making it finish immediately. This is code 2.
+    Unwind,
+    /// Abort the program. This is code 3.
+    Abort,
+}
+impl IntAction {
+    /// Parse an action from the code returned by a handler. Unknown codes resume the thread.
+    #[inline]
+    pub fn from_code(c: Option<u8>) -> Self {
+        match c {
+            Some(1) => Self::Retry,
+            Some(2) => Self::Unwind,
+            Some(3) => Self::Abort,
+            _ => Self::Resume,
+        }
+    }
+}
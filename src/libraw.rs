@@ -5,23 +5,32 @@
 //! and it doesn't contain any features that don't support one of Windows or UNIX.
 //!
 
+pub mod atomic;
 pub mod bytes;
 pub mod cio;
+pub mod corochan;
 pub mod deque;
 pub mod dl;
 pub mod env;
 pub mod floatpoint;
 pub mod fs;
+pub mod gc;
+pub mod generator;
 pub mod hashmap;
 pub mod interruptions;
 pub mod intvec;
 pub mod iohmgr;
+pub mod mem;
+pub mod net;
+pub mod parallel;
 pub mod proc;
 pub mod rng;
 pub mod string;
+pub mod task;
 pub mod thread;
 pub mod time;
 pub mod vector;
+pub mod weak;
 
 #[cfg(feature = "cffi")]
 pub mod cffi;
@@ -52,6 +61,15 @@ fn init_functions() {
     time::init();
     interruptions::init();
     intvec::init();
+    gc::init();
+    weak::init();
+    mem::init();
+    net::init();
+    corochan::init();
+    generator::init();
+    task::init();
+    atomic::init();
+    parallel::init();
 
     #[cfg(feature = "cffi")]
     cffi::init();
@@ -0,0 +1,135 @@
+//!
+//! Atomic operations on statics of `libraw`.
+//!
+//! Every operation locks the static while it's running, so it never races with other atomic
+//! operations or `SetStatic` on the same static. Atomic statics must be integers. A static that
+//! is not set is treated as 0 of the type of the operand, and operations wrap around on overflow
+//! in the type of the static.
+//!
+
+use crate::{
+    context::{putnfp, updstatic},
+    error::VmError,
+    vmem::Var,
+};
+
+/// Cast integer `v` to the type of integer `like`.
+#[inline]
+fn cast(v: &Var, like: &Var) -> Result<Var, VmError> {
+    let x = v
+        .as_u64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    Ok(match like {
+        Var::I8(_) => Var::I8(x as i8),
+        Var::U8(_) => Var::U8(x as u8),
+        Var::I16(_) => Var::I16(x as i16),
+        Var::U16(_) => Var::U16(x as u16),
+        Var::I32(_) => Var::I32(x as i32),
+        Var::U32(_) => Var::U32(x as u32),
+        Var::I64(_) => Var::I64(x as i64),
+        Var::U64(_) => Var::U64(x),
+        Var::Usize(_) => Var::Usize(x as usize),
+        _ => return Err(VmError::new("raw::fatal::not_an_integer")),
+    })
+}
+
+/// Get the static name argument.
+#[inline]
+fn name(a: &[Var]) -> Result<Box<str>, VmError> {
+    let name = unsafe { a.get_unchecked(0) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let name = name.borrow()?;
+    Ok(Box::from(&name[..]))
+}
+
+/// Get the value of a static, or 0 of the type of `like` if it's not set.
+#[inline]
+fn current(val: &Option<Var>, like: &Var) -> Result<Var, VmError> {
+    match val {
+        Some(x) => cast(x, x),
+        None => cast(&Var::U64(0), like),
+    }
+}
+
+/// Update a static with `op`, which gets the old value and the operand. Returns the old value.
+#[inline]
+fn fetch_update(a: &mut [Var], op: fn(u64, u64) -> u64) -> Result<(), VmError> {
+    let name = name(a)?;
+    let operand = unsafe { a.get_unchecked(1) }.to_owned();
+    let old = updstatic(&name, |val| {
+        let old = current(val, &operand)?;
+        let x = op(
+            old.as_u64().unwrap(),
+            cast(&operand, &operand)?.as_u64().unwrap(),
+        );
+        *val = Some(cast(&Var::U64(x), &old)?);
+        Ok::<_, VmError>(old)
+    })?;
+    *(unsafe { a.get_unchecked_mut(0) }) = old;
+    Ok(())
+}
+
+/// Load a static.
+pub fn load(a: &mut [Var]) -> Result<(), VmError> {
+    let name = name(a)?;
+    let val = updstatic(&name, |val| current(val, &Var::U64(0)))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = val;
+    Ok(())
+}
+
+/// Store an integer to a static, in the type of the static if it's set.
+pub fn store(a: &mut [Var]) -> Result<(), VmError> {
+    let name = name(a)?;
+    let operand = unsafe { a.get_unchecked(1) }.to_owned();
+    updstatic(&name, |val| {
+        let new = cast(&operand, &current(val, &operand)?)?;
+        *val = Some(new);
+        Ok(())
+    })
+}
+
+/// Add to a static. Returns the old value.
+pub fn fetch_add(a: &mut [Var]) -> Result<(), VmError> {
+    fetch_update(a, u64::wrapping_add)
+}
+
+/// Subtract from a static. Returns the old value.
+pub fn fetch_sub(a: &mut [Var]) -> Result<(), VmError> {
+    fetch_update(a, u64::wrapping_sub)
+}
+
+/// Replace a static. Returns the old value.
+pub fn swap(a: &mut [Var]) -> Result<(), VmError> {
+    fetch_update(a, |_, x| x)
+}
+
+/// Replace a static with the new value at register 2 if it's equal to the expected value at
+/// register 1. Returns whether it's replaced, and the old value.
+pub fn compare_exchange(a: &mut [Var]) -> Result<(), VmError> {
+    let name = name(a)?;
+    let expected = unsafe { a.get_unchecked(1) }.to_owned();
+    let new = unsafe { a.get_unchecked(2) }.to_owned();
+    let (stat, old) = updstatic(&name, |val| {
+        let old = current(val, &expected)?;
+        if cast(&expected, &old)? != old {
+            return Ok::<_, VmError>((false, old));
+        }
+        *val = Some(cast(&new, &old)?);
+        Ok((true, old))
+    })?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(stat as u8);
+    *(unsafe { a.get_unchecked_mut(1) }) = old;
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::atomic::load", load);
+    putnfp("raw::atomic::store", store);
+    putnfp("raw::atomic::fetch_add", fetch_add);
+    putnfp("raw::atomic::fetch_sub", fetch_sub);
+    putnfp("raw::atomic::swap", swap);
+    putnfp("raw::atomic::compare_exchange", compare_exchange);
+}
@@ -2,15 +2,14 @@
 //! Bytes operating library of `libraw`.
 //!
 
-use crate::{context::putnfp, vmem::Var};
-use anyhow::anyhow;
+use crate::{context::putnfp, error::VmError, vmem::Var};
 
 macro_rules! impl_fromint {
     ($a: ident, $b: ident, $c: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let val = match unsafe { a.get_unchecked(0) } {
                 Var::$c(x) => x,
-                _ => return Err(anyhow!("raw::fatal::not_an_integer")),
+                _ => return Err(VmError::new("raw::fatal::not_an_integer")),
             };
             *(unsafe { a.get_unchecked_mut(0) }) = Var::Bytes(val.$b().to_vec().into());
             Ok(())
@@ -20,10 +19,10 @@ macro_rules! impl_fromint {
 
 macro_rules! impl_rslvint {
     ($a: ident, $b: ident, $c: ty, $d: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let val = match unsafe { a.get_unchecked(0) } {
                 Var::Bytes(x) => x,
-                _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+                _ => return Err(VmError::new("raw::fatal::not_a_buf")),
             }
             .clone();
             let val = val.borrow()?;
@@ -40,36 +39,36 @@ macro_rules! impl_rslvint {
 }
 
 /// Push an element into a vector.
-pub fn push(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn push(a: &mut [Var]) -> Result<(), VmError> {
     match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => {
             x.push(
                 unsafe { a.get_unchecked(1) }
                     .as_u8()
-                    .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?,
+                    .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?,
             )?;
         }
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     Ok(())
 }
 
 /// Clear the vector.
-pub fn clear(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn clear(a: &mut [Var]) -> Result<(), VmError> {
     match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => {
             x.clear()?;
         }
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     Ok(())
 }
 
 /// Convert to pointer.
-pub fn to_ptr(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn to_ptr(a: &mut [Var]) -> Result<(), VmError> {
     let val = match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => x,
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     .borrow_mut()?
     .as_mut_ptr();
@@ -78,42 +77,42 @@ pub fn to_ptr(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Truncate the vector.
-pub fn truncate(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn truncate(a: &mut [Var]) -> Result<(), VmError> {
     match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => {
             x.borrow_mut()?.truncate(
                 unsafe { a.get_unchecked(1) }
                     .as_usize()
-                    .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?,
+                    .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?,
             );
         }
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     Ok(())
 }
 
 /// Remove an element from the vector.
-pub fn remove(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn remove(a: &mut [Var]) -> Result<(), VmError> {
     match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => {
             let index = unsafe { a.get_unchecked(1) }
                 .as_usize()
-                .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
             if index >= x.len()? {
-                return Err(anyhow!("raw::fatal::out_of_range"));
+                return Err(VmError::new("raw::fatal::out_of_range"));
             }
             x.borrow_mut()?.remove(index);
         }
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     Ok(())
 }
 
 /// Pop an element from this vec.
-pub fn pop(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn pop(a: &mut [Var]) -> Result<(), VmError> {
     let v = match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => x,
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     };
     let mut v = v.borrow_mut()?;
     match v.pop() {
@@ -131,32 +130,32 @@ pub fn pop(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Resize a vector, truncate or fill.
-pub fn resize(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn resize(a: &mut [Var]) -> Result<(), VmError> {
     match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => {
             x.borrow_mut()?.resize(
                 unsafe { a.get_unchecked(1) }
                     .as_usize()
-                    .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?,
+                    .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?,
                 unsafe { a.get_unchecked(2) }
                     .as_u8()
-                    .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?,
+                    .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?,
             );
         }
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     Ok(())
 }
 
 /// Append.
-pub fn append(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn append(a: &mut [Var]) -> Result<(), VmError> {
     let cur = match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => x.clone(),
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     };
     let arg = match unsafe { a.get_unchecked(1) } {
         Var::Bytes(x) => x.clone(),
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     };
     let arg = arg.borrow()?;
     for i in arg.iter() {
@@ -166,7 +165,7 @@ pub fn append(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Equal test for bytes.
-pub fn eq(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn eq(a: &mut [Var]) -> Result<(), VmError> {
     let val1 = unsafe { a.get_unchecked(0) };
     let val2 = unsafe { a.get_unchecked(1) };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8((val1 == val2) as u8);
@@ -174,20 +173,20 @@ pub fn eq(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Insert.
-pub fn insert(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn insert(a: &mut [Var]) -> Result<(), VmError> {
     let cur = match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => x.clone(),
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     };
     let mut cur = cur.borrow_mut()?;
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let val = unsafe { a.get_unchecked(2) }
         .as_u8()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     if index > cur.len() {
-        Err(anyhow!("raw::fatal::out_of_range"))
+        Err(VmError::new("raw::fatal::out_of_range"))
     } else {
         cur.insert(index, val);
         Ok(())
@@ -195,25 +194,25 @@ pub fn insert(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Deep clone.
-pub fn deep_clone(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn deep_clone(a: &mut [Var]) -> Result<(), VmError> {
     let cur = match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => x.clone(),
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::Bytes(cur.borrow()?.clone().into());
     Ok(())
 }
 
 /// Containing test.
-pub fn contains(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn contains(a: &mut [Var]) -> Result<(), VmError> {
     let cur = match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => x,
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     .clone();
     let val = unsafe { a.get_unchecked(1) }
         .as_u8()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(cur.borrow()?.contains(&val) as u8);
     Ok(())
 }
@@ -4,10 +4,10 @@
 
 use crate::{
     context::putnfp,
+    error::VmError,
     libraw::iohmgr::FakeHasher,
     vmem::{Var, VectorRef},
 };
-use anyhow::anyhow;
 use libc::c_char;
 use libffi::high::{call::Arg as FfiArg, CType, CodePtr};
 use libloading::{library_filename, Library, Symbol};
@@ -43,19 +43,19 @@ enum OwnedFfiObject {
     Pointer(*mut u8),
 }
 impl TryFrom<&Var> for OwnedFfiObject {
-    type Error = anyhow::Error;
+    type Error = VmError;
 
-    fn try_from(v: &Var) -> Result<Self, anyhow::Error> {
+    fn try_from(v: &Var) -> Result<Self, VmError> {
         match v {
             Var::Object(x) => match x
                 .get("raw::cffi::type")?
                 .as_u8()
-                .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?
             {
                 12 => match x
                     .get("val")?
                     .as_sr()
-                    .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
                     .borrow()
                 {
                     Ok(y) => match CString::new(y.to_owned()) {
@@ -67,31 +67,31 @@ impl TryFrom<&Var> for OwnedFfiObject {
                 14 => Ok(OwnedFfiObject::Pointer(
                     x.get("val")?
                         .as_usize()
-                        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?
+                        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?
                         as *mut u8,
                 )),
                 8 => Ok(OwnedFfiObject::I8(
                     x.get("val")?
                         .as_u8()
-                        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?
+                        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?
                         as i8,
                 )),
                 16 => Ok(OwnedFfiObject::I16(
                     x.get("val")?
                         .as_u16()
-                        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?
+                        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?
                         as i16,
                 )),
                 32 => Ok(OwnedFfiObject::I32(x.get("val")?.as_i32()?)),
                 64 => Ok(OwnedFfiObject::I64(
                     x.get("val")?
                         .as_u64()
-                        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?
+                        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?
                         as i64,
                 )),
-                _ => Err(anyhow!("raw::fatal::invalid")),
+                _ => Err(VmError::new("raw::fatal::invalid")),
             },
-            _ => Err(anyhow!("raw::fatal::not_an_object")),
+            _ => Err(VmError::new("raw::fatal::not_an_object")),
         }
     }
 }
@@ -102,10 +102,10 @@ static LIBRARIES: RwLock<Vec<Library>> = const_rwlock(Vec::new());
 static SYMBOLS: RwLock<Vec<Sym>> = const_rwlock(Vec::new());
 
 /// Open a dynamic library.
-pub fn opendll(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn opendll(a: &mut [Var]) -> Result<(), VmError> {
     let path = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     match unsafe { Library::new(&path.borrow()?[..]) } {
         Ok(x) => {
             let mut guard = LIBRARIES.write();
@@ -122,13 +122,13 @@ pub fn opendll(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Open a symbol.
-pub fn opensym(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn opensym(a: &mut [Var]) -> Result<(), VmError> {
     let libid = unsafe { a.get_unchecked(0) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))?;
     let symid = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     match LIBRARIES.read().get(libid) {
         Some(x) => match unsafe { x.get::<*mut u8>(symid.borrow()?.as_bytes()) } {
             Ok(x) => {
@@ -150,32 +150,32 @@ pub fn opensym(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Make library filename.
-pub fn libpath(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn libpath(a: &mut [Var]) -> Result<(), VmError> {
     let name = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     *(unsafe { a.get_unchecked_mut(0) }) =
         Var::UString((&library_filename(&name.borrow()?[..]).to_string_lossy()[..]).into());
     Ok(())
 }
 
 /// Invoke a dynamic library.
-pub fn invoke(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn invoke(a: &mut [Var]) -> Result<(), VmError> {
     let symid = unsafe { a.get_unchecked(0) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))?;
     let args = match unsafe { a.get_unchecked(1) } {
         Var::Vector(x) => x,
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     .clone();
     let rettyp = unsafe { a.get_unchecked(2) }
         .as_u8()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let sym = SYMBOLS
         .read()
         .get(symid)
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))?
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))?
         .0;
     match rettyp {
         8 => unsafe {
@@ -202,39 +202,39 @@ pub fn invoke(a: &mut [Var]) -> Result<(), anyhow::Error> {
             *a.get_unchecked_mut(0) = Var::I64(_invoke(sym, args)?);
             Ok(())
         },
-        _ => Err(anyhow!("raw::fatal::invalid")),
+        _ => Err(VmError::new("raw::fatal::invalid")),
     }
 }
 
 /// Calculate pointer offset.
-pub fn ptroffset(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn ptroffset(a: &mut [Var]) -> Result<(), VmError> {
     let rptr = unsafe { a.get_unchecked(0) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))? as *const u8;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))? as *const u8;
     let offs = unsafe { a.get_unchecked(1) }
         .as_isize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))?;
     *(unsafe { a.get_unchecked_mut(0) }) = Var::Usize(rptr.wrapping_offset(offs) as usize);
     Ok(())
 }
 
 /// Get value from pointer.
-pub fn ptrget(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn ptrget(a: &mut [Var]) -> Result<(), VmError> {
     let rptr = unsafe { a.get_unchecked(0) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))? as *const u8;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))? as *const u8;
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(unsafe { *rptr });
     Ok(())
 }
 
 /// Set value to pointer.
-pub fn ptrset(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn ptrset(a: &mut [Var]) -> Result<(), VmError> {
     let rptr = unsafe { a.get_unchecked(0) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))? as *mut u8;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))? as *mut u8;
     let val = unsafe { a.get_unchecked(1) }
         .as_u8()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     unsafe { *rptr = val }
     Ok(())
 }
@@ -253,7 +253,7 @@ pub fn init() {
 
 /// Core of `raw::cffi::invoke`.
 #[inline]
-unsafe fn _invoke<T: CType>(fp: NonNull<u8>, args: VectorRef) -> Result<T, anyhow::Error> {
+unsafe fn _invoke<T: CType>(fp: NonNull<u8>, args: VectorRef) -> Result<T, VmError> {
     let args = args.borrow()?;
     let mut owned_ffi_objs = Vec::with_capacity(args.len());
     for i in args.iter() {
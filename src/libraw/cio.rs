@@ -4,9 +4,9 @@
 
 use crate::{
     context::putnfp,
+    error::VmError,
     vmem::{BytesRef, CreateNull, StringRef, Var},
 };
-use anyhow::anyhow;
 use parking_lot::RwLockReadGuard;
 use std::io::{stderr, stdin, stdout, BufRead, BufReader, Write};
 
@@ -15,24 +15,24 @@ macro_rules! to_buf {
         match $a {
             Var::UString(x) => Ok(RwLockReadGuard::map(
                 x.0.as_ref()
-                    .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
                     .read(),
                 |y| y.as_bytes(),
             )),
             Var::Bytes(x) => Ok(RwLockReadGuard::map(
                 x.0.as_ref()
-                    .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
                     .read(),
                 |y| &y[..],
             )),
-            _ => Err(anyhow!("raw::fatal::not_a_buf")),
+            _ => Err(VmError::new("raw::fatal::not_a_buf")),
         }
     };
 }
 
 macro_rules! impl_print {
     ($a: ident, $b: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let x = $b()
                 .write_all(&to_buf!(unsafe { a.get_unchecked(0) })?)
                 .is_ok();
@@ -47,7 +47,7 @@ macro_rules! impl_print {
 
 macro_rules! impl_flush {
     ($a: ident, $b: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             match $b().flush() {
                 Ok(()) => unsafe { *a.get_unchecked_mut(0) = Var::U8(1) },
                 Err(_) => unsafe { *a.get_unchecked_mut(0) = Var::U8(0) },
@@ -61,7 +61,7 @@ impl_print!(print, stdout);
 impl_flush!(flush, stdout);
 
 /// Read a line to a Bytes.
-pub fn read_bytes(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn read_bytes(a: &mut [Var]) -> Result<(), VmError> {
     let mut buf = Vec::new();
     let mut reader = BufReader::new(stdin());
     match reader.read_until(0xA, &mut buf).is_ok() {
@@ -0,0 +1,204 @@
+//!
+//! Channels between coroutines of `libraw`.
+//!
+//! In coroutine mode, `raw::coro::chan::send` on a full channel and `raw::coro::chan::recv` on an
+//! empty channel suspend the calling coroutine until the channel is ready. Outside coroutine mode
+//! they never block, and return `WOULD_BLOCK` instead. Values that have been sent can still be
+//! received after the channel is closed. If every coroutine is waiting for a channel, the waiting
+//! coroutines are interrupted with `raw::coro::deadlock`.
+//!
+
+use crate::{
+    context::putnfp,
+    error::VmError,
+    executor::reactor::{self, READABLE, WRITABLE},
+    libraw::iohmgr::{error as io_error, FakeHasher, IdGen},
+    vmem::{
+        mem::{Accounted, Kind, Measure},
+        Var,
+    },
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use parking_lot::{const_mutex, Mutex, RwLock};
+use std::collections::VecDeque;
+
+/// A channel.
+struct Channel {
+    /// Values that haven't been received.
+    queue: VecDeque<Var>,
+    /// Capacity of the channel. 0 means unbounded.
+    cap: usize,
+    /// If the channel is closed.
+    closed: bool,
+}
+impl Measure for Channel {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.queue.heap_size()
+    }
+}
+
+/// ID generator for channels.
+static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
+/// Table of channels.
+static TABLE: Lazy<DashMap<u64, Accounted<RwLock<Channel>>, FakeHasher>> =
+    Lazy::new(DashMap::default);
+
+/// Judge if a channel is ready for `interest`, so a coroutine waiting for it can be woken. A
+/// closed or dropped channel is always ready.
+pub fn ready(id: u64, interest: u64) -> bool {
+    let chan = match TABLE.get(&id) {
+        Some(x) => x,
+        None => return true,
+    };
+    let chan = chan.read();
+    chan.closed
+        || (interest & READABLE != 0 && !chan.queue.is_empty())
+        || (interest & WRITABLE != 0 && (chan.cap == 0 || chan.queue.len() < chan.cap))
+}
+
+/// Get the channel ID at register 0.
+#[inline]
+fn id(a: &[Var]) -> Result<u64, VmError> {
+    unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Send a value. If `wait` is `true`, the coroutine is suspended when the channel is full.
+fn send_impl(a: &mut [Var], wait: bool) -> Result<(), VmError> {
+    let id = id(a)?;
+    let status = match TABLE.get(&id) {
+        Some(x) => {
+            let mut chan = x.write();
+            if chan.closed {
+                io_error::BROKEN_PIPE
+            } else if chan.cap != 0 && chan.queue.len() >= chan.cap {
+                io_error::WOULD_BLOCK
+            } else {
+                chan.queue
+                    .push_back(unsafe { a.get_unchecked(1) }.to_owned());
+                0
+            }
+        }
+        None => io_error::OTHER,
+    };
+    if wait && status == io_error::WOULD_BLOCK {
+        reactor::wait_chan(id, WRITABLE)?;
+    }
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(status);
+    Ok(())
+}
+
+/// Receive a value. If `wait` is `true`, the coroutine is suspended when the channel is empty.
+fn recv_impl(a: &mut [Var], wait: bool) -> Result<(), VmError> {
+    let id = id(a)?;
+    let status = match TABLE.get(&id) {
+        Some(x) => {
+            let mut chan = x.write();
+            match chan.queue.pop_front() {
+                Some(y) => {
+                    *(unsafe { a.get_unchecked_mut(1) }) = y;
+                    0
+                }
+                None if chan.closed => io_error::BROKEN_PIPE,
+                None => io_error::WOULD_BLOCK,
+            }
+        }
+        None => io_error::OTHER,
+    };
+    if wait && status == io_error::WOULD_BLOCK {
+        reactor::wait_chan(id, READABLE)?;
+    }
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(status);
+    Ok(())
+}
+
+/// Create a channel with specified capacity. 0 means unbounded.
+pub fn new(a: &mut [Var]) -> Result<(), VmError> {
+    let cap = unsafe { a.get_unchecked(0) }
+        .as_usize()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let id = IDGEN.lock().next();
+    let chan = Channel {
+        queue: VecDeque::with_capacity(cap.min(64)),
+        cap,
+        closed: false,
+    };
+    TABLE.insert(id, Accounted::measured(Kind::Channel, chan));
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
+    Ok(())
+}
+
+/// Send a value, and suspend the coroutine while the channel is full.
+pub fn send(a: &mut [Var]) -> Result<(), VmError> {
+    send_impl(a, true)
+}
+
+/// Send a value if the channel is not full.
+pub fn try_send(a: &mut [Var]) -> Result<(), VmError> {
+    send_impl(a, false)
+}
+
+/// Receive a value, and suspend the coroutine while the channel is empty.
+pub fn recv(a: &mut [Var]) -> Result<(), VmError> {
+    recv_impl(a, true)
+}
+
+/// Receive a value if the channel is not empty.
+pub fn try_recv(a: &mut [Var]) -> Result<(), VmError> {
+    recv_impl(a, false)
+}
+
+/// Close a channel.
+pub fn close(a: &mut [Var]) -> Result<(), VmError> {
+    if let Some(x) = TABLE.get(&id(a)?) {
+        x.write().closed = true;
+    }
+    Ok(())
+}
+
+/// Judge if a channel is closed. A dropped channel is closed.
+pub fn is_closed(a: &mut [Var]) -> Result<(), VmError> {
+    let closed = match TABLE.get(&id(a)?) {
+        Some(x) => x.read().closed,
+        None => true,
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(closed as u8);
+    Ok(())
+}
+
+/// Get the count of values that haven't been received.
+pub fn len(a: &mut [Var]) -> Result<(), VmError> {
+    let len = match TABLE.get(&id(a)?) {
+        Some(x) => x.read().queue.len(),
+        None => 0,
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(len as u64);
+    Ok(())
+}
+
+/// Drop a channel.
+pub fn drop(a: &mut [Var]) -> Result<(), VmError> {
+    let id = id(a)?;
+    if TABLE.remove(&id).is_some() {
+        IDGEN.lock().free(id);
+    }
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    IDGEN.lock().next();
+    putnfp("raw::coro::chan::new", new);
+    putnfp("raw::coro::chan::send", send);
+    putnfp("raw::coro::chan::try_send", try_send);
+    putnfp("raw::coro::chan::recv", recv);
+    putnfp("raw::coro::chan::try_recv", try_recv);
+    putnfp("raw::coro::chan::close", close);
+    putnfp("raw::coro::chan::is_closed", is_closed);
+    putnfp("raw::coro::chan::len", len);
+    putnfp("raw::coro::chan::drop", drop);
+}
@@ -4,27 +4,33 @@
 
 use crate::{
     context::putnfp,
+    error::VmError,
     libraw::iohmgr::{FakeHasher, IdGen},
-    vmem::Var,
+    vmem::{
+        mem::{Accounted, Kind},
+        Var,
+    },
 };
-use anyhow::anyhow;
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use parking_lot::{const_mutex, Mutex, RwLock};
 use rayon::prelude::*;
 use std::collections::VecDeque;
 
+/// A deque.
+type Deque = Accounted<RwLock<VecDeque<Var>>>;
+
 /// ID Generator for deques.
 static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
 /// Table of deques.
-static DEQUES: Lazy<DashMap<u64, RwLock<VecDeque<Var>>, FakeHasher>> = Lazy::new(DashMap::default);
+static DEQUES: Lazy<DashMap<u64, Deque, FakeHasher>> = Lazy::new(DashMap::default);
 
 macro_rules! impl_pop {
     ($a: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let id = unsafe { a.get_unchecked(0) }
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
             let deque = match DEQUES.get(&id) {
                 Some(x) => x,
                 None => unsafe {
@@ -48,10 +54,10 @@ macro_rules! impl_pop {
 
 macro_rules! impl_push {
     ($a: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let id = unsafe { a.get_unchecked(0) }
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
             let val = unsafe { a.get_unchecked(1) }.to_owned();
             let deque = match DEQUES.get(&id) {
                 Some(x) => x,
@@ -68,18 +74,18 @@ macro_rules! impl_push {
 }
 
 /// Create a new one.
-pub fn new(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn new(a: &mut [Var]) -> Result<(), VmError> {
     let id = IDGEN.lock().next();
-    DEQUES.insert(id, RwLock::new(VecDeque::new()));
+    DEQUES.insert(id, Accounted::measured(Kind::Deque, VecDeque::new()));
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
     Ok(())
 }
 
 /// Drop a deque.
-pub fn drop(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn drop(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     if DEQUES.remove(&id).is_some() {
         IDGEN.lock().free(id);
     }
@@ -87,13 +93,13 @@ pub fn drop(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get a value.
-pub fn get(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn get(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -114,13 +120,13 @@ pub fn get(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Set a value.
-pub fn set(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn set(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let val = unsafe { a.get_unchecked(2) }.to_owned();
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
@@ -142,24 +148,24 @@ pub fn set(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Swap values.
-pub fn swap(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn swap(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let index1 = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let index2 = unsafe { a.get_unchecked(2) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     let mut deque = deque.write();
     let len = deque.len();
     if index1 >= len || index2 >= len {
-        Err(anyhow!("raw::fatal::out_of_range"))
+        Err(VmError::new("raw::fatal::out_of_range"))
     } else {
         deque.swap(index1, index2);
         Ok(())
@@ -167,13 +173,13 @@ pub fn swap(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Truncate.
-pub fn truncate(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn truncate(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let newlen = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -187,10 +193,10 @@ pub fn truncate(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get length.
-pub fn len(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn len(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -204,10 +210,10 @@ pub fn len(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Clear.
-pub fn clear(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn clear(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -221,21 +227,21 @@ pub fn clear(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Insert.
-pub fn insert(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn insert(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        _ => return Err(anyhow!("raw::fatal::segfault")),
+        _ => return Err(VmError::new("raw::fatal::segfault")),
     };
     let mut cur = cur.write();
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let val = unsafe { a.get_unchecked(2) }.to_owned();
     if index > cur.len() {
-        Err(anyhow!("raw::fatal::out_of_range"))
+        Err(VmError::new("raw::fatal::out_of_range"))
     } else {
         cur.insert(index, val);
         Ok(())
@@ -243,34 +249,34 @@ pub fn insert(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Remove an element from the deque.
-pub fn remove(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn remove(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     let mut cur = cur.write();
 
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     if index >= cur.len() {
-        return Err(anyhow!("raw::fatal::out_of_range"));
+        return Err(VmError::new("raw::fatal::out_of_range"));
     }
     cur.remove(index);
     Ok(())
 }
 
 /// Convert to vector.
-pub fn to_vec(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn to_vec(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     *(unsafe { a.get_unchecked_mut(0) }) =
         Var::Vector(Into::<Vec<_>>::into(cur.read().to_owned()).into());
@@ -278,36 +284,39 @@ pub fn to_vec(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Create a new one from a vector.
-pub fn from_vec(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn from_vec(a: &mut [Var]) -> Result<(), VmError> {
     let id = IDGEN.lock().next();
     DEQUES.insert(
         id,
-        RwLock::new(VecDeque::from(Into::<Vec<Var>>::into(
-            &match unsafe { a.get_unchecked(0) } {
-                Var::Vector(x) => x,
-                _ => return Err(anyhow!("raw::fatal::not_a_buf")),
-            }
-            .borrow()?[..],
-        ))),
+        Accounted::measured(
+            Kind::Deque,
+            VecDeque::from(Into::<Vec<Var>>::into(
+                &match unsafe { a.get_unchecked(0) } {
+                    Var::Vector(x) => x,
+                    _ => return Err(VmError::new("raw::fatal::not_a_buf")),
+                }
+                .borrow()?[..],
+            )),
+        ),
     );
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
     Ok(())
 }
 
 /// Append elements.
-pub fn append(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn append(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     match unsafe { a.get_unchecked(1) } {
         Var::U64(x) => {
             let ndeq = match DEQUES.get(x) {
                 Some(x) => x,
-                None => return Err(anyhow!("raw::fatal::segfault")),
+                None => return Err(VmError::new("raw::fatal::segfault")),
             };
             ndeq.read()
                 .par_iter()
@@ -320,19 +329,19 @@ pub fn append(a: &mut [Var]) -> Result<(), anyhow::Error> {
                 .for_each(|x| cur.write().push_back(x.to_owned()));
             Ok(())
         }
-        _ => Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => Err(VmError::new("raw::fatal::not_a_buf")),
     }
 }
 
 /// Content containing test.
-pub fn contains(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn contains(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let val = unsafe { a.get_unchecked(1) };
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(cur.read().contains(val) as u8);
     Ok(())
@@ -4,12 +4,12 @@
 
 use crate::{
     context::{self, fpatch, getfp, putnfp, Thread},
-    executor,
+    error::VmError,
+    executor::{self, reactor},
     isa::FuncPtr,
     resolver,
     vmem::{CreateNull, StringRef, Var},
 };
-use anyhow::anyhow;
 
 /// Initialize the library.
 #[inline(always)]
@@ -17,21 +17,25 @@ pub fn init() {
     putnfp("raw::dl::load_file", vload);
     putnfp("raw::vhw::locate_func", locate_func);
     putnfp("raw::coro::enter", coroenter);
+    putnfp("raw::coro::set_quantum", set_quantum);
+    putnfp("raw::coro::get_quantum", get_quantum);
+    putnfp("raw::coro::sleep", sleep);
     putnfp("raw::vhw::dump<context>", dump);
     putnfp("raw::vhw::expand<topsil>", expand);
+    putnfp("raw::vhw::collect<topsil>", collect);
     putnfp("raw::info<host>", os_id);
     putnfp("raw::vhw::sync_cache", force_sync_cache);
     putnfp("raw::vhw::patch_func", patch_func);
 }
 
 /// Patch a function.
-pub fn patch_func(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn patch_func(a: &mut [Var]) -> Result<(), VmError> {
     let origin = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let patched = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let origin = origin.borrow()?;
     let patched = patched.borrow()?;
     fpatch(&origin[..], &patched[..])?;
@@ -39,7 +43,7 @@ pub fn patch_func(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Core dump context.
-pub fn dump(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn dump(a: &mut [Var]) -> Result<(), VmError> {
     unsafe {
         *a.get_unchecked_mut(0) = Var::UString((&*context::dump()).into());
     }
@@ -47,13 +51,13 @@ pub fn dump(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Force sync the function cache of current thread.
-pub fn force_sync_cache(_: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn force_sync_cache(_: &mut [Var]) -> Result<(), VmError> {
     context::force_sync_cache();
     Ok(())
 }
 
 /// Get OS ID.
-pub fn os_id(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn os_id(a: &mut [Var]) -> Result<(), VmError> {
     unsafe {
         *a.get_unchecked_mut(0) = Var::UString(std::env::consts::FAMILY.into());
         *a.get_unchecked_mut(1) = Var::UString(std::env::consts::OS.into());
@@ -63,15 +67,15 @@ pub fn os_id(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Expand SIL.
-pub fn expand(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn expand(a: &mut [Var]) -> Result<(), VmError> {
     let rb = match unsafe { a.get_unchecked(0) } {
         Var::Vector(x) => x,
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     .clone();
     let rb = rb.borrow()?;
     if rb.len() > 50 {
-        return Err(anyhow!("raw::fatal::segfault"));
+        return Err(VmError::new("raw::fatal::segfault"));
     }
     for i in 0..rb.len() {
         unsafe {
@@ -81,11 +85,20 @@ pub fn expand(a: &mut [Var]) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Collect SIL into a vector, which is the reverse of `expand`.
+pub fn collect(a: &mut [Var]) -> Result<(), VmError> {
+    let rb: Vec<Var> = a.iter().map(Var::to_owned).collect();
+    unsafe {
+        *a.get_unchecked_mut(0) = Var::Vector(rb.into());
+    }
+    Ok(())
+}
+
 /// Dynamically load a VM library from file.
-pub fn vload(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn vload(a: &mut [Var]) -> Result<(), VmError> {
     let path = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let path = path.borrow()?;
     match resolver::resolve(&*path) {
         Ok(()) => unsafe { *a.get_unchecked_mut(0) = Var::UString(StringRef::null()) },
@@ -95,21 +108,49 @@ pub fn vload(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Enter coro mode.
-pub fn coroenter(_: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn coroenter(_: &mut [Var]) -> Result<(), VmError> {
     match getfp("_start_coro") {
         Some(FuncPtr::Virtual(x)) => {
             executor::start_coro(Thread::new(x));
             Ok(())
         }
-        _ => Err(anyhow!("raw::fatal::segfault")),
+        _ => Err(VmError::new("raw::fatal::segfault")),
     }
 }
 
+/// Set the count of instructions a coroutine executes before it yields automatically. 0 means
+/// disabled.
+pub fn set_quantum(a: &mut [Var]) -> Result<(), VmError> {
+    let n = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    reactor::set_quantum(n);
+    Ok(())
+}
+
+/// Get the count of instructions a coroutine executes before it yields automatically. 0 means
+/// disabled.
+pub fn get_quantum(a: &mut [Var]) -> Result<(), VmError> {
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(reactor::quantum());
+    Ok(())
+}
+
+/// Sleep for specified milliseconds. In a coroutine or a task, only the current one is suspended;
+/// otherwise, the OS thread blocks.
+pub fn sleep(a: &mut [Var]) -> Result<(), VmError> {
+    let ms = unsafe { a.get_unchecked(0) }
+        .as_u64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    reactor::sleep(ms)?;
+    std::thread::sleep(std::time::Duration::from_millis(ms));
+    Ok(())
+}
+
 /// Locate a function.
-pub fn locate_func(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn locate_func(a: &mut [Var]) -> Result<(), VmError> {
     let name_ref = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let name = name_ref.borrow()?;
     *(unsafe { a.get_unchecked_mut(0) }) = match getfp(&*name) {
         Some(_) => Var::UString(name_ref.clone()),
@@ -4,12 +4,12 @@
 
 use crate::{
     context::{putnfp, Thread},
+    error::VmError,
     executor,
     isa::VirtFuncPtr,
     libraw::iohmgr,
     vmem::Var,
 };
-use anyhow::anyhow;
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 
@@ -35,16 +35,16 @@ pub fn init() {
 }
 
 /// Argument count.
-pub fn argc(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn argc(a: &mut [Var]) -> Result<(), VmError> {
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64((ARGS.len() as u64) - 1);
     Ok(())
 }
 
 /// Change working directory.
-pub fn cd(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn cd(a: &mut [Var]) -> Result<(), VmError> {
     let dir = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let dir = dir.borrow()?;
     match std::env::set_current_dir(&dir[..]) {
         Ok(()) => unsafe { *a.get_unchecked_mut(0) = Var::U64(0) },
@@ -54,22 +54,22 @@ pub fn cd(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get temp directory.
-pub fn temp_dir(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn temp_dir(a: &mut [Var]) -> Result<(), VmError> {
     *(unsafe { a.get_unchecked_mut(0) }) =
         Var::UString(std::env::temp_dir().to_string_lossy()[..].into());
     Ok(())
 }
 
 /// Catch system interruption.
-pub fn catch_sysint(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn catch_sysint(a: &mut [Var]) -> Result<(), VmError> {
     use crate::{context::getfp, isa::FuncPtr};
 
     let fp = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let fp = match getfp(&fp.borrow()?[..]) {
         Some(FuncPtr::Virtual(x)) => x,
-        _ => return Err(anyhow!("raw::fatal::segfault")),
+        _ => return Err(VmError::new("raw::fatal::segfault")),
     };
     *CTRLC_HANDLER.lock() = Some(fp);
     unsafe {
@@ -84,13 +84,13 @@ extern "C" fn ctrlc_handler(_: libc::c_int) {
 }
 
 /// Get argument.
-pub fn argv(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn argv(a: &mut [Var]) -> Result<(), VmError> {
     use crate::vmem::{CreateNull, StringRef};
 
     let val = match ARGS.get(
         unsafe { a.get_unchecked(0) }
             .as_usize()
-            .ok_or_else(|| anyhow!("raw::fatal::not_a_ptr"))?
+            .ok_or_else(|| VmError::new("raw::fatal::not_a_ptr"))?
             + 1,
     ) {
         Some(x) => x.to_owned().into(),
@@ -101,17 +101,17 @@ pub fn argv(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get environment variable.
-pub fn getenv(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn getenv(a: &mut [Var]) -> Result<(), VmError> {
     use crate::vmem::{CreateNull, StringRef};
     use std::env::var;
 
     let id = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let id = id.borrow()?;
 
     if id.contains('=') {
-        return Err(anyhow!("raw::fatal::invalid_envid"));
+        return Err(VmError::new("raw::fatal::invalid_envid"));
     }
 
     match var(&**id) {
@@ -122,20 +122,20 @@ pub fn getenv(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Set environment variable.
-pub fn setenv(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn setenv(a: &mut [Var]) -> Result<(), VmError> {
     use std::env::set_var;
 
     let id = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let id = id.borrow()?;
 
     if id.contains('=') {
-        return Err(anyhow!("raw::fatal::invalid_envid"));
+        return Err(VmError::new("raw::fatal::invalid_envid"));
     }
     let val = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let val = val.borrow()?;
 
     set_var(&**id, &**val);
@@ -143,36 +143,36 @@ pub fn setenv(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Unset an environment variable.
-pub fn unset(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn unset(a: &mut [Var]) -> Result<(), VmError> {
     use std::env::remove_var;
 
     let id = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let id = id.borrow()?;
     if id.contains('=') {
-        return Err(anyhow!("raw::fatal::invalid_envid"));
+        return Err(VmError::new("raw::fatal::invalid_envid"));
     }
     remove_var(&id[..]);
     Ok(())
 }
 
 /// Exit this process immediately.
-pub fn exit(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn exit(a: &mut [Var]) -> Result<(), VmError> {
     std::process::exit(
         unsafe { a.get_unchecked(0) }
             .as_usize()
-            .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))? as i32,
+            .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))? as i32,
     )
 }
 
 /// Abort this process immediately.
-pub fn abort(_: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn abort(_: &mut [Var]) -> Result<(), VmError> {
     std::process::abort()
 }
 
 /// Get PID of current process.
-pub fn getpid(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn getpid(a: &mut [Var]) -> Result<(), VmError> {
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U32(std::process::id());
     Ok(())
 }
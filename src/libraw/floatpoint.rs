@@ -4,21 +4,20 @@
 //! `system::f64` module provides high-level abstraction of this library.
 //!
 
-use crate::{context::putnfp, vmem::Var};
-use anyhow::anyhow;
+use crate::{context::putnfp, error::VmError, vmem::Var};
 
 macro_rules! impl_fpcalc {
     ($a: ident, $b: tt) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let val1 = f64::from_bits(
                 unsafe { a.get_unchecked(0) }
                     .as_u64_strict()
-                    .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?
             );
             let val2 = f64::from_bits(
                 unsafe { a.get_unchecked(1) }
                     .as_u64_strict()
-                    .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?
             );
             *(unsafe { a.get_unchecked_mut(0) }) = Var::U64((val1 $b val2).to_bits());
             Ok(())
@@ -27,21 +26,21 @@ macro_rules! impl_fpcalc {
 }
 
 /// Convert an integer to a float.
-pub fn from_int(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn from_int(a: &mut [Var]) -> Result<(), VmError> {
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(
         unsafe { a.get_unchecked(0) }
             .as_f64()
-            .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?
+            .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?
             .to_bits(),
     );
     Ok(())
 }
 
 /// Parse a string to float.
-pub fn parse(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn parse(a: &mut [Var]) -> Result<(), VmError> {
     let tp = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let tp = tp.borrow()?;
     match tp.parse::<f64>() {
         Ok(x) => {
@@ -56,12 +55,12 @@ pub fn parse(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Convert to string.
-pub fn to_str(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn to_str(a: &mut [Var]) -> Result<(), VmError> {
     let val = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::UString(val.to_string().into());
@@ -69,19 +68,19 @@ pub fn to_str(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Equal test.
-pub fn eq(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn eq(a: &mut [Var]) -> Result<(), VmError> {
     let val1 = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     let val2 = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8((val1 == val2) as u8);
@@ -89,12 +88,12 @@ pub fn eq(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Convert from F64 to I64.
-pub fn to_i64(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn to_i64(a: &mut [Var]) -> Result<(), VmError> {
     let val = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::I64(val as i64);
@@ -102,19 +101,19 @@ pub fn to_i64(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Less-than test.
-pub fn lt(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn lt(a: &mut [Var]) -> Result<(), VmError> {
     let val1 = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     let val2 = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8((val1 < val2) as u8);
@@ -122,19 +121,19 @@ pub fn lt(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// More-than test.
-pub fn mt(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn mt(a: &mut [Var]) -> Result<(), VmError> {
     let val1 = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     let val2 = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8((val1 > val2) as u8);
@@ -142,12 +141,12 @@ pub fn mt(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// SQRT math algorithm.
-pub fn sqrt(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn sqrt(a: &mut [Var]) -> Result<(), VmError> {
     let val = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(val.sqrt().to_bits());
@@ -155,12 +154,12 @@ pub fn sqrt(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// CBRT math algorithm.
-pub fn cbrt(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn cbrt(a: &mut [Var]) -> Result<(), VmError> {
     let val = unsafe {
         f64::from_bits(
             a.get_unchecked(0)
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::math_type_error"))?,
+                .ok_or_else(|| VmError::new("raw::fatal::math_type_error"))?,
         )
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(val.cbrt().to_bits());
@@ -1,21 +1,46 @@
 //!
 //! Local filesystem I/O module of `libraw`.
 //!
+//! Path operations return an `iohmgr::error` status at register 0, and their results from
+//! register 1. Paths are returned as strings; names which are not valid UTF-8 are converted
+//! lossily.
+//!
+
+#[cfg(target_os = "linux")]
+pub mod watch;
 
 use crate::{
     context::putnfp,
+    error::VmError,
+    executor::reactor::{READABLE, WRITABLE},
     libraw::iohmgr::{self, error as io_error, RawObject},
-    vmem::Var,
+    vmem::{
+        mapped::{self, MappedRef},
+        ObjectRef, Var,
+    },
+};
+use std::{
+    fs::{File, FileType, Metadata, TryLockError},
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
 };
-use anyhow::anyhow;
-use std::io::Read;
+
+/// Kind of a regular file.
+pub const KIND_FILE: u8 = 1;
+/// Kind of a directory.
+pub const KIND_DIR: u8 = 2;
+/// Kind of a symbolic link.
+pub const KIND_SYMLINK: u8 = 4;
+/// Kind of other files, such as devices, sockets and pipes.
+pub const KIND_OTHER: u8 = 8;
 
 macro_rules! impl_remove {
     ($a: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let path = unsafe { a.get_unchecked(0) }
                 .as_sr()
-                .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+                .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
             let path = path.borrow()?;
             match std::fs::$a(&path[..]) {
                 Ok(_) => *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(0),
@@ -26,11 +51,236 @@ macro_rules! impl_remove {
     };
 }
 
+/// Get the path argument at `i`.
+#[inline]
+fn arg_path(a: &[Var], i: usize) -> Result<String, VmError> {
+    let path = unsafe { a.get_unchecked(i) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let path = path.borrow()?;
+    Ok(path.to_owned())
+}
+
+/// Put the status of a path operation, and the result at 1 if it has succeeded.
+#[inline]
+fn put_result(a: &mut [Var], result: std::io::Result<Var>) {
+    match result {
+        Ok(x) => unsafe {
+            *a.get_unchecked_mut(0) = Var::U64(0);
+            *a.get_unchecked_mut(1) = x;
+        },
+        Err(x) => *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(io_error::from(x.kind())),
+    }
+}
+
+/// Convert a path to a string.
+#[inline]
+fn path_var(path: &Path) -> Var {
+    Var::UString(path.to_string_lossy().as_ref().into())
+}
+
+/// Get the kind of a file type.
+#[inline]
+fn kind(t: FileType) -> u8 {
+    if t.is_symlink() {
+        KIND_SYMLINK
+    } else if t.is_dir() {
+        KIND_DIR
+    } else if t.is_file() {
+        KIND_FILE
+    } else {
+        KIND_OTHER
+    }
+}
+
+/// Convert a time to a UNIX timestamp in seconds, which is 0 if the time is unavailable.
+#[inline]
+fn timestamp(t: std::io::Result<SystemTime>) -> Var {
+    Var::I64(match t {
+        Ok(x) => match x.duration_since(UNIX_EPOCH) {
+            Ok(y) => y.as_secs() as i64,
+            Err(y) => -(y.duration().as_secs() as i64),
+        },
+        Err(_) => 0,
+    })
+}
+
+/// Convert metadata to an object of type `raw::fs::metadata`.
+fn metadata_var(m: &Metadata) -> Result<Var, VmError> {
+    #[cfg(unix)]
+    let mode = std::os::unix::fs::PermissionsExt::mode(&m.permissions());
+    #[cfg(not(unix))]
+    let mode = 0;
+    let result = ObjectRef::new("raw::fs::metadata");
+    result.set("kind", Var::U8(kind(m.file_type())))?;
+    result.set("len", Var::U64(m.len()))?;
+    result.set("readonly", Var::U8(m.permissions().readonly() as u8))?;
+    result.set("mode", Var::U32(mode))?;
+    result.set("modified", timestamp(m.modified()))?;
+    result.set("accessed", timestamp(m.accessed()))?;
+    result.set("created", timestamp(m.created()))?;
+    Ok(Var::Object(result))
+}
+
+/// List names of entries in a directory, in the order of names.
+pub fn list(a: &mut [Var]) -> Result<(), VmError> {
+    let path = arg_path(a, 0)?;
+    let result = std::fs::read_dir(&path).and_then(|x| {
+        let mut names = x
+            .map(|y| y.map(|z| z.file_name().to_string_lossy().into_owned()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        names.sort_unstable();
+        Ok(Var::Vector(
+            names
+                .into_iter()
+                .map(|y| Var::UString(y.as_str().into()))
+                .collect::<Vec<_>>()
+                .into(),
+        ))
+    });
+    put_result(a, result);
+    Ok(())
+}
+
+/// Get metadata of a file, following symbolic links. Returns an object of type
+/// `raw::fs::metadata`, with `kind`, `len`, `readonly`, `mode` (permission bits on UNIX, or 0),
+/// `modified`, `accessed` and `created` (UNIX timestamps, or 0 if unavailable) fields.
+pub fn stat(a: &mut [Var]) -> Result<(), VmError> {
+    let path = arg_path(a, 0)?;
+    match std::fs::metadata(&path) {
+        Ok(x) => put_result(a, Ok(metadata_var(&x)?)),
+        Err(x) => put_result(a, Err(x)),
+    }
+    Ok(())
+}
+
+/// Get metadata of a file without following symbolic links.
+pub fn lstat(a: &mut [Var]) -> Result<(), VmError> {
+    let path = arg_path(a, 0)?;
+    match std::fs::symlink_metadata(&path) {
+        Ok(x) => put_result(a, Ok(metadata_var(&x)?)),
+        Err(x) => put_result(a, Err(x)),
+    }
+    Ok(())
+}
+
+/// Rename a file or directory, replacing the destination if it's a file.
+pub fn rename(a: &mut [Var]) -> Result<(), VmError> {
+    let (from, to) = (arg_path(a, 0)?, arg_path(a, 1)?);
+    put_result(a, std::fs::rename(from, to).map(|_| Var::U64(0)));
+    Ok(())
+}
+
+/// Copy a file, including its permissions. Returns the count of bytes copied.
+pub fn copy(a: &mut [Var]) -> Result<(), VmError> {
+    let (from, to) = (arg_path(a, 0)?, arg_path(a, 1)?);
+    put_result(a, std::fs::copy(from, to).map(Var::U64));
+    Ok(())
+}
+
+/// Create a symbolic link at 1 which points to 0.
+pub fn symlink(a: &mut [Var]) -> Result<(), VmError> {
+    let (target, link) = (arg_path(a, 0)?, arg_path(a, 1)?);
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(target, link);
+    #[cfg(windows)]
+    let result = match Path::new(&target).is_dir() {
+        true => std::os::windows::fs::symlink_dir(target, link),
+        false => std::os::windows::fs::symlink_file(target, link),
+    };
+    put_result(a, result.map(|_| Var::U64(0)));
+    Ok(())
+}
+
+/// Create a hard link at 1 to the file at 0.
+pub fn hard_link(a: &mut [Var]) -> Result<(), VmError> {
+    let (from, to) = (arg_path(a, 0)?, arg_path(a, 1)?);
+    put_result(a, std::fs::hard_link(from, to).map(|_| Var::U64(0)));
+    Ok(())
+}
+
+/// Get the target of a symbolic link.
+pub fn read_link(a: &mut [Var]) -> Result<(), VmError> {
+    let path = arg_path(a, 0)?;
+    put_result(a, std::fs::read_link(path).map(|x| path_var(&x)));
+    Ok(())
+}
+
+/// Get the absolute path with symbolic links resolved.
+pub fn canonicalize(a: &mut [Var]) -> Result<(), VmError> {
+    let path = arg_path(a, 0)?;
+    put_result(a, std::fs::canonicalize(path).map(|x| path_var(&x)));
+    Ok(())
+}
+
+/// Filters of walking a directory.
+struct Filter {
+    /// Maximum depth, where entries in the root are at depth 1. 0 means unlimited.
+    depth: u64,
+    /// Mask of kinds. 0 means all kinds.
+    kinds: u8,
+    /// Suffix of names. Empty means any name.
+    suffix: String,
+}
+impl Filter {
+    /// Judge if an entry is kept.
+    #[inline]
+    fn keep(&self, kind: u8, name: &str) -> bool {
+        (self.kinds == 0 || self.kinds & kind != 0) && name.ends_with(&self.suffix[..])
+    }
+}
+
+/// Walk a directory recursively in pre-order, and push paths of kept entries to `result`.
+/// Subdirectories which can't be read are skipped.
+fn walk_dir(dir: &Path, depth: u64, filter: &Filter, result: &mut Vec<Var>) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_unstable_by_key(|x| x.file_name());
+    for i in entries {
+        let kind = match i.file_type() {
+            Ok(x) => kind(x),
+            Err(_) => continue,
+        };
+        let path = i.path();
+        if filter.keep(kind, &i.file_name().to_string_lossy()) {
+            result.push(path_var(&path));
+        }
+        if kind == KIND_DIR && (filter.depth == 0 || depth < filter.depth) {
+            walk_dir(&path, depth + 1, filter, result).ok();
+        }
+    }
+    Ok(())
+}
+
+/// Walk a directory recursively without following symbolic links. Arguments are the root, the
+/// maximum depth (0 means unlimited), a mask of kinds (`KIND_*`, 0 means all kinds) and a suffix
+/// of names (empty means any name). Returns a vector of paths of matched entries, with parents
+/// before their children.
+pub fn walk(a: &mut [Var]) -> Result<(), VmError> {
+    let root = arg_path(a, 0)?;
+    let filter = Filter {
+        depth: unsafe { a.get_unchecked(1) }
+            .as_u64()
+            .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?,
+        kinds: unsafe { a.get_unchecked(2) }
+            .as_u8()
+            .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?,
+        suffix: arg_path(a, 3)?,
+    };
+    let mut result = Vec::new();
+    let result =
+        walk_dir(Path::new(&root), 1, &filter, &mut result).map(|_| Var::Vector(result.into()));
+    put_result(a, result);
+    Ok(())
+}
+
 /// Read full content to a bytes.
-pub fn read_to_bytes(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn read_to_bytes(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    if let Some(x) = iohmgr::get(id) {
+        x.wait(READABLE)?;
+    }
     match iohmgr::get(id) {
         Some(x) => {
             let mut buf = Vec::new();
@@ -60,22 +310,25 @@ pub fn read_to_bytes(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Write bytes.
-pub fn write_bytes(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn write_bytes(a: &mut [Var]) -> Result<(), VmError> {
     use std::io::Write;
 
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    if let Some(x) = iohmgr::get(id) {
+        x.wait(WRITABLE)?;
+    }
     let content = match unsafe { a.get_unchecked(1) } {
         Var::Bytes(x) => x,
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     }
     .clone();
     let content = &content.borrow()?[..];
     match match &iohmgr::get(id) {
         Some(x) => match &**x {
             RawObject::LocalFile(y) => y,
-            _ => return Err(anyhow!("raw::fatal::segfault")),
+            _ => return Err(VmError::new("raw::fatal::segfault")),
         },
         None => {
             *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(io_error::OTHER);
@@ -95,25 +348,25 @@ pub fn write_bytes(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Close a file.
-pub fn close(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn close(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     iohmgr::del(id as u64);
     Ok(())
 }
 
 /// Open a file.
-pub fn open(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn open(a: &mut [Var]) -> Result<(), VmError> {
     use std::fs::OpenOptions;
 
     let path = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw:fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let path = path.borrow()?;
     let mode = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw:fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let mode = mode.borrow()?;
     let mut options = &mut OpenOptions::new();
     for i in mode.as_bytes().iter() {
@@ -142,13 +395,16 @@ pub fn open(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Read content with specified length to a bytes.
-pub fn read(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn read(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    if let Some(x) = iohmgr::get(id) {
+        x.wait(READABLE)?;
+    }
     let bufc = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     match iohmgr::get(id) {
         Some(x) => {
             let mut buf = vec![0u8; bufc];
@@ -179,10 +435,10 @@ pub fn read(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get file length.
-pub fn len(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn len(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     match iohmgr::get(id) {
         Some(x) => {
             match match &*x {
@@ -210,6 +466,229 @@ pub fn len(a: &mut [Var]) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Run an operation on the file handle at 0, and put its status and result. The file is cloned,
+/// so the handle table is not locked while the operation blocks.
+fn file_op(a: &mut [Var], f: impl FnOnce(&File) -> std::io::Result<Var>) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let file = match iohmgr::get(id).as_deref() {
+        Some(RawObject::LocalFile(x)) => x.try_clone(),
+        _ => Err(ErrorKind::Other.into()),
+    };
+    put_result(a, file.and_then(|x| f(&x)));
+    Ok(())
+}
+
+/// Get the offset argument at `i`.
+#[inline]
+fn arg_offset(a: &[Var], i: usize) -> Result<u64, VmError> {
+    unsafe { a.get_unchecked(i) }
+        .as_u64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Read bytes at an offset, which may be shorter than the requested length.
+#[cfg(unix)]
+#[inline]
+fn read_at_impl(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+/// Read bytes at an offset, which may be shorter than the requested length. This moves the
+/// cursor on Windows.
+#[cfg(windows)]
+#[inline]
+fn read_at_impl(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// Write all bytes at an offset.
+#[cfg(unix)]
+#[inline]
+fn write_at_impl(file: &File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+/// Write all bytes at an offset. This moves the cursor on Windows.
+#[cfg(windows)]
+fn write_at_impl(file: &File, mut buf: &[u8], mut offset: u64) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_write(file, buf, offset)? {
+            0 => return Err(ErrorKind::WriteZero.into()),
+            n => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Move the cursor of a file. The origin at 2 is 0 for the start, 1 for the current position and
+/// 2 for the end. Returns the new position from the start.
+pub fn seek(a: &mut [Var]) -> Result<(), VmError> {
+    let offset = unsafe { a.get_unchecked(1) }
+        .as_i64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let pos = match unsafe { a.get_unchecked(2) }.as_u8() {
+        Some(0) if offset >= 0 => SeekFrom::Start(offset as u64),
+        Some(0) => {
+            *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(io_error::INVALID_INPUT);
+            return Ok(());
+        }
+        Some(1) => SeekFrom::Current(offset),
+        Some(2) => SeekFrom::End(offset),
+        Some(_) => return Err(VmError::new("raw::fatal::out_of_range")),
+        None => return Err(VmError::new("raw::fatal::not_an_integer")),
+    };
+    file_op(a, |mut x| x.seek(pos).map(Var::U64))
+}
+
+/// Get the position of the cursor of a file.
+pub fn tell(a: &mut [Var]) -> Result<(), VmError> {
+    file_op(a, |mut x| x.stream_position().map(Var::U64))
+}
+
+/// Read content with specified length at an offset to a bytes, without moving the cursor on
+/// UNIX. Like `raw::fs::read`, the count of bytes read is returned at 2.
+pub fn read_at(a: &mut [Var]) -> Result<(), VmError> {
+    let len = unsafe { a.get_unchecked(1) }
+        .as_usize()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let offset = arg_offset(a, 2)?;
+    let mut count = 0;
+    file_op(a, |x| {
+        let mut buf = vec![0u8; len];
+        count = read_at_impl(x, &mut buf, offset)?;
+        buf.truncate(count);
+        Ok(Var::Bytes(buf.into()))
+    })?;
+    *(unsafe { a.get_unchecked_mut(2) }) = Var::U64(count as u64);
+    Ok(())
+}
+
+/// Write bytes at an offset, without moving the cursor on UNIX.
+pub fn write_at(a: &mut [Var]) -> Result<(), VmError> {
+    let content = match unsafe { a.get_unchecked(1) } {
+        Var::Bytes(x) => x.clone(),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
+    };
+    let offset = arg_offset(a, 2)?;
+    let content = content.borrow()?;
+    file_op(a, |x| {
+        write_at_impl(x, &content, offset).map(|_| Var::U64(0))
+    })
+}
+
+/// Truncate or extend a file to specified length. The cursor is not moved. Returns
+/// `INVALID_INPUT` if the file would be truncated below the length of a live mapping of it.
+pub fn set_len(a: &mut [Var]) -> Result<(), VmError> {
+    let len = arg_offset(a, 1)?;
+    file_op(a, |x| mapped::set_len(x, len).map(|_| Var::U64(0)))
+}
+
+/// Flush content and metadata of a file to the disk.
+pub fn sync_all(a: &mut [Var]) -> Result<(), VmError> {
+    file_op(a, |x| x.sync_all().map(|_| Var::U64(0)))
+}
+
+/// Flush content of a file to the disk.
+pub fn sync_data(a: &mut [Var]) -> Result<(), VmError> {
+    file_op(a, |x| x.sync_data().map(|_| Var::U64(0)))
+}
+
+/// Get the lock mode argument, which is 0 for an exclusive lock, or 1 for a shared lock.
+#[inline]
+fn arg_shared(a: &[Var]) -> Result<bool, VmError> {
+    match unsafe { a.get_unchecked(1) }.as_u8() {
+        Some(x) => Ok(x != 0),
+        None => Err(VmError::new("raw::fatal::not_an_integer")),
+    }
+}
+
+/// Take an advisory lock of a file, and block until it's available. The mode at 1 is 0 for an
+/// exclusive lock, or 1 for a shared lock. Locks belong to the file handle, and are released when
+/// it's closed. This blocks the OS thread even in coroutines.
+pub fn lock(a: &mut [Var]) -> Result<(), VmError> {
+    let shared = arg_shared(a)?;
+    file_op(a, |x| {
+        match shared {
+            true => x.lock_shared(),
+            false => x.lock(),
+        }
+        .map(|_| Var::U64(0))
+    })
+}
+
+/// Try to take an advisory lock of a file. Returns `WOULD_BLOCK` if it's held by others.
+pub fn try_lock(a: &mut [Var]) -> Result<(), VmError> {
+    let shared = arg_shared(a)?;
+    file_op(a, |x| {
+        match shared {
+            true => x.try_lock_shared(),
+            false => x.try_lock(),
+        }
+        .map(|_| Var::U64(0))
+        .map_err(|y| match y {
+            TryLockError::WouldBlock => ErrorKind::WouldBlock.into(),
+            TryLockError::Error(z) => z,
+        })
+    })
+}
+
+/// Release the advisory lock of a file.
+pub fn unlock(a: &mut [Var]) -> Result<(), VmError> {
+    file_op(a, |x| x.unlock().map(|_| Var::U64(0)))
+}
+
+/// Map a file handle to memory. If the flag at 1 is non-zero, the file is mapped for writing,
+/// and must be opened for reading and writing. Returns the mapping, which can be accessed like a
+/// bytes, at 1.
+pub fn mmap(a: &mut [Var]) -> Result<(), VmError> {
+    let writable = unsafe { a.get_unchecked(1) }
+        .as_u8()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    file_op(a, |x| MappedRef::map(x, writable != 0).map(Var::Mapped))
+}
+
+/// Get the mapping argument at 0.
+#[inline]
+fn arg_mapped(a: &[Var]) -> Result<MappedRef, VmError> {
+    match unsafe { a.get_unchecked(0) } {
+        Var::Mapped(x) => Ok(x.clone()),
+        _ => Err(VmError::new("raw::fatal::not_a_buf")),
+    }
+}
+
+/// Flush writes of a mapping to the file.
+pub fn mmap_flush(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_mapped(a)?.flush()?;
+    put_result(a, result.map(|_| Var::U64(0)));
+    Ok(())
+}
+
+/// Unmap a mapping. Writes which are not flushed are still written to the file eventually.
+pub fn mmap_unmap(a: &mut [Var]) -> Result<(), VmError> {
+    arg_mapped(a)?.unmap()
+}
+
+/// Copy a range of a mapping to a bytes. The range at 1 and 2 is the offset and the length, and
+/// is clamped to the mapping.
+pub fn mmap_read(a: &mut [Var]) -> Result<(), VmError> {
+    let mapped = arg_mapped(a)?;
+    let (offset, len) = (
+        unsafe { a.get_unchecked(1) }
+            .as_usize()
+            .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?,
+        unsafe { a.get_unchecked(2) }
+            .as_usize()
+            .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?,
+    );
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::Bytes(mapped.copy(offset, len)?.into());
+    Ok(())
+}
+
 impl_remove!(remove_file);
 impl_remove!(remove_dir_all);
 impl_remove!(remove_dir);
@@ -219,6 +698,8 @@ impl_remove!(create_dir_all);
 /// Initialize the library.
 #[inline(always)]
 pub fn init() {
+    #[cfg(target_os = "linux")]
+    watch::init();
     putnfp("raw::fs::open", open);
     putnfp("raw::fs::close", close);
     putnfp("raw::fs::len", len);
@@ -230,4 +711,28 @@ pub fn init() {
     putnfp("raw::fs::remove_seed", remove_dir);
     putnfp("raw::fs::mkseed", create_dir);
     putnfp("raw::fs::mktree", create_dir_all);
+    putnfp("raw::fs::seek", seek);
+    putnfp("raw::fs::tell", tell);
+    putnfp("raw::fs::read_at", read_at);
+    putnfp("raw::fs::write_at", write_at);
+    putnfp("raw::fs::set_len", set_len);
+    putnfp("raw::fs::sync_all", sync_all);
+    putnfp("raw::fs::sync_data", sync_data);
+    putnfp("raw::fs::lock", lock);
+    putnfp("raw::fs::try_lock", try_lock);
+    putnfp("raw::fs::unlock", unlock);
+    putnfp("raw::fs::mmap", mmap);
+    putnfp("raw::fs::mmap::flush", mmap_flush);
+    putnfp("raw::fs::mmap::unmap", mmap_unmap);
+    putnfp("raw::fs::mmap::read", mmap_read);
+    putnfp("raw::fs::list", list);
+    putnfp("raw::fs::stat", stat);
+    putnfp("raw::fs::lstat", lstat);
+    putnfp("raw::fs::rename", rename);
+    putnfp("raw::fs::copy", copy);
+    putnfp("raw::fs::symlink", symlink);
+    putnfp("raw::fs::hard_link", hard_link);
+    putnfp("raw::fs::read_link", read_link);
+    putnfp("raw::fs::canonicalize", canonicalize);
+    putnfp("raw::fs::walk", walk);
 }
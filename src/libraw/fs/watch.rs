@@ -0,0 +1,462 @@
+//!
+//! File system change notifications of `libraw`, backed by `inotify`. This is only available on
+//! Linux.
+//!
+//! A watcher is an I/O handle which watches some paths. A watched directory reports changes of its
+//! entries, and a recursive watch also watches subdirectories, including ones created later.
+//! Events are objects of type `raw::fs::event`, with `kind`, `path`, `from` and `dir` fields. The
+//! kind is one of `create`, `modify`, `delete`, `rename` and `overflow`; `from` is the old path
+//! of a rename, or null. An entry moved into a watched directory from elsewhere is reported as
+//! created, and one moved out is reported as deleted.
+//!
+//! Events are delivered by reading the watcher, or to a callback which is called on a thread
+//! named "fs-watch" for each event. A watcher with a callback shouldn't be read, because events
+//! are delivered only once.
+//!
+
+use crate::{
+    context::{getfp, putnfp, ExecUnit, Thread},
+    error::VmError,
+    executor::{self, reactor::READABLE},
+    isa::{FuncPtr, VirtFuncPtr},
+    libraw::iohmgr::{self, error as io_error, RawObject},
+    vmem::{CreateNull, ObjectRef, StringRef, Var},
+};
+use libc::{c_int, inotify_event};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    ffi::{CString, OsStr},
+    io::ErrorKind,
+    mem::size_of,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+/// Events every watch is registered for.
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_MODIFY
+    | libc::IN_DELETE
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO;
+
+/// A watch.
+struct Watch {
+    path: PathBuf,
+    recursive: bool,
+    /// If this watch is added by the user, rather than for a subdirectory.
+    root: bool,
+}
+
+/// A change.
+struct Event {
+    kind: &'static str,
+    path: Option<PathBuf>,
+    from: Option<PathBuf>,
+    dir: bool,
+}
+impl Event {
+    /// Convert this to an object of type `raw::fs::event`.
+    fn to_var(&self) -> Result<Var, VmError> {
+        let path = |x: &Option<PathBuf>| {
+            Var::UString(match x {
+                Some(y) => y.to_string_lossy().as_ref().into(),
+                None => StringRef::null(),
+            })
+        };
+        let result = ObjectRef::new("raw::fs::event");
+        result.set("kind", Var::UString(self.kind.into()))?;
+        result.set("path", path(&self.path))?;
+        result.set("from", path(&self.from))?;
+        result.set("dir", Var::U8(self.dir as u8))?;
+        Ok(Var::Object(result))
+    }
+}
+
+/// The `inotify` instance of a watcher.
+struct Inner {
+    fd: c_int,
+    watches: Mutex<HashMap<c_int, Watch>>,
+}
+impl Drop for Inner {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+impl Inner {
+    /// Watch a path. If `recursive` is `true`, subdirectories are watched too.
+    fn add(&self, path: &Path, recursive: bool, root: bool) -> std::io::Result<()> {
+        let cpath = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| std::io::Error::from(ErrorKind::InvalidInput))?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd, cpath.as_ptr(), MASK) };
+        if wd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        self.watches.lock().insert(
+            wd,
+            Watch {
+                path: path.to_owned(),
+                recursive,
+                root,
+            },
+        );
+        if recursive {
+            if let Ok(x) = std::fs::read_dir(path) {
+                for i in x.flatten() {
+                    if i.file_type().is_ok_and(|y| y.is_dir()) {
+                        // Subdirectories which can't be watched are skipped.
+                        self.add(&i.path(), true, false).ok();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Stop watching a path and paths under it. Returns `false` if the path is not watched.
+    fn remove(&self, path: &Path) -> bool {
+        let mut watches = self.watches.lock();
+        let wds: Vec<c_int> = watches
+            .iter()
+            .filter(|(_, x)| x.path.starts_with(path))
+            .map(|(x, _)| *x)
+            .collect();
+        for i in wds.iter() {
+            unsafe { libc::inotify_rm_watch(self.fd, *i) };
+            watches.remove(i);
+        }
+        !wds.is_empty()
+    }
+    /// Read changes, waiting at most `timeout`, or forever if it's `None`. Returns an empty vector
+    /// if it has timed out.
+    fn read(&self, timeout: Option<Duration>) -> std::io::Result<Vec<Event>> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |x| x.as_millis().min(c_int::MAX as u128) as c_int);
+        match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+            0 => return Ok(Vec::new()),
+            x if x < 0 => {
+                let e = std::io::Error::last_os_error();
+                return match e.kind() {
+                    ErrorKind::Interrupted => Ok(Vec::new()),
+                    _ => Err(e),
+                };
+            }
+            _ => (),
+        }
+        let mut buf = vec![0u8; 64 * (size_of::<inotify_event>() + 256)];
+        let n = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+        if n < 0 {
+            let e = std::io::Error::last_os_error();
+            return match e.kind() {
+                ErrorKind::WouldBlock | ErrorKind::Interrupted => Ok(Vec::new()),
+                _ => Err(e),
+            };
+        }
+        Ok(self.parse(&buf[..n as usize]))
+    }
+    /// Parse raw events, and update watches for created, moved and deleted directories.
+    fn parse(&self, mut buf: &[u8]) -> Vec<Event> {
+        let mut events = Vec::new();
+        // Indices of events of entries moved out, by cookies.
+        let mut moves: HashMap<u32, usize> = HashMap::new();
+        let mut created = Vec::new();
+        while buf.len() >= size_of::<inotify_event>() {
+            let raw = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const inotify_event) };
+            let end = (size_of::<inotify_event>() + raw.len as usize).min(buf.len());
+            let name = &buf[size_of::<inotify_event>()..end];
+            let name = &name[..name.iter().position(|x| *x == 0).unwrap_or(name.len())];
+            buf = &buf[end..];
+            if raw.mask & libc::IN_Q_OVERFLOW != 0 {
+                events.push(Event {
+                    kind: "overflow",
+                    path: None,
+                    from: None,
+                    dir: false,
+                });
+                continue;
+            }
+            let mut watches = self.watches.lock();
+            if raw.mask & libc::IN_IGNORED != 0 {
+                watches.remove(&raw.wd);
+                continue;
+            }
+            let watch = match watches.get(&raw.wd) {
+                Some(x) => x,
+                None => continue,
+            };
+            let path = match name.is_empty() {
+                true => watch.path.clone(),
+                false => watch.path.join(OsStr::from_bytes(name)),
+            };
+            let dir = raw.mask & libc::IN_ISDIR != 0;
+            let kind = if raw.mask & libc::IN_CREATE != 0 {
+                if dir && watch.recursive {
+                    created.push(path.clone());
+                }
+                "create"
+            } else if raw.mask & libc::IN_MODIFY != 0 {
+                "modify"
+            } else if raw.mask & libc::IN_DELETE != 0 {
+                "delete"
+            } else if raw.mask & libc::IN_DELETE_SELF != 0 {
+                // Deletion of subdirectories is reported by their parents.
+                match watch.root {
+                    true => "delete",
+                    false => continue,
+                }
+            } else if raw.mask & libc::IN_MOVED_FROM != 0 {
+                moves.insert(raw.cookie, events.len());
+                "delete"
+            } else if raw.mask & libc::IN_MOVED_TO != 0 {
+                if let Some(i) = moves.remove(&raw.cookie) {
+                    let from = events[i].path.take();
+                    if dir {
+                        if let Some(x) = &from {
+                            for y in watches.values_mut() {
+                                if let Ok(z) = y.path.strip_prefix(x) {
+                                    y.path = path.join(z);
+                                }
+                            }
+                        }
+                    }
+                    events[i] = Event {
+                        kind: "rename",
+                        path: Some(path),
+                        from,
+                        dir,
+                    };
+                    continue;
+                }
+                if dir && watch.recursive {
+                    created.push(path.clone());
+                }
+                "create"
+            } else {
+                continue;
+            };
+            events.push(Event {
+                kind,
+                path: Some(path),
+                from: None,
+                dir,
+            });
+        }
+        // Directories moved out are not watched anymore.
+        for i in moves.into_values() {
+            if let Event {
+                path: Some(x),
+                dir: true,
+                ..
+            } = &events[i]
+            {
+                self.remove(x);
+            }
+        }
+        for i in created {
+            self.add(&i, true, false).ok();
+        }
+        events
+    }
+}
+
+/// A watcher.
+#[derive(Clone)]
+pub struct Watcher(Arc<Inner>);
+impl Watcher {
+    /// Create a watcher.
+    fn new() -> std::io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self(Arc::new(Inner {
+            fd,
+            watches: Mutex::new(HashMap::new()),
+        })))
+    }
+    /// Get the file descriptor.
+    #[inline]
+    pub fn fd(&self) -> c_int {
+        self.0.fd
+    }
+}
+
+/// Get the watcher at 0. The watcher is cloned, so the handle table is not locked while it's
+/// being read.
+#[inline]
+fn watcher(a: &[Var]) -> Result<Option<Watcher>, VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    Ok(match iohmgr::get(id) {
+        Some(x) => match &*x {
+            RawObject::FsWatcher(y) => Some(y.clone()),
+            _ => None,
+        },
+        None => None,
+    })
+}
+
+/// Put a status.
+#[inline]
+fn put(a: &mut [Var], stat: u64) {
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(stat);
+}
+
+/// Create a watcher. Returns the handle at 1.
+pub fn new(a: &mut [Var]) -> Result<(), VmError> {
+    match Watcher::new() {
+        Ok(x) => {
+            put(a, 0);
+            *(unsafe { a.get_unchecked_mut(1) }) = Var::U64(iohmgr::add(RawObject::FsWatcher(x)));
+        }
+        Err(x) => put(a, io_error::from(x.kind())),
+    }
+    Ok(())
+}
+
+/// Watch a path. If the flag at 2 is non-zero, subdirectories are watched too.
+pub fn add(a: &mut [Var]) -> Result<(), VmError> {
+    let path = unsafe { a.get_unchecked(1) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let path = PathBuf::from(&path.borrow()?[..]);
+    let recursive = unsafe { a.get_unchecked(2) }
+        .as_u8()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let stat = match watcher(a)? {
+        Some(x) => match x.0.add(&path, recursive != 0, true) {
+            Ok(()) => 0,
+            Err(y) => io_error::from(y.kind()),
+        },
+        None => io_error::OTHER,
+    };
+    put(a, stat);
+    Ok(())
+}
+
+/// Stop watching a path and paths under it.
+pub fn remove(a: &mut [Var]) -> Result<(), VmError> {
+    let path = unsafe { a.get_unchecked(1) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let path = PathBuf::from(&path.borrow()?[..]);
+    let stat = match watcher(a)? {
+        Some(x) if x.0.remove(&path) => 0,
+        Some(_) => io_error::NOT_FOUND,
+        None => io_error::OTHER,
+    };
+    put(a, stat);
+    Ok(())
+}
+
+/// Read events, blocking until there are some, or the timeout in milliseconds at 1 is reached,
+/// where 0 means no timeout. Returns a vector of events at 1, or `TIMED_OUT`. In coroutine mode,
+/// only the current coroutine waits, and the timeout is ignored.
+pub fn read(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let deadline = match unsafe { a.get_unchecked(1) }
+        .as_u64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?
+    {
+        0 => None,
+        x => Some(Instant::now() + Duration::from_millis(x)),
+    };
+    let x = match watcher(a)? {
+        Some(x) => x,
+        None => {
+            put(a, io_error::OTHER);
+            return Ok(());
+        }
+    };
+    loop {
+        if let Some(y) = iohmgr::get(id) {
+            y.wait(READABLE)?;
+        }
+        let timeout = deadline.map(|y| y.saturating_duration_since(Instant::now()));
+        match x.0.read(timeout) {
+            // Events may all be filtered out, such as when a watch is removed, so wait again until
+            // the deadline.
+            Ok(y) if y.is_empty() => match deadline {
+                Some(z) if Instant::now() >= z => put(a, io_error::TIMED_OUT),
+                _ => continue,
+            },
+            Ok(y) => {
+                let events = y.iter().map(Event::to_var).collect::<Result<Vec<_>, _>>()?;
+                put(a, 0);
+                *(unsafe { a.get_unchecked_mut(1) }) = Var::Vector(events.into());
+            }
+            Err(y) => put(a, io_error::from(y.kind())),
+        }
+        return Ok(());
+    }
+}
+
+/// Call a function by name with each event at register 100, on a new thread. The thread stops
+/// after the watcher is closed.
+pub fn callback(a: &mut [Var]) -> Result<(), VmError> {
+    let fp = unsafe { a.get_unchecked(1) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let fp = match getfp(&fp.borrow()?) {
+        Some(FuncPtr::Virtual(x)) => x,
+        _ => return Err(VmError::new("raw::fatal::segfault")),
+    };
+    let weak = match watcher(a)? {
+        Some(x) => Arc::downgrade(&x.0),
+        None => {
+            put(a, io_error::OTHER);
+            return Ok(());
+        }
+    };
+    let result = std::thread::Builder::new()
+        .name("fs-watch".to_owned())
+        .spawn(move || deliver(weak, fp));
+    put(
+        a,
+        match result {
+            Ok(_) => 0,
+            Err(x) => io_error::from(x.kind()),
+        },
+    );
+    Ok(())
+}
+
+/// Deliver events to a callback until the watcher is closed.
+fn deliver(weak: Weak<Inner>, fp: VirtFuncPtr) {
+    // The watcher is upgraded only while it's polled, so closing the handle drops it.
+    while let Some(inner) = weak.upgrade() {
+        let events = match inner.read(Some(Duration::from_millis(200))) {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        drop(inner);
+        for i in events {
+            let event = match i.to_var() {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let mut thread = Thread::new(fp.clone());
+            thread.sset(100, event).ok();
+            executor::start_thread(thread).ok();
+        }
+    }
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::fs::watch::new", new);
+    putnfp("raw::fs::watch::add", add);
+    putnfp("raw::fs::watch::remove", remove);
+    putnfp("raw::fs::watch::read", read);
+    putnfp("raw::fs::watch::callback", callback);
+}
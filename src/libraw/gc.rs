@@ -0,0 +1,34 @@
+//!
+//! Garbage collector controlling library of `libraw`.
+//!
+
+use crate::{
+    context::putnfp,
+    error::VmError,
+    vmem::{gc, ObjectRef, Var},
+};
+
+/// Collect garbage cycles.
+pub fn collect(a: &mut [Var]) -> Result<(), VmError> {
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(gc::collect());
+    Ok(())
+}
+
+/// Get statistics of the heap.
+pub fn stats(a: &mut [Var]) -> Result<(), VmError> {
+    let stats = gc::stats();
+    let result = ObjectRef::new("raw::gc::stats");
+    result.set("objects", Var::U64(stats.objects))?;
+    result.set("vectors", Var::U64(stats.vectors))?;
+    result.set("collections", Var::U64(stats.collections))?;
+    result.set("collected", Var::U64(stats.collected))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::Object(result);
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::gc::collect", collect);
+    putnfp("raw::gc::stats", stats);
+}
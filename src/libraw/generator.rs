@@ -0,0 +1,147 @@
+//!
+//! Generators of `libraw`.
+//!
+//! A generator is a thread created from a function and its arguments, which is executed only when
+//! it's resumed. The generator body yields a value with `~ raw::gen::yield <register>`, then the
+//! `raw::gen::resume` call that resumed it returns the value, and the body is suspended until it's
+//! resumed again.
+//!
+
+use crate::{
+    context::{getfp, putnfp, ExecUnit, Thread},
+    error::VmError,
+    executor,
+    isa::FuncPtr,
+    libraw::{
+        iohmgr::{FakeHasher, IdGen},
+        thread::sync,
+    },
+    vmem::Var,
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use parking_lot::{const_mutex, Mutex};
+
+/// State of a generator.
+enum Generator {
+    /// The generator is suspended, and can be resumed.
+    Suspended(Box<Thread>),
+    /// The generator is being resumed.
+    Running,
+    /// The generator has finished.
+    Done,
+}
+
+/// ID Generator for generators.
+static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
+/// Table of generators.
+static GENERATORS: Lazy<DashMap<u64, Generator, FakeHasher>> = Lazy::new(DashMap::default);
+
+/// Create a generator from a function name and a vector of arguments.
+pub fn new(a: &mut [Var]) -> Result<(), VmError> {
+    let fp = unsafe { a.get_unchecked(0) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let fp = fp.borrow()?;
+    let mut thread = Thread::new(match getfp(&fp) {
+        Some(FuncPtr::Virtual(x)) => x,
+        _ => return Err(VmError::new("raw::fatal::segfault")),
+    });
+    match unsafe { a.get_unchecked(1) } {
+        Var::Vector(x) => {
+            let args = x.borrow()?;
+            if args.len() > 50 {
+                return Err(VmError::new("raw::fatal::segfault"));
+            }
+            for (i, y) in args.iter().enumerate() {
+                thread.sset(100 + i, y.to_owned())?;
+            }
+        }
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
+    }
+    let id = IDGEN.lock().next();
+    GENERATORS.insert(id, Generator::Suspended(Box::new(thread)));
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
+    Ok(())
+}
+
+/// Resume a generator. If it yields a value, 1 and the value are returned. If it finishes, 0 and
+/// its return register 100 are returned. If it has finished or doesn't exist, 0 is returned.
+pub fn resume(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let mut thread = match GENERATORS.get_mut(&id) {
+        Some(mut x) => match std::mem::replace(&mut *x, Generator::Running) {
+            Generator::Suspended(y) => y,
+            Generator::Running => return Err(VmError::new("raw::gen::already_running")),
+            Generator::Done => {
+                *x = Generator::Done;
+                *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(0);
+                return Ok(());
+            }
+        },
+        None => {
+            *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(0);
+            return Ok(());
+        }
+    };
+    let (state, result) = match executor::start_gen(&mut thread) {
+        Ok(Some(x)) => {
+            *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(1);
+            *(unsafe { a.get_unchecked_mut(1) }) = x;
+            (Generator::Suspended(thread), Ok(()))
+        }
+        Ok(None) => {
+            *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(0);
+            *(unsafe { a.get_unchecked_mut(1) }) = thread.sget(100)?.to_owned();
+            (Generator::Done, Ok(()))
+        }
+        Err(x) => (Generator::Done, Err(x)),
+    };
+    // The generator may have been dropped while it was running, then a suspended generator is
+    // dropped here.
+    match GENERATORS.get_mut(&id) {
+        Some(mut x) => *x = state,
+        None => {
+            if let Generator::Suspended(x) = state {
+                sync::release(x.owner());
+            }
+        }
+    }
+    result
+}
+
+/// Judge if a generator has finished. A generator that doesn't exist has finished.
+pub fn is_done(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let done = !matches!(
+        GENERATORS.get(&id).as_deref(),
+        Some(Generator::Suspended(_) | Generator::Running)
+    );
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(done as u8);
+    Ok(())
+}
+
+/// Drop a generator. Locks held by a suspended generator are released.
+pub fn drop(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    if let Some((_, Generator::Suspended(x))) = GENERATORS.remove(&id) {
+        sync::release(x.owner());
+    }
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    IDGEN.lock().next();
+    putnfp("raw::gen::new", new);
+    putnfp("raw::gen::resume", resume);
+    putnfp("raw::gen::is_done", is_done);
+    putnfp("raw::gen::drop", drop);
+}
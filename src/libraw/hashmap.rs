@@ -4,32 +4,40 @@
 
 use crate::{
     context::putnfp,
+    error::VmError,
     libraw::iohmgr::{FakeHasher, IdGen},
-    vmem::Var,
+    vmem::{
+        mem::{Accounted, Kind},
+        Var,
+    },
 };
-use anyhow::anyhow;
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use parking_lot::{const_mutex, Mutex};
+use std::mem::size_of;
+
+/// A hashmap.
+type HashMap = Accounted<DashMap<Var, Var, ahash::RandomState>>;
 
 /// Id generator for hashmaps.
 static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
 /// Table of hashmaps.
-static TABLE: Lazy<DashMap<u64, DashMap<Var, Var, ahash::RandomState>, FakeHasher>> =
-    Lazy::new(DashMap::default);
+static TABLE: Lazy<DashMap<u64, HashMap, FakeHasher>> = Lazy::new(DashMap::default);
+/// Estimated size of an entry in a hashmap.
+const ENTRY_SIZE: usize = size_of::<(Var, Var)>();
 
 /// Make a new one.
-pub fn new(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn new(a: &mut [Var]) -> Result<(), VmError> {
     let id = IDGEN.lock().next();
-    TABLE.insert(id, DashMap::default());
+    TABLE.insert(id, Accounted::new(Kind::HashMap, DashMap::default(), 0));
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
     Ok(())
 }
 /// Drop a map.
-pub fn drop(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn drop(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     if TABLE.remove(&id).is_some() {
         IDGEN.lock().free(id);
     }
@@ -37,10 +45,10 @@ pub fn drop(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get a value from a map.
-pub fn get(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn get(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let key = unsafe { a.get_unchecked(1) }.to_owned();
     let map = match TABLE.get(&id) {
         Some(x) => x,
@@ -62,13 +70,13 @@ pub fn get(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get a vector that contains all keys of a hashmap.
-pub fn keys(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn keys(a: &mut [Var]) -> Result<(), VmError> {
     use crate::vmem::VectorRef;
     use rayon::prelude::*;
 
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let map = match TABLE.get(&id) {
         Some(x) => x,
         None => {
@@ -86,10 +94,10 @@ pub fn keys(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Set a value to a map.
-pub fn set(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn set(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let key = unsafe { a.get_unchecked(1) }.to_owned();
     let val = unsafe { a.get_unchecked(2) }.to_owned();
     let map = match TABLE.get(&id) {
@@ -99,16 +107,18 @@ pub fn set(a: &mut [Var]) -> Result<(), anyhow::Error> {
             return Ok(());
         }
     };
-    map.insert(key, val);
+    if map.insert(key, val).is_none() {
+        map.grow(ENTRY_SIZE);
+    }
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(1);
     Ok(())
 }
 
 /// Remove a value from a map.
-pub fn remove(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn remove(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let key = unsafe { a.get_unchecked(1) };
     let map = match TABLE.get(&id) {
         Some(x) => x,
@@ -119,6 +129,7 @@ pub fn remove(a: &mut [Var]) -> Result<(), anyhow::Error> {
     };
     match map.remove(key) {
         Some(_) => {
+            map.shrink(ENTRY_SIZE);
             *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(1);
         }
         None => {
@@ -129,10 +140,10 @@ pub fn remove(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Clear a map.
-pub fn clear(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn clear(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let map = match TABLE.get(&id) {
         Some(x) => x,
         None => {
@@ -141,6 +152,7 @@ pub fn clear(a: &mut [Var]) -> Result<(), anyhow::Error> {
         }
     };
     map.clear();
+    map.resize(0);
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(1);
     Ok(())
 }
@@ -4,29 +4,29 @@
 
 use crate::{
     context::{intabort, putnfp},
+    error::VmError,
     vmem::Var,
 };
-use anyhow::anyhow;
 
-pub fn ign(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn ign(a: &mut [Var]) -> Result<(), VmError> {
     use crate::context::intignore;
 
     intignore(
         &*unsafe { a.get_unchecked(0) }
             .as_sr()
-            .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?
+            .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
             .borrow()?,
     );
     Ok(())
 }
-pub fn abrt(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn abrt(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let id = id.borrow()?;
     let msg = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let msg = match msg.borrow() {
         Ok(x) => Some(x.to_owned()),
         Err(_) => None,
@@ -34,7 +34,7 @@ pub fn abrt(a: &mut [Var]) -> Result<(), anyhow::Error> {
     intabort(&id[..], msg);
     Ok(())
 }
-pub fn intcatch(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn intcatch(a: &mut [Var]) -> Result<(), VmError> {
     use crate::{
         context::{getfp, intcatch as catchcore},
         isa::FuncPtr,
@@ -42,15 +42,16 @@ pub fn intcatch(a: &mut [Var]) -> Result<(), anyhow::Error> {
 
     let id = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let id = id.borrow()?;
     let fp = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
-    let fp = match getfp(&fp.borrow()?[..]).ok_or_else(|| anyhow!("raw::fatal::no_such_func"))? {
-        FuncPtr::Virtual(x) => x,
-        _ => return Err(anyhow!("raw::fatal::segfault")),
-    };
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let fp =
+        match getfp(&fp.borrow()?[..]).ok_or_else(|| VmError::new("raw::fatal::no_such_func"))? {
+            FuncPtr::Virtual(x) => x,
+            _ => return Err(VmError::new("raw::fatal::segfault")),
+        };
     catchcore(&id[..], fp);
     Ok(())
 }
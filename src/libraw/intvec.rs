@@ -4,26 +4,32 @@
 
 use crate::{
     context::putnfp,
+    error::VmError,
     libraw::iohmgr::{FakeHasher, IdGen},
-    vmem::Var,
+    vmem::{
+        mem::{Accounted, Kind},
+        Var,
+    },
 };
-use anyhow::anyhow;
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use parking_lot::{const_mutex, Mutex, RwLock};
 use rayon::prelude::*;
 
+/// An int vector.
+type IntVec = Accounted<RwLock<Vec<i64>>>;
+
 /// ID Generator for deques.
 static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
 /// Table of deques.
-static DEQUES: Lazy<DashMap<u64, RwLock<Vec<i64>>, FakeHasher>> = Lazy::new(DashMap::default);
+static DEQUES: Lazy<DashMap<u64, IntVec, FakeHasher>> = Lazy::new(DashMap::default);
 
 macro_rules! impl_pop {
     ($a: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let id = unsafe { a.get_unchecked(0) }
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
             let deque = match DEQUES.get(&id) {
                 Some(x) => x,
                 None => unsafe {
@@ -47,13 +53,13 @@ macro_rules! impl_pop {
 
 macro_rules! impl_push {
     ($a: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let id = unsafe { a.get_unchecked(0) }
                 .as_u64_strict()
-                .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
             let val = unsafe { a.get_unchecked(1) }
                 .as_i64()
-                .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
             let deque = match DEQUES.get(&id) {
                 Some(x) => x,
                 None => unsafe {
@@ -69,18 +75,18 @@ macro_rules! impl_push {
 }
 
 /// Create a new one.
-pub fn new(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn new(a: &mut [Var]) -> Result<(), VmError> {
     let id = IDGEN.lock().next();
-    DEQUES.insert(id, RwLock::new(Vec::new()));
+    DEQUES.insert(id, Accounted::measured(Kind::IntVec, Vec::new()));
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
     Ok(())
 }
 
 /// Drop a deque.
-pub fn drop(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn drop(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     if DEQUES.remove(&id).is_some() {
         IDGEN.lock().free(id);
     }
@@ -88,13 +94,13 @@ pub fn drop(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get a value.
-pub fn get(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn get(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -115,16 +121,16 @@ pub fn get(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Set a value.
-pub fn set(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn set(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let val = unsafe { a.get_unchecked(2) }
         .as_i64()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -145,24 +151,24 @@ pub fn set(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Swap values.
-pub fn swap(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn swap(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let index1 = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let index2 = unsafe { a.get_unchecked(2) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     let mut deque = deque.write();
     let len = deque.len();
     if index1 >= len || index2 >= len {
-        Err(anyhow!("raw::fatal::out_of_range"))
+        Err(VmError::new("raw::fatal::out_of_range"))
     } else {
         deque.swap(index1, index2);
         Ok(())
@@ -170,13 +176,13 @@ pub fn swap(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Truncate.
-pub fn truncate(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn truncate(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let newlen = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -190,10 +196,10 @@ pub fn truncate(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get length.
-pub fn len(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn len(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -207,10 +213,10 @@ pub fn len(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Clear.
-pub fn clear(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn clear(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let deque = match DEQUES.get(&id) {
         Some(x) => x,
         None => unsafe {
@@ -224,23 +230,23 @@ pub fn clear(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Insert.
-pub fn insert(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn insert(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        _ => return Err(anyhow!("raw::fatal::segfault")),
+        _ => return Err(VmError::new("raw::fatal::segfault")),
     };
     let mut cur = cur.write();
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let val = unsafe { a.get_unchecked(2) }
         .as_i64()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     if index > cur.len() {
-        Err(anyhow!("raw::fatal::out_of_range"))
+        Err(VmError::new("raw::fatal::out_of_range"))
     } else {
         cur.insert(index, val);
         Ok(())
@@ -248,40 +254,40 @@ pub fn insert(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Remove an element from the deque.
-pub fn remove(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn remove(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     let mut cur = cur.write();
 
     let index = unsafe { a.get_unchecked(1) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     if index >= cur.len() {
-        return Err(anyhow!("raw::fatal::out_of_range"));
+        return Err(VmError::new("raw::fatal::out_of_range"));
     }
     cur.remove(index);
     Ok(())
 }
 
 /// Append elements.
-pub fn append(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn append(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     match unsafe { a.get_unchecked(1) } {
         Var::U64(x) => {
             let ndeq = match DEQUES.get(x) {
                 Some(x) => x,
-                None => return Err(anyhow!("raw::fatal::segfault")),
+                None => return Err(VmError::new("raw::fatal::segfault")),
             };
             ndeq.read()
                 .par_iter()
@@ -294,47 +300,47 @@ pub fn append(a: &mut [Var]) -> Result<(), anyhow::Error> {
                 .for_each(|x| cur.write().push(*x as i64));
             Ok(())
         }
-        _ => Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => Err(VmError::new("raw::fatal::not_a_buf")),
     }
 }
 
 /// Content containing test.
-pub fn contains(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn contains(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let val = unsafe { a.get_unchecked(1) }
         .as_i64()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(cur.read().contains(&val) as u8);
     Ok(())
 }
 
 /// Timsort.
-pub fn sort_tim(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn sort_tim(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     cur.write().par_sort();
     Ok(())
 }
 
 /// Quicksort.
-pub fn sort_uns(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn sort_uns(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let cur = match DEQUES.get(&id) {
         Some(x) => x,
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     };
     cur.write().par_sort_unstable();
     Ok(())
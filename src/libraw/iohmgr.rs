@@ -3,6 +3,14 @@
 //! handlers. This is for all `libraw`'s IO modules: local filesystem, socket, etc.
 //!
 
+use crate::{
+    error::VmError,
+    executor::reactor,
+    vmem::{
+        mem::{self, Kind},
+        Var,
+    },
+};
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use parking_lot::{const_mutex, Mutex};
@@ -10,6 +18,7 @@ use smallvec::SmallVec;
 use std::{
     collections::HashSet,
     hash::{BuildHasher, Hasher},
+    mem::size_of,
     ops::Deref,
 };
 
@@ -217,13 +226,55 @@ impl CeIdGen {
 
 pub enum RawObject {
     LocalFile(std::fs::File),
-    Thread(std::thread::JoinHandle<()>),
+    Thread(std::thread::JoinHandle<Result<Vec<Var>, VmError>>),
+    TcpListener(std::net::TcpListener),
+    TcpStream(std::net::TcpStream),
+    UdpSocket(std::net::UdpSocket),
+    #[cfg(unix)]
+    UnixListener(std::os::unix::net::UnixListener),
+    #[cfg(unix)]
+    UnixStream(std::os::unix::net::UnixStream),
+    #[cfg(unix)]
+    UnixDatagram(std::os::unix::net::UnixDatagram),
+    #[cfg(target_os = "linux")]
+    FsWatcher(crate::libraw::fs::watch::Watcher),
+}
+impl RawObject {
+    /// In coroutine mode, throw `raw::coro::wait_io` if this is not ready for `interest`. See
+    /// `executor::reactor` for details.
+    #[inline]
+    pub fn wait(&self, interest: u64) -> Result<(), VmError> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let fd = match self {
+                Self::LocalFile(x) => x.as_raw_fd(),
+                Self::TcpListener(x) => x.as_raw_fd(),
+                Self::TcpStream(x) => x.as_raw_fd(),
+                Self::UdpSocket(x) => x.as_raw_fd(),
+                Self::UnixListener(x) => x.as_raw_fd(),
+                Self::UnixStream(x) => x.as_raw_fd(),
+                Self::UnixDatagram(x) => x.as_raw_fd(),
+                #[cfg(target_os = "linux")]
+                Self::FsWatcher(x) => x.fd(),
+                Self::Thread(_) => return Ok(()),
+            };
+            reactor::wait(fd, interest)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = interest;
+            Ok(())
+        }
+    }
 }
 
 /// Open a handler.
 pub fn add(obj: RawObject) -> u64 {
     let id = GLOBAL_IDGEN.lock().next();
     OBJECTS.insert(id, obj);
+    mem::charge(Kind::IoHandle, size_of::<RawObject>());
     id
 }
 
@@ -245,6 +296,7 @@ pub fn take(id: u64) -> Option<RawObject> {
     match OBJECTS.remove(&id) {
         Some((_, x)) => {
             GLOBAL_IDGEN.lock().free(id);
+            mem::uncharge(Kind::IoHandle, size_of::<RawObject>());
             Some(x)
         }
         None => None,
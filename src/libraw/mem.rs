@@ -0,0 +1,48 @@
+//!
+//! Memory accounting library of `libraw`.
+//!
+
+use crate::{
+    context::putnfp,
+    error::VmError,
+    vmem::{
+        mem::{self, Kind},
+        ObjectRef, Var,
+    },
+};
+
+/// Get the memory usage. The result has a `total` field, a `limit` field which is 0 if the memory
+/// is unlimited, and a field for each kind of memory.
+pub fn usage(a: &mut [Var]) -> Result<(), VmError> {
+    let result = ObjectRef::new("raw::mem::usage");
+    for i in Kind::ALL {
+        result.set(i.name(), Var::U64(mem::usage_of(i) as u64))?;
+    }
+    let limit = match mem::limit() {
+        usize::MAX => 0,
+        x => x as u64,
+    };
+    result.set("limit", Var::U64(limit))?;
+    result.set("total", Var::U64(mem::usage() as u64))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::Object(result);
+    Ok(())
+}
+
+/// Set the memory limit in bytes. 0 means unlimited.
+pub fn set_limit(a: &mut [Var]) -> Result<(), VmError> {
+    let limit = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    mem::set_limit(match limit {
+        0 => usize::MAX,
+        x => x as usize,
+    });
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::mem::usage", usage);
+    putnfp("raw::mem::set_limit", set_limit);
+}
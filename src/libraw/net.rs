@@ -0,0 +1,78 @@
+//!
+//! Networking library of `libraw`. Sockets are stored in `iohmgr`, and every operation returns a
+//! status code of `iohmgr::error` at register 0, like `raw::fs::*`.
+//!
+
+pub mod tcp;
+pub mod udp;
+#[cfg(unix)]
+pub mod unix;
+
+use crate::{
+    error::VmError,
+    libraw::iohmgr::{self, error as io_error, RawObject},
+    vmem::Var,
+};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    ops::Deref,
+};
+
+/// Get the socket address at index `i` of `a`. A host name is resolved, and the first address is
+/// used.
+fn addr(a: &[Var], i: usize) -> Result<io::Result<SocketAddr>, VmError> {
+    let addr = unsafe { a.get_unchecked(i) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let addr = addr.borrow()?;
+    Ok(addr.to_socket_addrs().and_then(|mut x| {
+        x.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address resolved"))
+    }))
+}
+
+/// Get the I/O handler whose ID is at index 0 of `a`.
+fn handler(a: &[Var]) -> Result<Option<impl Deref<Target = RawObject>>, VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    Ok(iohmgr::get(id))
+}
+
+/// In coroutine mode, wait until the I/O handler whose ID is at register 0 is ready for
+/// `interest`.
+fn wait(a: &[Var], interest: u64) -> Result<(), VmError> {
+    match handler(a)?.as_deref() {
+        Some(x) => x.wait(interest),
+        None => Ok(()),
+    }
+}
+
+/// Write the status of `r` to register 0, and the result to register 1 if it succeeded.
+fn put<T>(a: &mut [Var], r: io::Result<T>, f: impl FnOnce(T) -> Var) {
+    match r {
+        Ok(x) => {
+            *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(0);
+            *(unsafe { a.get_unchecked_mut(1) }) = f(x);
+        }
+        Err(x) => *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(io_error::from(x.kind())),
+    }
+}
+
+/// Write the status of `r` to register 0.
+fn put_status(a: &mut [Var], r: io::Result<()>) {
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(match r {
+        Ok(()) => 0,
+        Err(x) => io_error::from(x.kind()),
+    });
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    tcp::init();
+    udp::init();
+    #[cfg(unix)]
+    unix::init();
+}
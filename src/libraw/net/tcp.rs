@@ -0,0 +1,165 @@
+//!
+//! TCP sockets of `libraw`.
+//!
+
+use super::{addr, handler, put, put_status, wait};
+use crate::{
+    context::putnfp,
+    error::VmError,
+    executor::reactor::{READABLE, WRITABLE},
+    libraw::iohmgr::{self, RawObject},
+    vmem::{StringRef, Var},
+};
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    time::Duration,
+};
+
+/// Run `f` on the TCP stream whose ID is at register 0. If there is no such stream, this fails
+/// with `io::ErrorKind::Other`. The stream is cloned, so the handle table is not locked while `f`
+/// blocks.
+fn on_stream<T>(
+    a: &[Var],
+    f: impl FnOnce(&TcpStream) -> io::Result<T>,
+) -> Result<io::Result<T>, VmError> {
+    let stream = match handler(a)?.as_deref() {
+        Some(RawObject::TcpStream(x)) => x.try_clone(),
+        _ => Err(io::ErrorKind::Other.into()),
+    };
+    Ok(stream.and_then(|x| f(&x)))
+}
+
+/// Listen on an address.
+pub fn listen(a: &mut [Var]) -> Result<(), VmError> {
+    let r = addr(a, 0)?.and_then(TcpListener::bind);
+    put(a, r, |x| Var::U64(iohmgr::add(RawObject::TcpListener(x))));
+    Ok(())
+}
+
+/// Accept a connection. The address of the peer is put at register 2.
+pub fn accept(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, READABLE)?;
+    let listener = match handler(a)?.as_deref() {
+        Some(RawObject::TcpListener(x)) => x.try_clone(),
+        _ => Err(io::ErrorKind::Other.into()),
+    };
+    let r = listener.and_then(|x| x.accept());
+    if let Ok((_, x)) = &r {
+        *(unsafe { a.get_unchecked_mut(2) }) = Var::UString(StringRef::from(x.to_string()));
+    }
+    put(a, r, |(x, _)| {
+        Var::U64(iohmgr::add(RawObject::TcpStream(x)))
+    });
+    Ok(())
+}
+
+/// Connect to an address. The timeout is in milliseconds, and 0 means no timeout.
+pub fn connect(a: &mut [Var]) -> Result<(), VmError> {
+    let timeout = unsafe { a.get_unchecked(1) }
+        .as_u64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let r = addr(a, 0)?.and_then(|x| match timeout {
+        0 => TcpStream::connect(x),
+        y => TcpStream::connect_timeout(&x, Duration::from_millis(y)),
+    });
+    put(a, r, |x| Var::U64(iohmgr::add(RawObject::TcpStream(x))));
+    Ok(())
+}
+
+/// Read at most specified length of content to a bytes. The count of read bytes is put at
+/// register 2, and 0 means the peer has closed the connection.
+pub fn read(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, READABLE)?;
+    let bufc = unsafe { a.get_unchecked(1) }
+        .as_usize()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let mut buf = vec![0u8; bufc];
+    let r = on_stream(a, |mut x| x.read(&mut buf))?;
+    if let Ok(x) = &r {
+        buf.truncate(*x);
+        *(unsafe { a.get_unchecked_mut(2) }) = Var::U64(*x as u64);
+    }
+    put(a, r, |_| Var::Bytes(buf.into()));
+    Ok(())
+}
+
+/// Write all of a bytes.
+pub fn write(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, WRITABLE)?;
+    let content = match unsafe { a.get_unchecked(1) } {
+        Var::Bytes(x) => x,
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
+    }
+    .clone();
+    let content = &content.borrow()?[..];
+    let r = on_stream(a, |mut x| x.write_all(content))?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Shut down the reading half(0), the writing half(1) or both halves(2) of a connection.
+pub fn shutdown(a: &mut [Var]) -> Result<(), VmError> {
+    let how = match unsafe { a.get_unchecked(1) }.as_u8() {
+        Some(0) => Shutdown::Read,
+        Some(1) => Shutdown::Write,
+        Some(2) => Shutdown::Both,
+        Some(_) => return Err(VmError::new("raw::fatal::invalid")),
+        None => return Err(VmError::new("raw::fatal::not_an_integer")),
+    };
+    let r = on_stream(a, |x| x.shutdown(how))?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Enable or disable `TCP_NODELAY`.
+pub fn set_nodelay(a: &mut [Var]) -> Result<(), VmError> {
+    let nodelay = unsafe { a.get_unchecked(1) }
+        .is_not_zero()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let r = on_stream(a, |x| x.set_nodelay(nodelay))?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Get the address of the peer.
+pub fn peer_addr(a: &mut [Var]) -> Result<(), VmError> {
+    let r = on_stream(a, TcpStream::peer_addr)?;
+    put(a, r, |x| Var::UString(StringRef::from(x.to_string())));
+    Ok(())
+}
+
+/// Get the local address of a stream or a listener.
+pub fn local_addr(a: &mut [Var]) -> Result<(), VmError> {
+    let r = match handler(a)?.as_deref() {
+        Some(RawObject::TcpStream(x)) => x.local_addr(),
+        Some(RawObject::TcpListener(x)) => x.local_addr(),
+        _ => Err(io::ErrorKind::Other.into()),
+    };
+    put(a, r, |x| Var::UString(StringRef::from(x.to_string())));
+    Ok(())
+}
+
+/// Close a stream or a listener.
+pub fn close(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    iohmgr::del(id);
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::net::tcp::listen", listen);
+    putnfp("raw::net::tcp::accept", accept);
+    putnfp("raw::net::tcp::connect", connect);
+    putnfp("raw::net::tcp::read", read);
+    putnfp("raw::net::tcp::write", write);
+    putnfp("raw::net::tcp::shutdown", shutdown);
+    putnfp("raw::net::tcp::set_nodelay", set_nodelay);
+    putnfp("raw::net::tcp::peer_addr", peer_addr);
+    putnfp("raw::net::tcp::local_addr", local_addr);
+    putnfp("raw::net::tcp::close", close);
+}
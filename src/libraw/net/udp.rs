@@ -0,0 +1,228 @@
+//!
+//! UDP sockets of `libraw`.
+//!
+
+use super::{addr, handler, put, put_status, wait};
+use crate::{
+    context::putnfp,
+    error::VmError,
+    executor::reactor::{READABLE, WRITABLE},
+    libraw::iohmgr::{self, RawObject},
+    vmem::{StringRef, Var},
+};
+use std::{
+    io,
+    net::{IpAddr, UdpSocket},
+};
+
+/// Run `f` on the UDP socket whose ID is at register 0. If there is no such socket, this fails
+/// with `io::ErrorKind::Other`. The socket is cloned, so the handle table is not locked while `f`
+/// blocks.
+fn on_socket<T>(
+    a: &[Var],
+    f: impl FnOnce(&UdpSocket) -> io::Result<T>,
+) -> Result<io::Result<T>, VmError> {
+    let socket = match handler(a)?.as_deref() {
+        Some(RawObject::UdpSocket(x)) => x.try_clone(),
+        _ => Err(io::ErrorKind::Other.into()),
+    };
+    Ok(socket.and_then(|x| f(&x)))
+}
+
+/// Get the IP address at index `i` of `a`.
+fn ip(a: &[Var], i: usize) -> Result<io::Result<IpAddr>, VmError> {
+    let ip = unsafe { a.get_unchecked(i) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let ip = ip.borrow()?;
+    Ok(ip.parse().map_err(|_| io::ErrorKind::InvalidInput.into()))
+}
+
+/// Get the bytes at index 1 of `a`.
+fn content(a: &[Var]) -> Result<Vec<u8>, VmError> {
+    match unsafe { a.get_unchecked(1) } {
+        Var::Bytes(x) => Ok(x.borrow()?.to_owned()),
+        _ => Err(VmError::new("raw::fatal::not_a_buf")),
+    }
+}
+
+/// Get the flag at index 1 of `a`.
+fn flag(a: &[Var]) -> Result<bool, VmError> {
+    unsafe { a.get_unchecked(1) }
+        .is_not_zero()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Bind a socket to an address.
+pub fn bind(a: &mut [Var]) -> Result<(), VmError> {
+    let r = addr(a, 0)?.and_then(UdpSocket::bind);
+    put(a, r, |x| Var::U64(iohmgr::add(RawObject::UdpSocket(x))));
+    Ok(())
+}
+
+/// Connect a socket to an address, so `raw::net::udp::send` and `raw::net::udp::recv` can be
+/// used, and datagrams from other addresses are dropped.
+pub fn connect(a: &mut [Var]) -> Result<(), VmError> {
+    let r = match addr(a, 1)? {
+        Ok(x) => on_socket(a, |y| y.connect(x))?,
+        Err(x) => Err(x),
+    };
+    put_status(a, r);
+    Ok(())
+}
+
+/// Send a datagram to an address. The count of sent bytes is put at register 1.
+pub fn send_to(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, WRITABLE)?;
+    let content = content(a)?;
+    let r = match addr(a, 2)? {
+        Ok(x) => on_socket(a, |y| y.send_to(&content, x))?,
+        Err(x) => Err(x),
+    };
+    put(a, r, |x| Var::U64(x as u64));
+    Ok(())
+}
+
+/// Send a datagram to the connected address. The count of sent bytes is put at register 1.
+pub fn send(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, WRITABLE)?;
+    let content = content(a)?;
+    let r = on_socket(a, |x| x.send(&content))?;
+    put(a, r, |x| Var::U64(x as u64));
+    Ok(())
+}
+
+/// Receive a datagram of at most specified length. The count of received bytes is put at register
+/// 2, and the address of the sender is put at register 3.
+pub fn recv_from(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, READABLE)?;
+    let bufc = unsafe { a.get_unchecked(1) }
+        .as_usize()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let mut buf = vec![0u8; bufc];
+    let r = on_socket(a, |x| x.recv_from(&mut buf))?;
+    if let Ok((x, y)) = &r {
+        buf.truncate(*x);
+        *(unsafe { a.get_unchecked_mut(2) }) = Var::U64(*x as u64);
+        *(unsafe { a.get_unchecked_mut(3) }) = Var::UString(StringRef::from(y.to_string()));
+    }
+    put(a, r, |_| Var::Bytes(buf.into()));
+    Ok(())
+}
+
+/// Receive a datagram of at most specified length from the connected address. The count of
+/// received bytes is put at register 2.
+pub fn recv(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, READABLE)?;
+    let bufc = unsafe { a.get_unchecked(1) }
+        .as_usize()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let mut buf = vec![0u8; bufc];
+    let r = on_socket(a, |x| x.recv(&mut buf))?;
+    if let Ok(x) = &r {
+        buf.truncate(*x);
+        *(unsafe { a.get_unchecked_mut(2) }) = Var::U64(*x as u64);
+    }
+    put(a, r, |_| Var::Bytes(buf.into()));
+    Ok(())
+}
+
+/// Enable or disable `SO_BROADCAST`.
+pub fn set_broadcast(a: &mut [Var]) -> Result<(), VmError> {
+    let on = flag(a)?;
+    let r = on_socket(a, |x| x.set_broadcast(on))?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Enable or disable receiving multicast datagrams sent by this host.
+pub fn set_multicast_loop(a: &mut [Var]) -> Result<(), VmError> {
+    let on = flag(a)?;
+    let r = on_socket(a, |x| match x.local_addr()?.is_ipv4() {
+        true => x.set_multicast_loop_v4(on),
+        false => x.set_multicast_loop_v6(on),
+    })?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Set the time-to-live of IPv4 multicast datagrams.
+pub fn set_multicast_ttl(a: &mut [Var]) -> Result<(), VmError> {
+    let ttl = unsafe { a.get_unchecked(1) }
+        .as_u64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let r = on_socket(a, |x| x.set_multicast_ttl_v4(ttl as u32))?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Join or leave a multicast group. For IPv4, register 2 is the address of the local interface;
+/// for IPv6, register 2 is the index of the interface. 0 means any interface.
+fn multicast(a: &mut [Var], join: bool) -> Result<(), VmError> {
+    let r = match ip(a, 1)? {
+        Ok(IpAddr::V4(x)) => match ip(a, 2)? {
+            Ok(IpAddr::V4(y)) => on_socket(a, |z| match join {
+                true => z.join_multicast_v4(&x, &y),
+                false => z.leave_multicast_v4(&x, &y),
+            })?,
+            Ok(IpAddr::V6(_)) => Err(io::ErrorKind::InvalidInput.into()),
+            Err(y) => Err(y),
+        },
+        Ok(IpAddr::V6(x)) => {
+            let interface = unsafe { a.get_unchecked(2) }
+                .as_u64()
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+            on_socket(a, |z| match join {
+                true => z.join_multicast_v6(&x, interface as u32),
+                false => z.leave_multicast_v6(&x, interface as u32),
+            })?
+        }
+        Err(x) => Err(x),
+    };
+    put_status(a, r);
+    Ok(())
+}
+
+/// Join a multicast group.
+pub fn join_multicast(a: &mut [Var]) -> Result<(), VmError> {
+    multicast(a, true)
+}
+
+/// Leave a multicast group.
+pub fn leave_multicast(a: &mut [Var]) -> Result<(), VmError> {
+    multicast(a, false)
+}
+
+/// Get the local address.
+pub fn local_addr(a: &mut [Var]) -> Result<(), VmError> {
+    let r = on_socket(a, UdpSocket::local_addr)?;
+    put(a, r, |x| Var::UString(StringRef::from(x.to_string())));
+    Ok(())
+}
+
+/// Close a socket.
+pub fn close(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    iohmgr::del(id);
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::net::udp::bind", bind);
+    putnfp("raw::net::udp::connect", connect);
+    putnfp("raw::net::udp::send_to", send_to);
+    putnfp("raw::net::udp::send", send);
+    putnfp("raw::net::udp::recv_from", recv_from);
+    putnfp("raw::net::udp::recv", recv);
+    putnfp("raw::net::udp::set_broadcast", set_broadcast);
+    putnfp("raw::net::udp::set_multicast_loop", set_multicast_loop);
+    putnfp("raw::net::udp::set_multicast_ttl", set_multicast_ttl);
+    putnfp("raw::net::udp::join_multicast", join_multicast);
+    putnfp("raw::net::udp::leave_multicast", leave_multicast);
+    putnfp("raw::net::udp::local_addr", local_addr);
+    putnfp("raw::net::udp::close", close);
+}
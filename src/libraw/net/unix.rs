@@ -0,0 +1,253 @@
+//!
+//! Unix domain sockets of `libraw`. This is only available on UNIX.
+//!
+
+use super::{handler, put, put_status, wait};
+use crate::{
+    context::putnfp,
+    error::VmError,
+    executor::reactor::{READABLE, WRITABLE},
+    libraw::iohmgr::{self, RawObject},
+    vmem::{CreateNull, StringRef, Var},
+};
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::unix::net::{SocketAddr, UnixDatagram, UnixListener, UnixStream},
+};
+
+/// Run `f` on the stream whose ID is at register 0. If there is no such stream, this fails with
+/// `io::ErrorKind::Other`. The stream is cloned, so the handle table is not locked while `f`
+/// blocks.
+fn on_stream<T>(
+    a: &[Var],
+    f: impl FnOnce(&UnixStream) -> io::Result<T>,
+) -> Result<io::Result<T>, VmError> {
+    let stream = match handler(a)?.as_deref() {
+        Some(RawObject::UnixStream(x)) => x.try_clone(),
+        _ => Err(io::ErrorKind::Other.into()),
+    };
+    Ok(stream.and_then(|x| f(&x)))
+}
+
+/// Run `f` on the datagram socket whose ID is at register 0. If there is no such socket, this
+/// fails with `io::ErrorKind::Other`. The socket is cloned, so the handle table is not locked while
+/// `f` blocks.
+fn on_datagram<T>(
+    a: &[Var],
+    f: impl FnOnce(&UnixDatagram) -> io::Result<T>,
+) -> Result<io::Result<T>, VmError> {
+    let socket = match handler(a)?.as_deref() {
+        Some(RawObject::UnixDatagram(x)) => x.try_clone(),
+        _ => Err(io::ErrorKind::Other.into()),
+    };
+    Ok(socket.and_then(|x| f(&x)))
+}
+
+/// Get the path at index `i` of `a`.
+fn path(a: &[Var], i: usize) -> Result<String, VmError> {
+    Ok(unsafe { a.get_unchecked(i) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
+        .borrow()?
+        .to_owned())
+}
+
+/// Get the bytes at index 1 of `a`.
+fn content(a: &[Var]) -> Result<Vec<u8>, VmError> {
+    match unsafe { a.get_unchecked(1) } {
+        Var::Bytes(x) => Ok(x.borrow()?.to_owned()),
+        _ => Err(VmError::new("raw::fatal::not_a_buf")),
+    }
+}
+
+/// Get the length of the buffer at index 1 of `a`.
+fn bufc(a: &[Var]) -> Result<usize, VmError> {
+    unsafe { a.get_unchecked(1) }
+        .as_usize()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Convert a socket address to a string. An unnamed address is converted to a null string.
+fn addr_to_var(x: &SocketAddr) -> Var {
+    Var::UString(match x.as_pathname() {
+        Some(y) => StringRef::from(y.to_string_lossy().into_owned()),
+        None => StringRef::null(),
+    })
+}
+
+/// Listen on a path.
+pub fn listen(a: &mut [Var]) -> Result<(), VmError> {
+    let r = UnixListener::bind(path(a, 0)?);
+    put(a, r, |x| Var::U64(iohmgr::add(RawObject::UnixListener(x))));
+    Ok(())
+}
+
+/// Accept a connection.
+pub fn accept(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, READABLE)?;
+    let listener = match handler(a)?.as_deref() {
+        Some(RawObject::UnixListener(x)) => x.try_clone(),
+        _ => Err(io::ErrorKind::Other.into()),
+    };
+    let r = listener.and_then(|x| x.accept());
+    put(a, r, |(x, _)| {
+        Var::U64(iohmgr::add(RawObject::UnixStream(x)))
+    });
+    Ok(())
+}
+
+/// Connect to a path.
+pub fn connect(a: &mut [Var]) -> Result<(), VmError> {
+    let r = UnixStream::connect(path(a, 0)?);
+    put(a, r, |x| Var::U64(iohmgr::add(RawObject::UnixStream(x))));
+    Ok(())
+}
+
+/// Read at most specified length of content to a bytes. The count of read bytes is put at
+/// register 2, and 0 means the peer has closed the connection.
+pub fn read(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, READABLE)?;
+    let mut buf = vec![0u8; bufc(a)?];
+    let r = on_stream(a, |mut x| x.read(&mut buf))?;
+    if let Ok(x) = &r {
+        buf.truncate(*x);
+        *(unsafe { a.get_unchecked_mut(2) }) = Var::U64(*x as u64);
+    }
+    put(a, r, |_| Var::Bytes(buf.into()));
+    Ok(())
+}
+
+/// Write all of a bytes.
+pub fn write(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, WRITABLE)?;
+    let content = content(a)?;
+    let r = on_stream(a, |mut x| x.write_all(&content))?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Shut down the reading half(0), the writing half(1) or both halves(2) of a connection.
+pub fn shutdown(a: &mut [Var]) -> Result<(), VmError> {
+    let how = match unsafe { a.get_unchecked(1) }.as_u8() {
+        Some(0) => Shutdown::Read,
+        Some(1) => Shutdown::Write,
+        Some(2) => Shutdown::Both,
+        Some(_) => return Err(VmError::new("raw::fatal::invalid")),
+        None => return Err(VmError::new("raw::fatal::not_an_integer")),
+    };
+    let r = on_stream(a, |x| x.shutdown(how))?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Bind a datagram socket to a path.
+pub fn dgram_bind(a: &mut [Var]) -> Result<(), VmError> {
+    let r = UnixDatagram::bind(path(a, 0)?);
+    put(a, r, |x| Var::U64(iohmgr::add(RawObject::UnixDatagram(x))));
+    Ok(())
+}
+
+/// Create a datagram socket which is not bound to any path.
+pub fn dgram_unbound(a: &mut [Var]) -> Result<(), VmError> {
+    let r = UnixDatagram::unbound();
+    put(a, r, |x| Var::U64(iohmgr::add(RawObject::UnixDatagram(x))));
+    Ok(())
+}
+
+/// Connect a datagram socket to a path.
+pub fn dgram_connect(a: &mut [Var]) -> Result<(), VmError> {
+    let path = path(a, 1)?;
+    let r = on_datagram(a, |x| x.connect(path))?;
+    put_status(a, r);
+    Ok(())
+}
+
+/// Send a datagram to a path. The count of sent bytes is put at register 1.
+pub fn dgram_send_to(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, WRITABLE)?;
+    let content = content(a)?;
+    let path = path(a, 2)?;
+    let r = on_datagram(a, |x| x.send_to(&content, path))?;
+    put(a, r, |x| Var::U64(x as u64));
+    Ok(())
+}
+
+/// Send a datagram to the connected path. The count of sent bytes is put at register 1.
+pub fn dgram_send(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, WRITABLE)?;
+    let content = content(a)?;
+    let r = on_datagram(a, |x| x.send(&content))?;
+    put(a, r, |x| Var::U64(x as u64));
+    Ok(())
+}
+
+/// Receive a datagram of at most specified length. The count of received bytes is put at register
+/// 2, and the path of the sender is put at register 3.
+pub fn dgram_recv_from(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, READABLE)?;
+    let mut buf = vec![0u8; bufc(a)?];
+    let r = on_datagram(a, |x| x.recv_from(&mut buf))?;
+    if let Ok((x, y)) = &r {
+        buf.truncate(*x);
+        *(unsafe { a.get_unchecked_mut(2) }) = Var::U64(*x as u64);
+        *(unsafe { a.get_unchecked_mut(3) }) = addr_to_var(y);
+    }
+    put(a, r, |_| Var::Bytes(buf.into()));
+    Ok(())
+}
+
+/// Receive a datagram of at most specified length from the connected path. The count of received
+/// bytes is put at register 2.
+pub fn dgram_recv(a: &mut [Var]) -> Result<(), VmError> {
+    wait(a, READABLE)?;
+    let mut buf = vec![0u8; bufc(a)?];
+    let r = on_datagram(a, |x| x.recv(&mut buf))?;
+    if let Ok(x) = &r {
+        buf.truncate(*x);
+        *(unsafe { a.get_unchecked_mut(2) }) = Var::U64(*x as u64);
+    }
+    put(a, r, |_| Var::Bytes(buf.into()));
+    Ok(())
+}
+
+/// Get the local path of a listener, a stream or a datagram socket.
+pub fn local_addr(a: &mut [Var]) -> Result<(), VmError> {
+    let r = match handler(a)?.as_deref() {
+        Some(RawObject::UnixListener(x)) => x.local_addr(),
+        Some(RawObject::UnixStream(x)) => x.local_addr(),
+        Some(RawObject::UnixDatagram(x)) => x.local_addr(),
+        _ => Err(io::ErrorKind::Other.into()),
+    };
+    put(a, r, |x| addr_to_var(&x));
+    Ok(())
+}
+
+/// Close a socket. The path of a bound socket is not removed.
+pub fn close(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    iohmgr::del(id);
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::net::unix::listen", listen);
+    putnfp("raw::net::unix::accept", accept);
+    putnfp("raw::net::unix::connect", connect);
+    putnfp("raw::net::unix::read", read);
+    putnfp("raw::net::unix::write", write);
+    putnfp("raw::net::unix::shutdown", shutdown);
+    putnfp("raw::net::unix::dgram_bind", dgram_bind);
+    putnfp("raw::net::unix::dgram_unbound", dgram_unbound);
+    putnfp("raw::net::unix::dgram_connect", dgram_connect);
+    putnfp("raw::net::unix::dgram_send_to", dgram_send_to);
+    putnfp("raw::net::unix::dgram_send", dgram_send);
+    putnfp("raw::net::unix::dgram_recv_from", dgram_recv_from);
+    putnfp("raw::net::unix::dgram_recv", dgram_recv);
+    putnfp("raw::net::unix::local_addr", local_addr);
+    putnfp("raw::net::unix::close", close);
+}
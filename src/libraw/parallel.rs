@@ -0,0 +1,106 @@
+//!
+//! Parallel collection operations of `libraw`.
+//!
+//! The operations apply a VM function over elements of a vector with the global `rayon` pool. Each
+//! call is executed in its own thread, with arguments at register 100 and the result at register
+//! 100. The vector is copied before the operation starts, so the function may access it freely.
+//! If a call is terminated by an interruption, the interruption is thrown by the operation.
+//!
+
+use crate::{
+    context::{getfp, putnfp, ExecUnit, Thread},
+    error::VmError,
+    executor,
+    isa::{FuncPtr, VirtFuncPtr},
+    vmem::Var,
+};
+use rayon::prelude::*;
+
+/// Get the VM function whose name is the argument.
+fn func(name: &Var) -> Result<VirtFuncPtr, VmError> {
+    let name = name
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let name = name.borrow()?;
+    match getfp(&name) {
+        Some(FuncPtr::Virtual(x)) => Ok(x),
+        _ => Err(VmError::new("raw::fatal::segfault")),
+    }
+}
+
+/// Copy elements of the vector argument.
+fn elements(vector: &Var) -> Result<Vec<Var>, VmError> {
+    match vector {
+        Var::Vector(x) => Ok(x.borrow()?.clone()),
+        _ => Err(VmError::new("raw::fatal::not_a_buf")),
+    }
+}
+
+/// Call a VM function in a new thread, and get its result.
+fn call(fp: &VirtFuncPtr, args: &[Var]) -> Result<Var, VmError> {
+    let mut thread = Thread::new(fp.clone());
+    for (i, x) in args.iter().enumerate() {
+        thread.sset(100 + i, x.to_owned())?;
+    }
+    executor::start_thread(thread).map(|mut x| x.swap_remove(0))
+}
+
+/// Map elements of a vector with a function. Returns a vector of results in the same order.
+pub fn map(a: &mut [Var]) -> Result<(), VmError> {
+    let fp = func(unsafe { a.get_unchecked(0) })?;
+    let result = elements(unsafe { a.get_unchecked(1) })?
+        .into_par_iter()
+        .map(|x| call(&fp, &[x]))
+        .collect::<Result<Vec<_>, _>>()?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::Vector(result.into());
+    Ok(())
+}
+
+/// Filter elements of a vector with a predicate, which returns a non-zero integer to keep the
+/// element. Returns a vector of kept elements in the same order.
+pub fn filter(a: &mut [Var]) -> Result<(), VmError> {
+    let fp = func(unsafe { a.get_unchecked(0) })?;
+    let result = elements(unsafe { a.get_unchecked(1) })?
+        .into_par_iter()
+        .map(|x| {
+            let keep = call(&fp, std::slice::from_ref(&x))?
+                .as_u64()
+                .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+            Ok((keep != 0).then_some(x))
+        })
+        .collect::<Result<Vec<_>, VmError>>()?;
+    *(unsafe { a.get_unchecked_mut(0) }) =
+        Var::Vector(result.into_iter().flatten().collect::<Vec<_>>().into());
+    Ok(())
+}
+
+/// Reduce elements of a vector with a function, which combines two values at 100 and 101. The
+/// function should be associative, and the identity at 2 is used as the initial value of each
+/// piece of work.
+pub fn reduce(a: &mut [Var]) -> Result<(), VmError> {
+    let fp = func(unsafe { a.get_unchecked(0) })?;
+    let identity = unsafe { a.get_unchecked(2) }.to_owned();
+    let result = elements(unsafe { a.get_unchecked(1) })?
+        .into_par_iter()
+        .map(Ok)
+        .try_reduce(|| identity.clone(), |x, y| call(&fp, &[x, y]))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = result;
+    Ok(())
+}
+
+/// Call a function with each element of a vector, in no particular order.
+pub fn for_each(a: &mut [Var]) -> Result<(), VmError> {
+    let fp = func(unsafe { a.get_unchecked(0) })?;
+    elements(unsafe { a.get_unchecked(1) })?
+        .into_par_iter()
+        .try_for_each(|x| call(&fp, &[x]).map(|_| ()))
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::par::map", map);
+    putnfp("raw::par::filter", filter);
+    putnfp("raw::par::reduce", reduce);
+    putnfp("raw::par::for_each", for_each);
+}
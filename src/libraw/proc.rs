@@ -2,8 +2,7 @@
 //! Process library of `libraw`.
 //!
 
-use crate::{context::putnfp, vmem::Var};
-use anyhow::anyhow;
+use crate::{context::putnfp, error::VmError, vmem::Var};
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use std::process::Child;
@@ -11,10 +10,10 @@ use std::process::Child;
 static PROCESSES: Lazy<DashMap<u32, Child, ahash::RandomState>> = Lazy::new(DashMap::default);
 
 /// Spawn a process.
-pub fn spawn(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn spawn(a: &mut [Var]) -> Result<(), VmError> {
     let path = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let path = path.borrow()?;
     let args = unsafe { a.get_unchecked(1) };
     let argc = args.rcl()?;
@@ -23,7 +22,7 @@ pub fn spawn(a: &mut [Var]) -> Result<(), anyhow::Error> {
         argv.push(
             args.offset_get(i)?
                 .as_sr()
-                .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?
+                .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
                 .to_string(),
         );
     }
@@ -40,12 +39,12 @@ pub fn spawn(a: &mut [Var]) -> Result<(), anyhow::Error> {
     Ok(())
 }
 /// Wait a process.
-pub fn wait(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn wait(a: &mut [Var]) -> Result<(), VmError> {
     use crate::vmem::{CreateNull, ObjectRef};
 
     let id = unsafe { a.get_unchecked(0) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     match PROCESSES.remove(&(id as u32)) {
         Some((_, mut x)) => match x.wait() {
             Ok(y) => {
@@ -58,7 +57,7 @@ pub fn wait(a: &mut [Var]) -> Result<(), anyhow::Error> {
             }
             Err(_) => *(unsafe { a.get_unchecked_mut(0) }) = Var::Object(ObjectRef::null()),
         },
-        None => return Err(anyhow!("raw::fatal::segfault")),
+        None => return Err(VmError::new("raw::fatal::segfault")),
     }
     Ok(())
 }
@@ -2,8 +2,7 @@
 //! Random number generating utilities of `libraw`.
 //!
 
-use crate::{context::putnfp, vmem::Var};
-use anyhow::anyhow;
+use crate::{context::putnfp, error::VmError, vmem::Var};
 use getrandom::getrandom as os_getrand;
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
@@ -38,13 +37,13 @@ static CHACHA20: Lazy<Mutex<ChaCha20Rng>> = Lazy::new(|| {
 
 macro_rules! impl_genrand {
     ($a: ty, $b: ident, $c: ident) => {
-        pub fn $b(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $b(a: &mut [Var]) -> Result<(), VmError> {
             let mut buf = [0u8; size_of::<$a>()];
             match os_getrand(&mut buf) {
                 Ok(_) => {
                     *(unsafe { a.get_unchecked_mut(0) }) = Var::$c(unsafe { transmute(buf) });
                 }
-                Err(_) => return Err(anyhow!("raw::fatal::environment_error")),
+                Err(_) => return Err(VmError::new("raw::fatal::environment_error")),
             }
             Ok(())
         }
@@ -53,7 +52,7 @@ macro_rules! impl_genrand {
 
 macro_rules! impl_chacha {
     ($a: ty, $b: ident, $c: ident, $d: ident) => {
-        pub fn $c(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $c(a: &mut [Var]) -> Result<(), VmError> {
             let mut buf = [0u8; size_of::<$a>()];
             $b.lock().fill_bytes(&mut buf);
             *(unsafe { a.get_unchecked_mut(0) }) = Var::$d(unsafe { transmute(buf) });
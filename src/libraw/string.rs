@@ -4,19 +4,19 @@
 
 use crate::{
     context::putnfp,
+    error::VmError,
     vmem::{BytesRef, CreateNull, StringRef, Var},
 };
-use anyhow::anyhow;
 use parking_lot::RwLock;
 use rayon::prelude::*;
 use std::convert::TryFrom;
 
 macro_rules! impl_parse {
     ($a: ty, $b: ident, $c: ident) => {
-        pub fn $b(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $b(a: &mut [Var]) -> Result<(), VmError> {
             let parsed = unsafe { a.get_unchecked(0) }
                 .as_sr()
-                .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?
+                .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
                 .borrow()?
                 .parse::<$a>();
             match parsed {
@@ -35,10 +35,10 @@ macro_rules! impl_parse {
 
 macro_rules! impl_trim {
     ($a: ident) => {
-        pub fn $a(a: &mut [Var]) -> Result<(), anyhow::Error> {
+        pub fn $a(a: &mut [Var]) -> Result<(), VmError> {
             let untrimmed = unsafe { a.get_unchecked(0) }
                 .as_sr()
-                .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+                .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
             let untrimmed = untrimmed.borrow()?;
             let trimmed = untrimmed.$a();
             *(unsafe { a.get_unchecked_mut(0) }) = Var::UString(trimmed.into());
@@ -48,7 +48,7 @@ macro_rules! impl_trim {
 }
 
 /// Push a char into the paassed string.
-pub fn push(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn push(a: &mut [Var]) -> Result<(), VmError> {
     let c = match unsafe { a.get_unchecked(1) } {
         Var::I8(x) => *x as u8 as char,
         Var::U8(x) => *x as char,
@@ -56,32 +56,32 @@ pub fn push(a: &mut [Var]) -> Result<(), anyhow::Error> {
         Var::U16(x) => u16_to_char(*x)?,
         Var::I32(x) => u32_to_char(*x as u32)?,
         Var::U32(x) => u32_to_char(*x)?,
-        _ => return Err(anyhow!("raw::fatal::not_a_char")),
+        _ => return Err(VmError::new("raw::fatal::not_a_char")),
     };
     let r = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     r.borrow_mut()?.push(c);
     Ok(())
 }
 
 /// Push a string into the passed string.
-pub fn push_str(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn push_str(a: &mut [Var]) -> Result<(), VmError> {
     unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
         .borrow_mut()?
         .push_str(
             &*unsafe { a.get_unchecked(1) }
                 .as_sr()
-                .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?
+                .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
                 .borrow()?,
         );
     Ok(())
 }
 
 /// Create a new string from anything.
-pub fn autofrom(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn autofrom(a: &mut [Var]) -> Result<(), VmError> {
     use std::fmt::Write;
 
     let mut buf: String;
@@ -141,13 +141,21 @@ pub fn autofrom(a: &mut [Var]) -> Result<(), anyhow::Error> {
             buf = String::with_capacity(64);
             write!(buf, "{:?}", x).unwrap();
         }
+        Var::Weak(x) => {
+            buf = String::with_capacity(16);
+            write!(buf, "{:?}", x).unwrap();
+        }
+        Var::Mapped(x) => {
+            buf = String::with_capacity(32);
+            write!(buf, "{:?}", x).unwrap();
+        }
     };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::UString(StringRef::from(buf));
     Ok(())
 }
 
 /// From bytes.
-pub fn from_bytes(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn from_bytes(a: &mut [Var]) -> Result<(), VmError> {
     match unsafe { a.get_unchecked(0) } {
         Var::Bytes(x) => {
             if x.is_null() {
@@ -164,15 +172,15 @@ pub fn from_bytes(a: &mut [Var]) -> Result<(), anyhow::Error> {
             }
             Ok(())
         }
-        _ => Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => Err(VmError::new("raw::fatal::not_a_buf")),
     }
 }
 
 /// From chars.
-pub fn from_chars(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn from_chars(a: &mut [Var]) -> Result<(), VmError> {
     let val = match unsafe { a.get_unchecked(0) } {
         Var::Vector(x) => x,
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     };
     let sbuf = RwLock::new(String::new());
     for i in val.borrow()?.iter() {
@@ -181,13 +189,13 @@ pub fn from_chars(a: &mut [Var]) -> Result<(), anyhow::Error> {
             Var::U8(x) => x as char,
             Var::I32(x) => match char::try_from(x as u32) {
                 Ok(y) => y,
-                Err(_) => return Err(anyhow!("raw::fatal::invalid")),
+                Err(_) => return Err(VmError::new("raw::fatal::invalid")),
             },
             Var::U32(x) => match char::try_from(x) {
                 Ok(y) => y,
-                Err(_) => return Err(anyhow!("raw::fatal::invalid")),
+                Err(_) => return Err(VmError::new("raw::fatal::invalid")),
             },
-            _ => return Err(anyhow!("raw::fatal::not_an_integer")),
+            _ => return Err(VmError::new("raw::fatal::not_an_integer")),
         };
         sbuf.write().push(ch);
     }
@@ -196,7 +204,7 @@ pub fn from_chars(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Equal test for strings.
-pub fn eq(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn eq(a: &mut [Var]) -> Result<(), VmError> {
     let val1 = unsafe { a.get_unchecked(0) };
     let val2 = unsafe { a.get_unchecked(1) };
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8((val1 == val2) as u8);
@@ -204,10 +212,10 @@ pub fn eq(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Convert to bytes.
-pub fn tb(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn tb(a: &mut [Var]) -> Result<(), VmError> {
     let val = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let val = val.borrow();
     match val {
         Ok(x) => unsafe { *a.get_unchecked_mut(0) = Var::Bytes((&x[..]).into()) },
@@ -217,10 +225,10 @@ pub fn tb(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Convert to chars.
-pub fn to_chars(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn to_chars(a: &mut [Var]) -> Result<(), VmError> {
     let val = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let ch = val.borrow()?;
     let result = RwLock::new(Vec::new());
     ch.par_chars()
@@ -230,10 +238,10 @@ pub fn to_chars(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Deep clone a string.
-pub fn deep_clone(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn deep_clone(a: &mut [Var]) -> Result<(), VmError> {
     let val = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     if val.is_null() {
         unsafe { *a.get_unchecked_mut(0) = Var::UString(StringRef::null()) }
     } else {
@@ -243,16 +251,16 @@ pub fn deep_clone(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Split the string.
-pub fn split(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn split(a: &mut [Var]) -> Result<(), VmError> {
     use crate::vmem::VectorRef;
 
     let sym = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let sym = sym.borrow()?;
     let val = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let val = val.borrow()?;
     let vr = VectorRef::empty();
     for i in val.split(&*sym) {
@@ -263,63 +271,63 @@ pub fn split(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Judge if A contains B as a substring.
-pub fn contains(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn contains(a: &mut [Var]) -> Result<(), VmError> {
     let val = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let val = val.borrow()?;
     let subval = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let subval = subval.borrow()?;
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(val.contains(&subval[..]) as u8);
     Ok(())
 }
 
 /// Create a string that handles an IO error integer.
-pub fn from_ioerr(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn from_ioerr(a: &mut [Var]) -> Result<(), VmError> {
     let code = unsafe { a.get_unchecked(0) }
         .as_usize()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))? as u64;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))? as u64;
     let rslt = crate::libraw::iohmgr::error::to_string(code);
     *(unsafe { a.get_unchecked_mut(0) }) = Var::UString(rslt.into());
     Ok(())
 }
 
 /// Substring replace.
-pub fn replace(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn replace(a: &mut [Var]) -> Result<(), VmError> {
     let s = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let s = s.borrow()?;
     let src = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let src = src.borrow()?;
     let dest = unsafe { a.get_unchecked(2) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let dest = dest.borrow()?;
     *(unsafe { a.get_unchecked_mut(0) }) = Var::UString(s.replace(&src[..], &dest[..]).into());
     Ok(())
 }
 
 /// Clear a string.
-pub fn clear(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn clear(a: &mut [Var]) -> Result<(), VmError> {
     unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
         .borrow_mut()?
         .clear();
     Ok(())
 }
 
 /// Judge if a string's content is ASCII-encoded.
-pub fn is_ascii(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn is_ascii(a: &mut [Var]) -> Result<(), VmError> {
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(
         unsafe { a.get_unchecked(0) }
             .as_sr()
-            .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?
+            .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?
             .borrow()?
             .is_ascii() as u8,
     );
@@ -374,16 +382,16 @@ pub fn init() {
 }
 
 /// Convert from U32 to char, returns an result with interruptable error.
-fn u32_to_char(c: u32) -> Result<char, anyhow::Error> {
+fn u32_to_char(c: u32) -> Result<char, VmError> {
     match char::try_from(c) {
         Ok(x) => Ok(x),
-        Err(_) => Err(anyhow!("raw::fatal::not_valid_unicode")),
+        Err(_) => Err(VmError::new("raw::fatal::not_valid_unicode")),
     }
 }
 /// Convert from U16 to char, returns an result with interruptable error.
-fn u16_to_char(c: u16) -> Result<char, anyhow::Error> {
+fn u16_to_char(c: u16) -> Result<char, VmError> {
     match String::from_utf16(&[c]) {
         Ok(x) => Ok(x.chars().next().unwrap()),
-        Err(_) => Err(anyhow!("raw::fatal::not_valid_unicode")),
+        Err(_) => Err(VmError::new("raw::fatal::not_valid_unicode")),
     }
 }
@@ -0,0 +1,88 @@
+//!
+//! M:N green threads of `libraw`. See `executor::green` for details.
+//!
+//! A task yields with `~ raw::task::yield`, like a coroutine.
+//!
+
+use crate::{
+    context::{getfp, putnfp, ExecUnit, Thread},
+    error::VmError,
+    executor::green,
+    isa::FuncPtr,
+    vmem::Var,
+};
+
+/// Spawn a task from a function name and a vector of arguments.
+pub fn spawn(a: &mut [Var]) -> Result<(), VmError> {
+    let fp = unsafe { a.get_unchecked(0) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let fp = fp.borrow()?;
+    let mut thread = Thread::new(match getfp(&fp) {
+        Some(FuncPtr::Virtual(x)) => x,
+        _ => return Err(VmError::new("raw::fatal::segfault")),
+    });
+    match unsafe { a.get_unchecked(1) } {
+        Var::Vector(x) => {
+            let args = x.borrow()?;
+            if args.len() > 50 {
+                return Err(VmError::new("raw::fatal::segfault"));
+            }
+            for (i, y) in args.iter().enumerate() {
+                thread.sset(100 + i, y.to_owned())?;
+            }
+        }
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
+    }
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(green::spawn(thread));
+    Ok(())
+}
+
+/// Join a task. Its return registers are copied to the registers of the caller, or the
+/// interruption that killed it is thrown again.
+pub fn join(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    for (i, x) in green::join(id)??.into_iter().enumerate() {
+        if let Some(y) = a.get_mut(i) {
+            *y = x;
+        }
+    }
+    Ok(())
+}
+
+/// Detach a task, so its outcome is not kept.
+pub fn detach(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    green::detach(id);
+    Ok(())
+}
+
+/// Judge if a task is alive.
+pub fn is_alive(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(green::is_alive(id) as u8);
+    Ok(())
+}
+
+/// Get the ID of the current task, or 0 if this is not a task.
+pub fn current(a: &mut [Var]) -> Result<(), VmError> {
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(green::current());
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    green::init();
+    putnfp("raw::task::spawn", spawn);
+    putnfp("raw::task::join", join);
+    putnfp("raw::task::detach", detach);
+    putnfp("raw::task::is_alive", is_alive);
+    putnfp("raw::task::current", current);
+}
@@ -2,13 +2,18 @@
 //! Threading module of `libraw`. This also provides syncing utilities.
 //!
 
+pub mod chan;
+pub mod pool;
+pub mod sync;
+
 use crate::{
     context::{putnfp, ExecUnit, Thread},
+    error::VmError,
+    executor,
     libraw::iohmgr::{self, RawObject},
-    vmem::Var,
+    vmem::{CreateNull, ObjectRef, StringRef, Var},
 };
-use anyhow::anyhow;
-use std::{cell::RefCell, collections::HashMap};
+use std::{any::Any, cell::RefCell, collections::HashMap};
 
 thread_local! {
     /// TLS Map of current thread.
@@ -16,10 +21,10 @@ thread_local! {
 }
 
 /// Set a TLS.
-pub fn tls_set(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn tls_set(a: &mut [Var]) -> Result<(), VmError> {
     let key = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let key = key.borrow()?;
     let val = unsafe { a.get_unchecked(1) }.to_owned();
     TLS_MAP.with(|x| x.borrow_mut().insert(Box::from(&key[..]), val));
@@ -27,10 +32,10 @@ pub fn tls_set(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get a TLS.
-pub fn tls_get(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn tls_get(a: &mut [Var]) -> Result<(), VmError> {
     let name = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let name = name.borrow()?;
     TLS_MAP.with(|x| match x.borrow().get(&name[..]) {
         Some(y) => {
@@ -45,38 +50,38 @@ pub fn tls_get(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Delete a TLS.
-pub fn tls_del(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn tls_del(a: &mut [Var]) -> Result<(), VmError> {
     let name = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let name = name.borrow()?;
     TLS_MAP.with(|x| x.borrow_mut().remove(&name[..]));
     Ok(())
 }
 
 /// Spawn a thread.
-pub fn spawn(a: &mut [Var]) -> Result<(), anyhow::Error> {
-    use crate::{context::getfp, executor::start, isa::FuncPtr};
+pub fn spawn(a: &mut [Var]) -> Result<(), VmError> {
+    use crate::{context::getfp, executor::start_thread, isa::FuncPtr};
 
     let fp = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let fp = fp.borrow()?;
     let args = match unsafe { a.get_unchecked(1) } {
         Var::Vector(x) => x.borrow()?,
-        _ => return Err(anyhow!("raw::fatal::not_a_buf")),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
     };
     let mut new_thread = Thread::new(
         match match getfp(&fp) {
             Some(x) => x,
-            None => return Err(anyhow!("raw::fatal::segfault")),
+            None => return Err(VmError::new("raw::fatal::segfault")),
         } {
             FuncPtr::Virtual(x) => x,
-            _ => return Err(anyhow!("raw::fatal::segfault")),
+            _ => return Err(VmError::new("raw::fatal::segfault")),
         },
     );
     if args.len() > 50 {
-        return Err(anyhow!("raw::fatal::segfault"));
+        return Err(VmError::new("raw::fatal::segfault"));
     }
     for i in 0..args.len() {
         if new_thread
@@ -88,9 +93,7 @@ pub fn spawn(a: &mut [Var]) -> Result<(), anyhow::Error> {
     }
     let builder = std::thread::Builder::new().name("secondary".to_owned());
     drop(args);
-    match builder.spawn(move || {
-        start(new_thread);
-    }) {
+    match builder.spawn(move || start_thread(new_thread)) {
         Ok(x) => {
             *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(1);
             *(unsafe { a.get_unchecked_mut(1) }) = Var::U64(iohmgr::add(RawObject::Thread(x)))
@@ -100,65 +103,119 @@ pub fn spawn(a: &mut [Var]) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-/// Join a thread.
-pub fn join(a: &mut [Var]) -> Result<(), anyhow::Error> {
+/// Describe why a thread failed. The description has `id`, `message` and `payload` fields, like
+/// arguments of interruption handlers.
+fn failure(e: &VmError) -> Result<Var, VmError> {
+    let result = ObjectRef::new("raw::thrd::failure");
+    result.set("id", Var::UString(e.id().into()))?;
+    result.set(
+        "message",
+        Var::UString(match e.message() {
+            Some(x) => x.into(),
+            None => StringRef::null(),
+        }),
+    )?;
+    result.set(
+        "payload",
+        match e.payload() {
+            Some(x) => x.to_owned(),
+            None => Var::Object(ObjectRef::null()),
+        },
+    )?;
+    Ok(Var::Object(result))
+}
+
+/// Create the error of a thread which has panicked.
+fn panicked(e: Box<dyn Any + Send>) -> VmError {
+    let result = VmError::new("raw::thrd::panicked");
+    match e.downcast::<&str>() {
+        Ok(x) => result.with_message(*x),
+        Err(x) => match x.downcast::<String>() {
+            Ok(y) => result.with_message(*y),
+            Err(_) => result,
+        },
+    }
+}
+
+/// Put the outcome of a thread. If the thread has returned, 1 and a vector of its return registers
+/// are put. Otherwise, 0 and a description of the interruption that terminated it are put.
+fn put_outcome(a: &mut [Var], outcome: Result<Vec<Var>, VmError>) -> Result<(), VmError> {
+    let (stat, result) = match outcome {
+        Ok(x) => (1, Var::Vector(x.into())),
+        Err(x) => (0, failure(&x)?),
+    };
+    unsafe {
+        *a.get_unchecked_mut(0) = Var::U8(stat);
+        *a.get_unchecked_mut(1) = result;
+    }
+    Ok(())
+}
+
+/// Join a thread. If the thread has returned, 1 and a vector of its return registers are
+/// returned. Otherwise, 0 and a description of the interruption that terminated it are returned.
+pub fn join(a: &mut [Var]) -> Result<(), VmError> {
     let id = unsafe { a.get_unchecked(0) }
         .as_u64_strict()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
-    let handle = iohmgr::take(id);
-    match match handle {
-        Some(x) => x,
-        None => return Ok(()),
-    } {
-        RawObject::Thread(x) => {
-            x.join().unwrap();
-        }
-        _ => return Err(anyhow!("raw::fatal::segfault")),
-    }
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let outcome = match iohmgr::take(id) {
+        Some(RawObject::Thread(x)) => x.join().unwrap_or_else(|y| Err(panicked(y))),
+        Some(_) => return Err(VmError::new("raw::fatal::segfault")),
+        None => Err(VmError::new("raw::thrd::not_joinable")
+            .with_message("the thread doesn't exist or has been joined")),
+    };
+    put_outcome(a, outcome)
+}
+
+/// Set if an abort in a spawned thread exits the process. By default, it only ends the thread.
+pub fn set_abort_process(a: &mut [Var]) -> Result<(), VmError> {
+    let on = unsafe { a.get_unchecked(0) }
+        .as_u8()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    executor::set_abort_process(on != 0);
     Ok(())
 }
 
 /// Sleep this thread.
-pub fn msleep(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn msleep(a: &mut [Var]) -> Result<(), VmError> {
     let time = unsafe { a.get_unchecked(0) }
         .as_u64()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     std::thread::sleep(std::time::Duration::from_millis(time));
     Ok(())
 }
 
 /// Sleep this thread.
-pub fn sleep(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn sleep(a: &mut [Var]) -> Result<(), VmError> {
     let time = unsafe { a.get_unchecked(0) }
         .as_u64()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     std::thread::sleep(std::time::Duration::from_secs(time));
     Ok(())
 }
 
 /// Sleep nano.
-pub fn nsleep(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn nsleep(a: &mut [Var]) -> Result<(), VmError> {
     let time = unsafe { a.get_unchecked(0) }
         .as_u64()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     std::thread::sleep(std::time::Duration::from_nanos(time));
     Ok(())
 }
 
 /// Yield this thread.
-pub fn yield_now(_: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn yield_now(_: &mut [Var]) -> Result<(), VmError> {
     std::thread::yield_now();
     Ok(())
 }
 
 /// Count CPUs.
-pub fn cpu_count(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn cpu_count(a: &mut [Var]) -> Result<(), VmError> {
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(num_cpus::get_physical() as u64);
     Ok(())
 }
 
 /// Count threads.
-pub fn par_count(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn par_count(a: &mut [Var]) -> Result<(), VmError> {
     *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(num_cpus::get() as u64);
     Ok(())
 }
@@ -171,10 +228,14 @@ pub fn init() {
     putnfp("raw::thrd::tls::del", tls_del);
     putnfp("raw::thrd::spawn", spawn);
     putnfp("raw::thrd::join", join);
+    putnfp("raw::thrd::set_abort_process", set_abort_process);
     putnfp("raw::thrd::sleep<msec>", msleep);
     putnfp("raw::thrd::sleep<nsec>", nsleep);
     putnfp("raw::thrd::sleep<sec>", sleep);
     putnfp("raw::thrd::yield", yield_now);
     putnfp("raw::thrd::par_count", par_count);
     putnfp("raw::thrd::cpu_count", cpu_count);
+    sync::init();
+    chan::init();
+    pool::init();
 }
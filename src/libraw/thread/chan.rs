@@ -0,0 +1,350 @@
+//!
+//! Channels between threads of `libraw`.
+//!
+//! Channels are multi-producer and multi-consumer, and can be passed to other threads by their
+//! IDs. `send` on a full channel and `recv` on an empty channel block the OS thread, so they
+//! should not be used by coroutines or tasks. Values that have been sent can still be received
+//! after the channel is closed.
+//!
+//! Blocking operations take an optional timeout in milliseconds at the last argument register,
+//! where 0 means no timeout.
+//!
+
+use super::sync::Wait;
+use crate::{
+    context::putnfp,
+    error::VmError,
+    libraw::iohmgr::{error as io_error, FakeHasher, IdGen},
+    vmem::{
+        mem::{Accounted, Kind, Measure},
+        Var,
+    },
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use parking_lot::{const_mutex, Condvar, Mutex};
+use std::{collections::VecDeque, sync::Arc};
+
+/// State of a channel.
+struct State {
+    /// Values that haven't been received.
+    queue: VecDeque<Var>,
+    /// Capacity of the channel. 0 means unbounded.
+    cap: usize,
+    /// If the channel is closed.
+    closed: bool,
+    /// Selects waiting for this channel.
+    selectors: Vec<Arc<Signal>>,
+}
+impl State {
+    /// Judge if the channel is full.
+    #[inline]
+    fn is_full(&self) -> bool {
+        self.cap != 0 && self.queue.len() >= self.cap
+    }
+}
+
+/// A channel.
+struct Channel {
+    state: Mutex<State>,
+    /// Notified when a value is sent or the channel is closed.
+    readable: Condvar,
+    /// Notified when a value is received or the channel is closed.
+    writable: Condvar,
+}
+impl Channel {
+    /// Wake receivers after a value is sent or the channel is closed.
+    #[inline]
+    fn wake_receivers(&self, state: &State, all: bool) {
+        if all {
+            self.readable.notify_all();
+        } else {
+            self.readable.notify_one();
+        }
+        state.selectors.iter().for_each(|x| x.fire());
+    }
+}
+
+/// A signal that wakes a select.
+#[derive(Default)]
+struct Signal {
+    fired: Mutex<bool>,
+    cv: Condvar,
+}
+impl Signal {
+    /// Wake the select.
+    #[inline]
+    fn fire(&self) {
+        *self.fired.lock() = true;
+        self.cv.notify_one();
+    }
+}
+
+/// An accounted channel.
+type Chan = Accounted<Channel>;
+
+/// ID generator for channels.
+static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
+/// Table of channels.
+static TABLE: Lazy<DashMap<u64, Arc<Chan>, FakeHasher>> = Lazy::new(DashMap::default);
+
+/// Get a channel. The channel is cloned, so the table is not locked while it's being waited.
+#[inline]
+fn get(id: u64) -> Option<Arc<Chan>> {
+    TABLE.get(&id).map(|x| x.clone())
+}
+
+/// Get the channel ID at register 0.
+#[inline]
+fn id(a: &[Var]) -> Result<u64, VmError> {
+    unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Get the timeout at register `i`.
+#[inline]
+fn wait(a: &[Var], i: usize) -> Result<Wait, VmError> {
+    unsafe { a.get_unchecked(i) }
+        .as_u64()
+        .map(Wait::from_ms)
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Receive a value from a locked channel, or get the status if there's no value.
+#[inline]
+fn pop(chan: &Chan, state: &mut State) -> Result<Var, u64> {
+    match state.queue.pop_front() {
+        Some(x) => {
+            chan.resize(state.queue.heap_size());
+            chan.writable.notify_one();
+            Ok(x)
+        }
+        None if state.closed => Err(io_error::BROKEN_PIPE),
+        None => Err(io_error::WOULD_BLOCK),
+    }
+}
+
+/// Send a value with `wait`.
+fn send_impl(a: &mut [Var], wait: Wait) -> Result<(), VmError> {
+    let status = match get(id(a)?) {
+        Some(chan) => {
+            let mut state = chan.state.lock();
+            loop {
+                if state.closed {
+                    break io_error::BROKEN_PIPE;
+                }
+                if !state.is_full() {
+                    state
+                        .queue
+                        .push_back(unsafe { a.get_unchecked(1) }.to_owned());
+                    chan.resize(state.queue.heap_size());
+                    chan.wake_receivers(&state, false);
+                    break 0;
+                }
+                if !wait.wait(&chan.writable, &mut state) {
+                    break match wait {
+                        Wait::No => io_error::WOULD_BLOCK,
+                        _ => io_error::TIMED_OUT,
+                    };
+                }
+            }
+        }
+        None => io_error::OTHER,
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(status);
+    Ok(())
+}
+
+/// Receive a value with `wait`.
+fn recv_impl(a: &mut [Var], wait: Wait) -> Result<(), VmError> {
+    let status = match get(id(a)?) {
+        Some(chan) => {
+            let mut state = chan.state.lock();
+            loop {
+                match pop(&chan, &mut state) {
+                    Ok(x) => {
+                        *(unsafe { a.get_unchecked_mut(1) }) = x;
+                        break 0;
+                    }
+                    Err(io_error::WOULD_BLOCK) => (),
+                    Err(x) => break x,
+                }
+                if !wait.wait(&chan.readable, &mut state) {
+                    break match wait {
+                        Wait::No => io_error::WOULD_BLOCK,
+                        _ => io_error::TIMED_OUT,
+                    };
+                }
+            }
+        }
+        None => io_error::OTHER,
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(status);
+    Ok(())
+}
+
+/// Create a channel with specified capacity. 0 means unbounded.
+pub fn new(a: &mut [Var]) -> Result<(), VmError> {
+    let cap = unsafe { a.get_unchecked(0) }
+        .as_usize()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let id = IDGEN.lock().next();
+    let state = State {
+        queue: VecDeque::with_capacity(cap.min(64)),
+        cap,
+        closed: false,
+        selectors: Vec::new(),
+    };
+    let size = state.queue.heap_size();
+    let chan = Channel {
+        state: Mutex::new(state),
+        readable: Condvar::new(),
+        writable: Condvar::new(),
+    };
+    TABLE.insert(id, Arc::new(Accounted::new(Kind::Channel, chan, size)));
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
+    Ok(())
+}
+
+/// Send a value, and block while the channel is full.
+pub fn send(a: &mut [Var]) -> Result<(), VmError> {
+    let wait = wait(a, 2)?;
+    send_impl(a, wait)
+}
+
+/// Send a value if the channel is not full.
+pub fn try_send(a: &mut [Var]) -> Result<(), VmError> {
+    send_impl(a, Wait::No)
+}
+
+/// Receive a value, and block while the channel is empty.
+pub fn recv(a: &mut [Var]) -> Result<(), VmError> {
+    let wait = wait(a, 1)?;
+    recv_impl(a, wait)
+}
+
+/// Receive a value if the channel is not empty.
+pub fn try_recv(a: &mut [Var]) -> Result<(), VmError> {
+    recv_impl(a, Wait::No)
+}
+
+/// Receive a value from the first ready channel of a vector of channels. Returns the status, the
+/// index of the channel, and the value. If no channel has a value, but a channel is closed, or a
+/// channel doesn't exist, its index is returned with the status.
+pub fn select(a: &mut [Var]) -> Result<(), VmError> {
+    let ids = match unsafe { a.get_unchecked(0) } {
+        Var::Vector(x) => x.borrow()?.clone(),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
+    };
+    let wait = wait(a, 1)?;
+    let mut chans = Vec::with_capacity(ids.len());
+    for (i, x) in ids.iter().enumerate() {
+        match x.as_u64_strict().and_then(get) {
+            Some(y) => chans.push(y),
+            None => {
+                *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(io_error::OTHER);
+                *(unsafe { a.get_unchecked_mut(1) }) = Var::U64(i as u64);
+                return Ok(());
+            }
+        }
+    }
+    let signal = Arc::new(Signal::default());
+    // The signal is registered before channels are checked, so no value is missed.
+    for i in chans.iter() {
+        i.state.lock().selectors.push(signal.clone());
+    }
+    let (status, index) = 'select: loop {
+        let mut closed = None;
+        for (i, x) in chans.iter().enumerate() {
+            match pop(x, &mut x.state.lock()) {
+                Ok(y) => {
+                    *(unsafe { a.get_unchecked_mut(2) }) = y;
+                    break 'select (0, i);
+                }
+                Err(io_error::WOULD_BLOCK) => (),
+                Err(y) => closed = closed.or(Some((y, i))),
+            }
+        }
+        if let Some(x) = closed {
+            break x;
+        }
+        let mut fired = signal.fired.lock();
+        if !*fired && !wait.wait(&signal.cv, &mut fired) {
+            break match wait {
+                Wait::No => (io_error::WOULD_BLOCK, 0),
+                _ => (io_error::TIMED_OUT, 0),
+            };
+        }
+        *fired = false;
+    };
+    for i in chans.iter() {
+        i.state
+            .lock()
+            .selectors
+            .retain(|x| !Arc::ptr_eq(x, &signal));
+    }
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(status);
+    *(unsafe { a.get_unchecked_mut(1) }) = Var::U64(index as u64);
+    Ok(())
+}
+
+/// Close a channel.
+pub fn close(a: &mut [Var]) -> Result<(), VmError> {
+    if let Some(x) = get(id(a)?) {
+        let mut state = x.state.lock();
+        state.closed = true;
+        x.wake_receivers(&state, true);
+        x.writable.notify_all();
+    }
+    Ok(())
+}
+
+/// Judge if a channel is closed. A dropped channel is closed.
+pub fn is_closed(a: &mut [Var]) -> Result<(), VmError> {
+    let closed = match get(id(a)?) {
+        Some(x) => x.state.lock().closed,
+        None => true,
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(closed as u8);
+    Ok(())
+}
+
+/// Get the count of values that haven't been received.
+pub fn len(a: &mut [Var]) -> Result<(), VmError> {
+    let len = match get(id(a)?) {
+        Some(x) => x.state.lock().queue.len(),
+        None => 0,
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(len as u64);
+    Ok(())
+}
+
+/// Drop a channel. Threads blocked on it are woken as if it's closed.
+pub fn drop(a: &mut [Var]) -> Result<(), VmError> {
+    let id = id(a)?;
+    if let Some((_, x)) = TABLE.remove(&id) {
+        IDGEN.lock().free(id);
+        let mut state = x.state.lock();
+        state.closed = true;
+        x.wake_receivers(&state, true);
+        x.writable.notify_all();
+    }
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    IDGEN.lock().next();
+    putnfp("raw::thrd::chan::new", new);
+    putnfp("raw::thrd::chan::send", send);
+    putnfp("raw::thrd::chan::try_send", try_send);
+    putnfp("raw::thrd::chan::recv", recv);
+    putnfp("raw::thrd::chan::try_recv", try_recv);
+    putnfp("raw::thrd::chan::select", select);
+    putnfp("raw::thrd::chan::close", close);
+    putnfp("raw::thrd::chan::is_closed", is_closed);
+    putnfp("raw::thrd::chan::len", len);
+    putnfp("raw::thrd::chan::drop", drop);
+}
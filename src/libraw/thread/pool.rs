@@ -0,0 +1,297 @@
+//!
+//! Thread pools and futures of `libraw`.
+//!
+//! A pool has a fixed number of worker threads, which execute submitted functions in order. Each
+//! submission returns a future, which keeps the return registers of the function or the
+//! interruption that terminated it, until it's taken by `raw::thrd::future::get`. Like spawned
+//! threads, an abort in a submitted function only ends the function, unless aborts are configured
+//! to exit the process. Locks held by a function are released when it ends.
+//!
+
+use super::{panicked, put_outcome, sync::Wait};
+use crate::{
+    context::{getfp, putnfp, ExecUnit, Thread},
+    error::VmError,
+    executor,
+    isa::FuncPtr,
+    libraw::iohmgr::{FakeHasher, IdGen},
+    vmem::Var,
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use parking_lot::{const_mutex, Condvar, Mutex};
+use std::{
+    collections::VecDeque,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    thread::JoinHandle,
+};
+
+/// A submitted function.
+struct Job {
+    thread: Thread,
+    future: Arc<Future>,
+}
+
+/// State of a pool.
+#[derive(Default)]
+struct State {
+    /// Jobs which haven't been picked by a worker.
+    queue: VecDeque<Job>,
+    /// If the pool is shutting down.
+    closed: bool,
+}
+
+/// A thread pool.
+#[derive(Default)]
+struct Pool {
+    state: Mutex<State>,
+    available: Condvar,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+impl Pool {
+    /// Execute jobs until the pool is shut down and the queue is empty.
+    fn work(&self) {
+        loop {
+            let job = {
+                let mut state = self.state.lock();
+                loop {
+                    if let Some(x) = state.queue.pop_front() {
+                        break x;
+                    }
+                    if state.closed {
+                        return;
+                    }
+                    self.available.wait(&mut state);
+                }
+            };
+            let Job { thread, future } = job;
+            let outcome = catch_unwind(AssertUnwindSafe(|| executor::start_thread(thread)))
+                .unwrap_or_else(|x| Err(panicked(x)));
+            future.finish(outcome);
+        }
+    }
+}
+
+/// A future of a submitted function.
+#[derive(Default)]
+struct Future {
+    /// The outcome, which is `Some` if the function has finished.
+    outcome: Mutex<Option<Result<Vec<Var>, VmError>>>,
+    finished: Condvar,
+}
+impl Future {
+    /// Keep the outcome, and wake threads waiting for it.
+    fn finish(&self, outcome: Result<Vec<Var>, VmError>) {
+        *self.outcome.lock() = Some(outcome);
+        self.finished.notify_all();
+    }
+    /// Wait until the function finishes. Returns `false` if it has timed out.
+    fn wait(&self, wait: Wait) -> bool {
+        let mut outcome = self.outcome.lock();
+        while outcome.is_none() {
+            if !wait.wait(&self.finished, &mut outcome) {
+                return outcome.is_some();
+            }
+        }
+        true
+    }
+}
+
+/// ID generator for pools and futures.
+static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
+/// Table of pools.
+static POOLS: Lazy<DashMap<u64, Arc<Pool>, FakeHasher>> = Lazy::new(DashMap::default);
+/// Table of futures.
+static FUTURES: Lazy<DashMap<u64, Arc<Future>, FakeHasher>> = Lazy::new(DashMap::default);
+
+/// Get an ID argument.
+#[inline]
+fn arg_id(a: &[Var], i: usize) -> Result<u64, VmError> {
+    unsafe { a.get_unchecked(i) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Get a future. The future is cloned, so the table is not locked while it's being waited.
+#[inline]
+fn future(id: u64) -> Option<Arc<Future>> {
+    FUTURES.get(&id).map(|x| x.clone())
+}
+
+/// Create a pool with specified number of workers, where 0 means the number of CPUs. Returns 1
+/// and the pool ID if the workers are started.
+pub fn new(a: &mut [Var]) -> Result<(), VmError> {
+    let n = match unsafe { a.get_unchecked(0) }
+        .as_usize()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?
+    {
+        0 => num_cpus::get(),
+        x => x,
+    };
+    let id = IDGEN.lock().next();
+    let pool = Arc::new(Pool::default());
+    let mut workers = Vec::with_capacity(n);
+    for i in 0..n {
+        let worker = pool.clone();
+        match std::thread::Builder::new()
+            .name(format!("pool-{}-{}", id, i))
+            .spawn(move || worker.work())
+        {
+            Ok(x) => workers.push(x),
+            Err(_) => {
+                pool.state.lock().closed = true;
+                pool.available.notify_all();
+                IDGEN.lock().free(id);
+                *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(0);
+                return Ok(());
+            }
+        }
+    }
+    *pool.workers.lock() = workers;
+    POOLS.insert(id, pool);
+    unsafe {
+        *a.get_unchecked_mut(0) = Var::U8(1);
+        *a.get_unchecked_mut(1) = Var::U64(id);
+    }
+    Ok(())
+}
+
+/// Submit a function by name with a vector of arguments to a pool. Returns 1 and the future ID,
+/// or 0 if the pool doesn't exist or is shutting down.
+pub fn submit(a: &mut [Var]) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    let fp = unsafe { a.get_unchecked(1) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let mut thread = Thread::new(match getfp(&fp.borrow()?) {
+        Some(FuncPtr::Virtual(x)) => x,
+        _ => return Err(VmError::new("raw::fatal::segfault")),
+    });
+    match unsafe { a.get_unchecked(2) } {
+        Var::Vector(x) => {
+            let args = x.borrow()?;
+            if args.len() > 50 {
+                return Err(VmError::new("raw::fatal::segfault"));
+            }
+            for (i, y) in args.iter().enumerate() {
+                thread.sset(100 + i, y.to_owned())?;
+            }
+        }
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
+    }
+    let pool = match POOLS.get(&id) {
+        Some(x) => x.clone(),
+        None => {
+            *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(0);
+            return Ok(());
+        }
+    };
+    let mut state = pool.state.lock();
+    if state.closed {
+        *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(0);
+        return Ok(());
+    }
+    let future = Arc::new(Future::default());
+    let fid = IDGEN.lock().next();
+    FUTURES.insert(fid, future.clone());
+    state.queue.push_back(Job { thread, future });
+    pool.available.notify_one();
+    unsafe {
+        *a.get_unchecked_mut(0) = Var::U8(1);
+        *a.get_unchecked_mut(1) = Var::U64(fid);
+    }
+    Ok(())
+}
+
+/// Shut a pool down. Submitted functions are still executed, and this blocks until all of them
+/// have finished. Returns 0 if the pool doesn't exist.
+pub fn shutdown(a: &mut [Var]) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    let pool = match POOLS.remove(&id) {
+        Some((_, x)) => x,
+        None => {
+            *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(0);
+            return Ok(());
+        }
+    };
+    IDGEN.lock().free(id);
+    pool.state.lock().closed = true;
+    pool.available.notify_all();
+    let workers = std::mem::take(&mut *pool.workers.lock());
+    let current = std::thread::current().id();
+    for i in workers {
+        // A worker shutting its own pool down can't wait for itself.
+        if i.thread().id() != current {
+            i.join().ok();
+        }
+    }
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(1);
+    Ok(())
+}
+
+/// Wait for a future with a timeout in milliseconds at 1, where 0 means no timeout. Returns 1 if
+/// the function has finished, or 0 if it has timed out or the future doesn't exist.
+pub fn wait(a: &mut [Var]) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    let wait = unsafe { a.get_unchecked(1) }
+        .as_u64()
+        .map(Wait::from_ms)
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    let done = match future(id) {
+        Some(x) => x.wait(wait),
+        None => false,
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(done as u8);
+    Ok(())
+}
+
+/// Judge if the function of a future has finished.
+pub fn poll(a: &mut [Var]) -> Result<(), VmError> {
+    let done = match future(arg_id(a, 0)?) {
+        Some(x) => x.wait(Wait::No),
+        None => false,
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(done as u8);
+    Ok(())
+}
+
+/// Take the outcome of a future, blocking until the function finishes, and drop the future. Like
+/// `raw::thrd::join`, returns 1 and a vector of return registers, or 0 and a description of the
+/// interruption that terminated the function.
+pub fn get(a: &mut [Var]) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    let outcome = match FUTURES.remove(&id) {
+        Some((_, x)) => {
+            IDGEN.lock().free(id);
+            x.wait(Wait::Forever);
+            let outcome = x.outcome.lock().take();
+            outcome.unwrap_or_else(|| Err(VmError::new("raw::fatal::segfault")))
+        }
+        None => Err(VmError::new("raw::thrd::future::not_found")
+            .with_message("the future doesn't exist or has been taken")),
+    };
+    put_outcome(a, outcome)
+}
+
+/// Drop a future without taking its outcome. The function is still executed.
+pub fn drop(a: &mut [Var]) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    if FUTURES.remove(&id).is_some() {
+        IDGEN.lock().free(id);
+    }
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    IDGEN.lock().next();
+    putnfp("raw::thrd::pool::new", new);
+    putnfp("raw::thrd::pool::submit", submit);
+    putnfp("raw::thrd::pool::shutdown", shutdown);
+    putnfp("raw::thrd::future::wait", wait);
+    putnfp("raw::thrd::future::poll", poll);
+    putnfp("raw::thrd::future::get", get);
+    putnfp("raw::thrd::future::drop", drop);
+}
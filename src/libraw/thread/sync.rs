@@ -0,0 +1,592 @@
+//!
+//! Synchronization primitives of `libraw`.
+//!
+//! Mutexes and read-write locks share one table, because a mutex is a read-write lock that is only
+//! locked for writing. Locks are owned by VM threads, which are also coroutines, tasks, and
+//! functions executed by pools, timers and callbacks, so a task may still hold a lock after it's
+//! resumed by another OS thread. Interruption handlers act on behalf of the interrupted thread.
+//! Locks held by a thread are released when it ends, even if it ends because of an interruption.
+//!
+//! Locking blocks the OS thread, so a coroutine or a task waiting for a lock held by another one
+//! also blocks the ones which share the OS thread with it.
+//!
+//! Blocking operations take an optional timeout in milliseconds at the last argument register,
+//! where 0 means no timeout.
+//!
+
+use crate::{
+    context::{self, getfp, putnfp, Thread},
+    error::VmError,
+    executor,
+    isa::FuncPtr,
+    libraw::iohmgr::{FakeHasher, IdGen},
+    vmem::Var,
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use parking_lot::{const_mutex, Condvar, Mutex};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How long a blocking operation waits.
+#[derive(Clone, Copy)]
+pub(super) enum Wait {
+    /// Don't wait.
+    No,
+    /// Wait until the deadline.
+    Until(Instant),
+    /// Wait forever.
+    Forever,
+}
+impl Wait {
+    /// Get the wait from a timeout in milliseconds, where 0 means no timeout.
+    #[inline]
+    pub(super) fn from_ms(ms: u64) -> Self {
+        match ms {
+            0 => Self::Forever,
+            x => Self::Until(Instant::now() + Duration::from_millis(x)),
+        }
+    }
+    /// Wait for `cv` with `guard`. Returns `false` if it has timed out.
+    #[inline]
+    pub(super) fn wait<T>(self, cv: &Condvar, guard: &mut parking_lot::MutexGuard<'_, T>) -> bool {
+        match self {
+            Self::No => false,
+            Self::Until(x) => !cv.wait_until(guard, x).timed_out(),
+            Self::Forever => {
+                cv.wait(guard);
+                true
+            }
+        }
+    }
+}
+
+/// State of a lock.
+#[derive(Default)]
+struct LockState {
+    /// Owner token of the thread that holds the lock for writing, or 0.
+    writer: u64,
+    /// Owner tokens of threads that hold the lock for reading. A thread may appear multiple times.
+    readers: Vec<u64>,
+}
+
+/// A mutex or a read-write lock.
+#[derive(Default)]
+struct Lock {
+    state: Mutex<LockState>,
+    cv: Condvar,
+}
+impl Lock {
+    /// Lock this for writing or reading. Returns `false` if it has timed out.
+    fn lock(&self, id: u64, write: bool, wait: Wait) -> Result<bool, VmError> {
+        let me = token();
+        let mut state = self.state.lock();
+        if state.writer == me || (write && state.readers.contains(&me)) {
+            if let Wait::No = wait {
+                return Ok(false);
+            }
+            return Err(VmError::new("raw::thrd::deadlock")
+                .with_message("the lock is already held by this thread"));
+        }
+        loop {
+            let free = state.writer == 0 && (!write || state.readers.is_empty());
+            if free {
+                break;
+            }
+            if !wait.wait(&self.cv, &mut state) {
+                return Ok(false);
+            }
+        }
+        if write {
+            state.writer = me;
+        } else {
+            state.readers.push(me);
+        }
+        HELD.entry(me).or_default().push(id);
+        Ok(true)
+    }
+    /// Unlock this once. Returns whether it was held for writing, or `None` if this thread doesn't
+    /// hold it.
+    fn unlock(&self, id: u64) -> Option<bool> {
+        let me = token();
+        let mut state = self.state.lock();
+        let write = if state.writer == me {
+            state.writer = 0;
+            true
+        } else if let Some(x) = state.readers.iter().position(|x| *x == me) {
+            state.readers.swap_remove(x);
+            false
+        } else {
+            return None;
+        };
+        drop(state);
+        self.cv.notify_all();
+        if let Some(mut x) = HELD.get_mut(&me) {
+            if let Some(y) = x.iter().rposition(|y| *y == id) {
+                x.swap_remove(y);
+            }
+        }
+        HELD.remove_if(&me, |_, x| x.is_empty());
+        Some(write)
+    }
+    /// Release this from a thread entirely.
+    fn release(&self, owner: u64) {
+        let mut state = self.state.lock();
+        if state.writer == owner {
+            state.writer = 0;
+        }
+        state.readers.retain(|x| *x != owner);
+        drop(state);
+        self.cv.notify_all();
+    }
+}
+
+/// A condition variable.
+#[derive(Default)]
+struct Cond {
+    /// Count of notifications, which tells waiters if they are notified.
+    seq: Mutex<u64>,
+    cv: Condvar,
+}
+
+/// A semaphore.
+#[derive(Default)]
+struct Semaphore {
+    permits: Mutex<u64>,
+    cv: Condvar,
+}
+
+/// A barrier.
+struct Barrier {
+    /// Count of threads to wait for.
+    n: u64,
+    /// Count of waiting threads, and the generation.
+    state: Mutex<(u64, u64)>,
+    cv: Condvar,
+}
+
+/// A once cell.
+#[derive(Default)]
+struct Once {
+    /// 0 if it's not called, 1 if it's running, or 2 if it has finished.
+    state: Mutex<u8>,
+    cv: Condvar,
+}
+
+/// ID Generator for synchronization primitives.
+static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
+/// Table of mutexes and read-write locks.
+static LOCKS: Lazy<DashMap<u64, Arc<Lock>, FakeHasher>> = Lazy::new(DashMap::default);
+/// Table of condition variables.
+static CONDS: Lazy<DashMap<u64, Arc<Cond>, FakeHasher>> = Lazy::new(DashMap::default);
+/// Table of semaphores.
+static SEMAPHORES: Lazy<DashMap<u64, Arc<Semaphore>, FakeHasher>> = Lazy::new(DashMap::default);
+/// Table of barriers.
+static BARRIERS: Lazy<DashMap<u64, Arc<Barrier>, FakeHasher>> = Lazy::new(DashMap::default);
+/// Table of once cells.
+static ONCES: Lazy<DashMap<u64, Arc<Once>, FakeHasher>> = Lazy::new(DashMap::default);
+
+/// IDs of locks held by threads, by owner tokens. A lock may appear multiple times.
+static HELD: Lazy<DashMap<u64, Vec<u64>, FakeHasher>> = Lazy::new(DashMap::default);
+
+/// Get the owner token of the executing thread.
+#[inline]
+fn token() -> u64 {
+    context::owner()
+}
+
+/// Release locks held by a thread which has ended.
+pub fn release(owner: u64) {
+    if let Some((_, x)) = HELD.remove(&owner) {
+        for i in x {
+            if let Some(y) = get(&LOCKS, i) {
+                y.release(owner);
+            }
+        }
+    }
+}
+
+/// Get an entry of a table. The entry is cloned, so the table is not locked while the entry is
+/// being waited.
+#[inline]
+fn get<T>(table: &DashMap<u64, Arc<T>, FakeHasher>, id: u64) -> Option<Arc<T>> {
+    table.get(&id).map(|x| x.clone())
+}
+
+/// Get an ID argument.
+#[inline]
+fn arg_id(a: &[Var], i: usize) -> Result<u64, VmError> {
+    unsafe { a.get_unchecked(i) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Get a timeout argument.
+#[inline]
+fn arg_wait(a: &[Var], i: usize) -> Result<Wait, VmError> {
+    unsafe { a.get_unchecked(i) }
+        .as_u64()
+        .map(Wait::from_ms)
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Put a status.
+#[inline]
+fn put(a: &mut [Var], stat: bool) {
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(stat as u8);
+}
+
+/// Create a new ID.
+#[inline]
+fn next_id(a: &mut [Var]) -> u64 {
+    let id = IDGEN.lock().next();
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
+    id
+}
+
+/// Lock a lock with `wait`.
+#[inline]
+fn lock_impl(a: &mut [Var], write: bool, wait: Wait) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    let stat = match get(&LOCKS, id) {
+        Some(x) => x.lock(id, write, wait)?,
+        None => false,
+    };
+    put(a, stat);
+    Ok(())
+}
+
+/// Create a new mutex or read-write lock.
+pub fn lock_new(a: &mut [Var]) -> Result<(), VmError> {
+    let id = next_id(a);
+    LOCKS.insert(id, Arc::default());
+    Ok(())
+}
+
+/// Lock a mutex, or lock a read-write lock for writing.
+pub fn lock(a: &mut [Var]) -> Result<(), VmError> {
+    let wait = arg_wait(a, 1)?;
+    lock_impl(a, true, wait)
+}
+
+/// Try to lock a mutex, or try to lock a read-write lock for writing.
+pub fn try_lock(a: &mut [Var]) -> Result<(), VmError> {
+    lock_impl(a, true, Wait::No)
+}
+
+/// Lock a read-write lock for reading.
+pub fn read(a: &mut [Var]) -> Result<(), VmError> {
+    let wait = arg_wait(a, 1)?;
+    lock_impl(a, false, wait)
+}
+
+/// Try to lock a read-write lock for reading.
+pub fn try_read(a: &mut [Var]) -> Result<(), VmError> {
+    lock_impl(a, false, Wait::No)
+}
+
+/// Unlock a mutex or read-write lock once.
+pub fn unlock(a: &mut [Var]) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    let stat = match get(&LOCKS, id) {
+        Some(x) => x.unlock(id).is_some(),
+        None => false,
+    };
+    put(a, stat);
+    Ok(())
+}
+
+/// Drop a mutex or read-write lock.
+pub fn lock_drop(a: &mut [Var]) -> Result<(), VmError> {
+    LOCKS.remove(&arg_id(a, 0)?);
+    Ok(())
+}
+
+/// Create a new condition variable.
+pub fn cond_new(a: &mut [Var]) -> Result<(), VmError> {
+    let id = next_id(a);
+    CONDS.insert(id, Arc::default());
+    Ok(())
+}
+
+/// Wait for a condition variable with a mutex held by this thread. The mutex is unlocked while
+/// waiting, and locked again in the same mode before this returns. Returns 0 if it has timed out, or the mutex
+/// is not held by this thread.
+pub fn cond_wait(a: &mut [Var]) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    let lock_id = arg_id(a, 1)?;
+    let wait = arg_wait(a, 2)?;
+    let cond = match get(&CONDS, id) {
+        Some(x) => x,
+        None => {
+            put(a, false);
+            return Ok(());
+        }
+    };
+    let lock = match get(&LOCKS, lock_id) {
+        Some(x) => x,
+        None => {
+            put(a, false);
+            return Ok(());
+        }
+    };
+    let mut seq = cond.seq.lock();
+    let old = *seq;
+    let write = match lock.unlock(lock_id) {
+        Some(x) => x,
+        None => {
+            put(a, false);
+            return Ok(());
+        }
+    };
+    let mut notified = true;
+    while *seq == old {
+        if !wait.wait(&cond.cv, &mut seq) {
+            notified = false;
+            break;
+        }
+    }
+    drop(seq);
+    lock.lock(lock_id, write, Wait::Forever)?;
+    put(a, notified);
+    Ok(())
+}
+
+/// Wake one thread waiting for a condition variable.
+pub fn cond_notify_one(a: &mut [Var]) -> Result<(), VmError> {
+    if let Some(x) = get(&CONDS, arg_id(a, 0)?) {
+        *x.seq.lock() += 1;
+        x.cv.notify_one();
+    }
+    Ok(())
+}
+
+/// Wake all threads waiting for a condition variable.
+pub fn cond_notify_all(a: &mut [Var]) -> Result<(), VmError> {
+    if let Some(x) = get(&CONDS, arg_id(a, 0)?) {
+        *x.seq.lock() += 1;
+        x.cv.notify_all();
+    }
+    Ok(())
+}
+
+/// Drop a condition variable.
+pub fn cond_drop(a: &mut [Var]) -> Result<(), VmError> {
+    CONDS.remove(&arg_id(a, 0)?);
+    Ok(())
+}
+
+/// Create a new semaphore with specified count of permits.
+pub fn sem_new(a: &mut [Var]) -> Result<(), VmError> {
+    let permits = arg_id(a, 0)?;
+    let id = next_id(a);
+    SEMAPHORES.insert(
+        id,
+        Arc::new(Semaphore {
+            permits: Mutex::new(permits),
+            cv: Condvar::new(),
+        }),
+    );
+    Ok(())
+}
+
+/// Acquire a permit of a semaphore with `wait`.
+#[inline]
+fn sem_acquire_impl(a: &mut [Var], wait: Wait) -> Result<(), VmError> {
+    let sem = match get(&SEMAPHORES, arg_id(a, 0)?) {
+        Some(x) => x,
+        None => {
+            put(a, false);
+            return Ok(());
+        }
+    };
+    let mut permits = sem.permits.lock();
+    let mut stat = true;
+    while *permits == 0 {
+        if !wait.wait(&sem.cv, &mut permits) {
+            stat = false;
+            break;
+        }
+    }
+    if stat {
+        *permits -= 1;
+    }
+    drop(permits);
+    drop(sem);
+    put(a, stat);
+    Ok(())
+}
+
+/// Acquire a permit of a semaphore.
+pub fn sem_acquire(a: &mut [Var]) -> Result<(), VmError> {
+    let wait = arg_wait(a, 1)?;
+    sem_acquire_impl(a, wait)
+}
+
+/// Try to acquire a permit of a semaphore.
+pub fn sem_try_acquire(a: &mut [Var]) -> Result<(), VmError> {
+    sem_acquire_impl(a, Wait::No)
+}
+
+/// Release a permit of a semaphore.
+pub fn sem_release(a: &mut [Var]) -> Result<(), VmError> {
+    if let Some(x) = get(&SEMAPHORES, arg_id(a, 0)?) {
+        *x.permits.lock() += 1;
+        x.cv.notify_one();
+    }
+    Ok(())
+}
+
+/// Drop a semaphore.
+pub fn sem_drop(a: &mut [Var]) -> Result<(), VmError> {
+    SEMAPHORES.remove(&arg_id(a, 0)?);
+    Ok(())
+}
+
+/// Create a new barrier for specified count of threads.
+pub fn barrier_new(a: &mut [Var]) -> Result<(), VmError> {
+    let n = arg_id(a, 0)?.max(1);
+    let id = next_id(a);
+    BARRIERS.insert(
+        id,
+        Arc::new(Barrier {
+            n,
+            state: Mutex::new((0, 0)),
+            cv: Condvar::new(),
+        }),
+    );
+    Ok(())
+}
+
+/// Wait until all threads have reached a barrier. Returns 1 for the last thread that reached it, or
+/// 0 for other threads.
+pub fn barrier_wait(a: &mut [Var]) -> Result<(), VmError> {
+    let barrier = match get(&BARRIERS, arg_id(a, 0)?) {
+        Some(x) => x,
+        None => {
+            put(a, false);
+            return Ok(());
+        }
+    };
+    let mut state = barrier.state.lock();
+    state.0 += 1;
+    let leader = state.0 == barrier.n;
+    if leader {
+        *state = (0, state.1.wrapping_add(1));
+        barrier.cv.notify_all();
+    } else {
+        let generation = state.1;
+        while state.1 == generation {
+            barrier.cv.wait(&mut state);
+        }
+    }
+    drop(state);
+    drop(barrier);
+    put(a, leader);
+    Ok(())
+}
+
+/// Drop a barrier.
+pub fn barrier_drop(a: &mut [Var]) -> Result<(), VmError> {
+    BARRIERS.remove(&arg_id(a, 0)?);
+    Ok(())
+}
+
+/// Create a new once cell.
+pub fn once_new(a: &mut [Var]) -> Result<(), VmError> {
+    let id = next_id(a);
+    ONCES.insert(id, Arc::default());
+    Ok(())
+}
+
+/// Call a function by name if the once cell has never been called, or wait until the call has
+/// finished. Returns 1 if this call executed the function. If the function is terminated by an
+/// interruption, 2 and a description of the interruption are returned like `raw::thrd::join`, and
+/// the once cell is left uncalled, so a waiting thread calls it again. Like spawned threads, an
+/// abort in the function only ends the function, unless aborts are configured to exit the process.
+pub fn once_call(a: &mut [Var]) -> Result<(), VmError> {
+    let id = arg_id(a, 0)?;
+    let fp = unsafe { a.get_unchecked(1) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let fp = match getfp(&fp.borrow()?) {
+        Some(FuncPtr::Virtual(x)) => x,
+        _ => return Err(VmError::new("raw::fatal::segfault")),
+    };
+    let once = match get(&ONCES, id) {
+        Some(x) => x,
+        None => {
+            put(a, false);
+            return Ok(());
+        }
+    };
+    let mut state = once.state.lock();
+    while *state == 1 {
+        once.cv.wait(&mut state);
+    }
+    if *state == 2 {
+        drop(state);
+        drop(once);
+        put(a, false);
+        return Ok(());
+    }
+    *state = 1;
+    drop(state);
+    let result = executor::start_thread(Thread::new(fp));
+    *once.state.lock() = match result {
+        Ok(_) => 2,
+        Err(_) => 0,
+    };
+    once.cv.notify_all();
+    drop(once);
+    match result {
+        Ok(_) => put(a, true),
+        Err(x) => unsafe {
+            *a.get_unchecked_mut(1) = super::failure(&x)?;
+            *a.get_unchecked_mut(0) = Var::U8(2);
+        },
+    }
+    Ok(())
+}
+
+/// Drop a once cell.
+pub fn once_drop(a: &mut [Var]) -> Result<(), VmError> {
+    ONCES.remove(&arg_id(a, 0)?);
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    IDGEN.lock().next();
+    putnfp("raw::thrd::mutex::new", lock_new);
+    putnfp("raw::thrd::mutex::lock", lock);
+    putnfp("raw::thrd::mutex::try_lock", try_lock);
+    putnfp("raw::thrd::mutex::unlock", unlock);
+    putnfp("raw::thrd::mutex::drop", lock_drop);
+    putnfp("raw::thrd::rwlock::new", lock_new);
+    putnfp("raw::thrd::rwlock::read", read);
+    putnfp("raw::thrd::rwlock::try_read", try_read);
+    putnfp("raw::thrd::rwlock::write", lock);
+    putnfp("raw::thrd::rwlock::try_write", try_lock);
+    putnfp("raw::thrd::rwlock::unlock", unlock);
+    putnfp("raw::thrd::rwlock::drop", lock_drop);
+    putnfp("raw::thrd::condvar::new", cond_new);
+    putnfp("raw::thrd::condvar::wait", cond_wait);
+    putnfp("raw::thrd::condvar::notify_one", cond_notify_one);
+    putnfp("raw::thrd::condvar::notify_all", cond_notify_all);
+    putnfp("raw::thrd::condvar::drop", cond_drop);
+    putnfp("raw::thrd::semaphore::new", sem_new);
+    putnfp("raw::thrd::semaphore::acquire", sem_acquire);
+    putnfp("raw::thrd::semaphore::try_acquire", sem_try_acquire);
+    putnfp("raw::thrd::semaphore::release", sem_release);
+    putnfp("raw::thrd::semaphore::drop", sem_drop);
+    putnfp("raw::thrd::barrier::new", barrier_new);
+    putnfp("raw::thrd::barrier::wait", barrier_wait);
+    putnfp("raw::thrd::barrier::drop", barrier_drop);
+    putnfp("raw::thrd::once::new", once_new);
+    putnfp("raw::thrd::once::call", once_call);
+    putnfp("raw::thrd::once::drop", once_drop);
+}
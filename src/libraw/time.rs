@@ -1,13 +1,38 @@
 //!
 //! Utilities for getting/formatting time of `libraw`.
 //!
+//! Wall-clock timestamps may jump, so durations should be measured with the monotonic clock,
+//! which counts nanoseconds from an unspecified point before the first reading. Durations are
+//! unsigned nanoseconds, and their arithmetic saturates instead of overflowing.
+//!
+
+pub mod calendar;
+pub mod timer;
 
-use crate::{context::putnfp, vmem::Var};
-use anyhow::anyhow;
+use crate::{context::putnfp, error::VmError, vmem::Var};
 use chrono::prelude::*;
+use once_cell::sync::Lazy;
+use std::{fmt::Write, time::Instant};
+
+/// Origin of the monotonic clock.
+static ORIGIN: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Get nanoseconds elapsed since the origin of the monotonic clock.
+#[inline]
+fn mono() -> u64 {
+    ORIGIN.elapsed().as_nanos().min(u64::MAX as u128) as u64
+}
+
+/// Get the duration argument at `i`.
+#[inline]
+fn arg_dur(a: &[Var], i: usize) -> Result<u64, VmError> {
+    unsafe { a.get_unchecked(i) }
+        .as_u64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
 
 /// Get UNIX timestamp.
-pub fn gettime(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn gettime(a: &mut [Var]) -> Result<(), VmError> {
     unsafe {
         *a.get_unchecked_mut(0) = Var::I64(Utc::now().timestamp());
     }
@@ -15,7 +40,7 @@ pub fn gettime(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get UNIX timestamp.
-pub fn gettime_ms(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn gettime_ms(a: &mut [Var]) -> Result<(), VmError> {
     unsafe {
         *a.get_unchecked_mut(0) = Var::I64(Utc::now().timestamp_millis());
     }
@@ -23,7 +48,7 @@ pub fn gettime_ms(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get UNIX timestamp.
-pub fn gettime_ns(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn gettime_ns(a: &mut [Var]) -> Result<(), VmError> {
     unsafe {
         *a.get_unchecked_mut(0) = Var::I64(Utc::now().timestamp_nanos());
     }
@@ -31,7 +56,7 @@ pub fn gettime_ns(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get UNIX timestamp.
-pub fn gettime_lms(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn gettime_lms(a: &mut [Var]) -> Result<(), VmError> {
     unsafe {
         *a.get_unchecked_mut(0) = Var::I64(Local::now().timestamp_millis());
     }
@@ -39,7 +64,7 @@ pub fn gettime_lms(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get UNIX timestamp.
-pub fn gettime_l(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn gettime_l(a: &mut [Var]) -> Result<(), VmError> {
     unsafe {
         *a.get_unchecked_mut(0) = Var::I64(Local::now().timestamp());
     }
@@ -47,37 +72,116 @@ pub fn gettime_l(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Get UNIX timestamp.
-pub fn gettime_lns(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn gettime_lns(a: &mut [Var]) -> Result<(), VmError> {
     unsafe {
         *a.get_unchecked_mut(0) = Var::I64(Local::now().timestamp_nanos());
     }
     Ok(())
 }
 
+/// Get the monotonic clock in nanoseconds.
+pub fn mono_ns(a: &mut [Var]) -> Result<(), VmError> {
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(mono());
+    Ok(())
+}
+
+/// Get the monotonic clock in milliseconds.
+pub fn mono_ms(a: &mut [Var]) -> Result<(), VmError> {
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(mono() / 1_000_000);
+    Ok(())
+}
+
+/// Get nanoseconds elapsed since a reading of the monotonic clock in nanoseconds.
+pub fn elapsed(a: &mut [Var]) -> Result<(), VmError> {
+    let since = arg_dur(a, 0)?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(mono().saturating_sub(since));
+    Ok(())
+}
+
+/// Add two durations.
+pub fn dur_add(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_dur(a, 0)?.saturating_add(arg_dur(a, 1)?);
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(result);
+    Ok(())
+}
+
+/// Subtract a duration from another. Returns 0 if the result is negative.
+pub fn dur_sub(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_dur(a, 0)?.saturating_sub(arg_dur(a, 1)?);
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(result);
+    Ok(())
+}
+
+/// Multiply a duration by an integer.
+pub fn dur_mul(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_dur(a, 0)?.saturating_mul(arg_dur(a, 1)?);
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(result);
+    Ok(())
+}
+
+/// Divide a duration by an integer.
+pub fn dur_div(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_dur(a, 0)?
+        .checked_div(arg_dur(a, 1)?)
+        .ok_or_else(|| VmError::new("raw::fatal::divide_zero"))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(result);
+    Ok(())
+}
+
+/// Convert seconds to a duration.
+pub fn dur_from_s(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_dur(a, 0)?.saturating_mul(1_000_000_000);
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(result);
+    Ok(())
+}
+
+/// Convert milliseconds to a duration.
+pub fn dur_from_ms(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_dur(a, 0)?.saturating_mul(1_000_000);
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(result);
+    Ok(())
+}
+
+/// Convert a duration to whole seconds.
+pub fn dur_as_s(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_dur(a, 0)? / 1_000_000_000;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(result);
+    Ok(())
+}
+
+/// Convert a duration to whole milliseconds.
+pub fn dur_as_ms(a: &mut [Var]) -> Result<(), VmError> {
+    let result = arg_dur(a, 0)? / 1_000_000;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(result);
+    Ok(())
+}
+
 /// Format UNIX timestamp.
-pub fn tformat(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn tformat(a: &mut [Var]) -> Result<(), VmError> {
     let ts = unsafe { a.get_unchecked(0) }
         .as_i64()
-        .ok_or_else(|| anyhow!("raw::fatal::not_an_integer"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
     let fmt = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let fmt = fmt.borrow()?;
-    let result = NaiveDateTime::from_timestamp(ts, 0)
-        .format(&fmt[..])
-        .to_string();
+    let dt = DateTime::<Utc>::from_timestamp(ts, 0)
+        .ok_or_else(|| VmError::new("raw::fatal::out_of_range"))?;
+    let mut result = String::new();
+    write!(result, "{}", dt.format(&fmt[..]))
+        .map_err(|_| VmError::new("raw::fatal::invalid").with_message("invalid format"))?;
     *(unsafe { a.get_unchecked_mut(0) }) = Var::UString(result.into());
     Ok(())
 }
 
 /// Parse string-formatted time.
-pub fn parse_s(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn parse_s(a: &mut [Var]) -> Result<(), VmError> {
     let src = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let fmt = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let src = src.borrow()?;
     let fmt = fmt.borrow()?;
     let result = NaiveDateTime::parse_from_str(&src[..], &fmt[..]);
@@ -94,13 +198,13 @@ pub fn parse_s(a: &mut [Var]) -> Result<(), anyhow::Error> {
 }
 
 /// Parse string-formatted time.
-pub fn parse_ms(a: &mut [Var]) -> Result<(), anyhow::Error> {
+pub fn parse_ms(a: &mut [Var]) -> Result<(), VmError> {
     let src = unsafe { a.get_unchecked(0) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let fmt = unsafe { a.get_unchecked(1) }
         .as_sr()
-        .ok_or_else(|| anyhow!("raw::fatal::not_a_buf"))?;
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
     let src = src.borrow()?;
     let fmt = fmt.borrow()?;
     let result = NaiveDateTime::parse_from_str(&src[..], &fmt[..]);
@@ -119,13 +223,27 @@ pub fn parse_ms(a: &mut [Var]) -> Result<(), anyhow::Error> {
 /// Initialize the library.
 #[inline(always)]
 pub fn init() {
+    Lazy::force(&ORIGIN);
     putnfp("raw::time::get<utc,sec>", gettime);
     putnfp("raw::time::get<utc,msec>", gettime_ms);
     putnfp("raw::time::get<utc,nsec>", gettime_ns);
     putnfp("raw::time::get<local,msec>", gettime_lms);
     putnfp("raw::time::get<local,sec>", gettime_l);
     putnfp("raw::time::get<local,nsec>", gettime_lns);
+    putnfp("raw::time::mono<nsec>", mono_ns);
+    putnfp("raw::time::mono<msec>", mono_ms);
+    putnfp("raw::time::elapsed<nsec>", elapsed);
+    putnfp("raw::time::dur::add", dur_add);
+    putnfp("raw::time::dur::sub", dur_sub);
+    putnfp("raw::time::dur::mul", dur_mul);
+    putnfp("raw::time::dur::div", dur_div);
+    putnfp("raw::time::dur::from<sec>", dur_from_s);
+    putnfp("raw::time::dur::from<msec>", dur_from_ms);
+    putnfp("raw::time::dur::as<sec>", dur_as_s);
+    putnfp("raw::time::dur::as<msec>", dur_as_ms);
     putnfp("raw::time::format", tformat);
     putnfp("raw::str::parse<sec>", parse_s);
     putnfp("raw::str::parse<msec>", parse_ms);
+    calendar::init();
+    timer::init();
 }
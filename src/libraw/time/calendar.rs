@@ -0,0 +1,285 @@
+//!
+//! Calendar of `libraw`.
+//!
+//! Timestamps are UNIX timestamps in seconds, like other time natives. A time zone argument is a
+//! string, which is `utc`, `local`, or a fixed offset like `+08:00`. Local time follows the rules
+//! of the system, so a local date and time may be skipped or repeated when the offset changes; a
+//! skipped one can't be converted to a timestamp, and the earlier one of a repeated one is used.
+//!
+
+use crate::{
+    context::putnfp,
+    error::VmError,
+    vmem::{ObjectRef, Var},
+};
+use chrono::prelude::*;
+use chrono::{Days, Months};
+use std::{convert::TryFrom, fmt::Write};
+
+/// A time zone.
+#[derive(Clone, Copy)]
+enum Zone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+impl Zone {
+    /// Get the date and time of a timestamp in this zone.
+    fn at(self, ts: i64) -> Option<DateTime<FixedOffset>> {
+        let utc = DateTime::<Utc>::from_timestamp(ts, 0)?;
+        Some(match self {
+            Self::Utc => utc.fixed_offset(),
+            Self::Local => utc.with_timezone(&Local).fixed_offset(),
+            Self::Fixed(x) => utc.with_timezone(&x),
+        })
+    }
+    /// Get the timestamp of a date and time in this zone. Returns `None` if it doesn't exist.
+    fn resolve(self, naive: NaiveDateTime) -> Option<i64> {
+        match self {
+            Self::Utc => Some(naive.and_utc().timestamp()),
+            Self::Local => Local
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|x| x.timestamp()),
+            Self::Fixed(x) => x
+                .from_local_datetime(&naive)
+                .single()
+                .map(|y| y.timestamp()),
+        }
+    }
+}
+
+/// Get the time zone argument at `i`.
+fn arg_zone(a: &[Var], i: usize) -> Result<Zone, VmError> {
+    let zone = unsafe { a.get_unchecked(i) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let zone = zone.borrow()?;
+    match &zone[..] {
+        "utc" | "UTC" | "Z" => Ok(Zone::Utc),
+        "local" => Ok(Zone::Local),
+        x => x
+            .parse()
+            .map(Zone::Fixed)
+            .map_err(|_| VmError::new("raw::fatal::invalid").with_message("invalid time zone")),
+    }
+}
+
+/// Get the timestamp argument at `i`.
+#[inline]
+fn arg_ts(a: &[Var], i: usize) -> Result<i64, VmError> {
+    unsafe { a.get_unchecked(i) }
+        .as_i64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))
+}
+
+/// Put a status, and the timestamp at 1 if it's `Some`.
+#[inline]
+fn put_ts(a: &mut [Var], ts: Option<i64>) {
+    match ts {
+        Some(x) => unsafe {
+            *a.get_unchecked_mut(0) = Var::U8(1);
+            *a.get_unchecked_mut(1) = Var::I64(x);
+        },
+        None => *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(0),
+    }
+}
+
+/// Get an integer field of an object, or `default` if it doesn't exist.
+fn field(obj: &ObjectRef, name: &str, default: Option<i64>) -> Result<i64, VmError> {
+    match obj.get(name) {
+        Ok(x) => x
+            .as_i64()
+            .ok_or_else(|| VmError::new("raw::fatal::not_an_integer")),
+        Err(x) => default.ok_or(x),
+    }
+}
+
+/// Break a timestamp down into date fields in a time zone. Returns an object of type
+/// `raw::time::fields`, with `year`, `month`, `day`, `hour`, `minute`, `second`, `weekday` (0 is
+/// Monday), `yday` (1 is January 1st) and `offset` (seconds east of UTC) fields.
+pub fn fields(a: &mut [Var]) -> Result<(), VmError> {
+    let ts = arg_ts(a, 0)?;
+    let dt = arg_zone(a, 1)?
+        .at(ts)
+        .ok_or_else(|| VmError::new("raw::fatal::out_of_range"))?;
+    let result = ObjectRef::new("raw::time::fields");
+    result.set("year", Var::I64(dt.year() as i64))?;
+    result.set("month", Var::U8(dt.month() as u8))?;
+    result.set("day", Var::U8(dt.day() as u8))?;
+    result.set("hour", Var::U8(dt.hour() as u8))?;
+    result.set("minute", Var::U8(dt.minute() as u8))?;
+    result.set("second", Var::U8(dt.second() as u8))?;
+    result.set(
+        "weekday",
+        Var::U8(dt.weekday().num_days_from_monday() as u8),
+    )?;
+    result.set("yday", Var::U16(dt.ordinal() as u16))?;
+    result.set("offset", Var::I32(dt.offset().local_minus_utc()))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::Object(result);
+    Ok(())
+}
+
+/// Build a timestamp from an object with `year`, `month` and `day` fields, and optional `hour`,
+/// `minute` and `second` fields, in a time zone. Returns 1 and the timestamp, or 0 if the date
+/// and time is invalid or doesn't exist in the zone.
+pub fn from_fields(a: &mut [Var]) -> Result<(), VmError> {
+    let obj = unsafe { a.get_unchecked(0) }
+        .as_objref()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_object"))?;
+    let (year, month, day) = (
+        field(obj, "year", None)?,
+        field(obj, "month", None)?,
+        field(obj, "day", None)?,
+    );
+    let (hour, minute, second) = (
+        field(obj, "hour", Some(0))?,
+        field(obj, "minute", Some(0))?,
+        field(obj, "second", Some(0))?,
+    );
+    let zone = arg_zone(a, 1)?;
+    let naive = i32::try_from(year)
+        .ok()
+        .zip(u32::try_from(month).ok())
+        .zip(u32::try_from(day).ok())
+        .and_then(|((y, m), d)| NaiveDate::from_ymd_opt(y, m, d))
+        .zip(
+            u32::try_from(hour)
+                .ok()
+                .zip(u32::try_from(minute).ok())
+                .zip(u32::try_from(second).ok())
+                .and_then(|((h, m), s)| NaiveTime::from_hms_opt(h, m, s)),
+        )
+        .map(|(x, y)| x.and_time(y));
+    put_ts(a, naive.and_then(|x| zone.resolve(x)));
+    Ok(())
+}
+
+/// Add an amount of calendar units to a timestamp in a time zone. The unit is one of `year`,
+/// `month`, `week`, `day`, `hour`, `minute` and `second`, and the amount may be negative. Years,
+/// months, weeks and days keep the local time of day, and a day which doesn't exist in the target
+/// month is clamped to the last day of the month. Returns 1 and the timestamp, or 0 if the result
+/// is out of range or doesn't exist in the zone.
+pub fn add(a: &mut [Var]) -> Result<(), VmError> {
+    let ts = arg_ts(a, 0)?;
+    let amount = arg_ts(a, 1)?;
+    let unit = unsafe { a.get_unchecked(2) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let unit = unit.borrow()?.to_owned();
+    let zone = arg_zone(a, 3)?;
+    let seconds = |x: i64| amount.checked_mul(x).and_then(|y| ts.checked_add(y));
+    let months = |x: i64| {
+        let naive = zone.at(ts)?.naive_local();
+        let n = u32::try_from(amount.checked_mul(x)?.unsigned_abs()).ok()?;
+        let naive = match amount < 0 {
+            true => naive.checked_sub_months(Months::new(n))?,
+            false => naive.checked_add_months(Months::new(n))?,
+        };
+        zone.resolve(naive)
+    };
+    let days = |x: i64| {
+        let naive = zone.at(ts)?.naive_local();
+        let n = amount.checked_mul(x)?.unsigned_abs();
+        let naive = match amount < 0 {
+            true => naive.checked_sub_days(Days::new(n))?,
+            false => naive.checked_add_days(Days::new(n))?,
+        };
+        zone.resolve(naive)
+    };
+    let result = match &unit[..] {
+        "year" => months(12),
+        "month" => months(1),
+        "week" => days(7),
+        "day" => days(1),
+        "hour" => seconds(3600),
+        "minute" => seconds(60),
+        "second" => seconds(1),
+        _ => return Err(VmError::new("raw::fatal::invalid").with_message("invalid calendar unit")),
+    };
+    put_ts(a, result);
+    Ok(())
+}
+
+/// Get the offset of a time zone at a timestamp, in seconds east of UTC.
+pub fn offset(a: &mut [Var]) -> Result<(), VmError> {
+    let ts = arg_ts(a, 0)?;
+    let dt = arg_zone(a, 1)?
+        .at(ts)
+        .ok_or_else(|| VmError::new("raw::fatal::out_of_range"))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::I32(dt.offset().local_minus_utc());
+    Ok(())
+}
+
+/// Put the result of parsing a date and time with an explicit offset. Returns 1, the timestamp
+/// and the offset in seconds east of UTC, or 0 if it can't be parsed.
+#[inline]
+fn put_parsed(a: &mut [Var], dt: Option<DateTime<FixedOffset>>) {
+    put_ts(a, dt.map(|x| x.timestamp()));
+    if let Some(x) = dt {
+        *(unsafe { a.get_unchecked_mut(2) }) = Var::I32(x.offset().local_minus_utc());
+    }
+}
+
+/// Parse an RFC 3339 date and time. Returns 1, the timestamp and the offset, or 0 if it's invalid.
+pub fn parse_rfc3339(a: &mut [Var]) -> Result<(), VmError> {
+    let src = unsafe { a.get_unchecked(0) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let result = DateTime::parse_from_rfc3339(&src.borrow()?).ok();
+    put_parsed(a, result);
+    Ok(())
+}
+
+/// Parse a date and time with a format, which must contain an offset such as `%z`. Returns 1, the
+/// timestamp and the offset, or 0 if it can't be parsed.
+pub fn parse(a: &mut [Var]) -> Result<(), VmError> {
+    let src = unsafe { a.get_unchecked(0) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let fmt = unsafe { a.get_unchecked(1) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let result = DateTime::parse_from_str(&src.borrow()?, &fmt.borrow()?).ok();
+    put_parsed(a, result);
+    Ok(())
+}
+
+/// Format a timestamp in a time zone.
+pub fn format(a: &mut [Var]) -> Result<(), VmError> {
+    let ts = arg_ts(a, 0)?;
+    let fmt = unsafe { a.get_unchecked(1) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let dt = arg_zone(a, 2)?
+        .at(ts)
+        .ok_or_else(|| VmError::new("raw::fatal::out_of_range"))?;
+    let mut result = String::new();
+    write!(result, "{}", dt.format(&fmt.borrow()?))
+        .map_err(|_| VmError::new("raw::fatal::invalid").with_message("invalid format"))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::UString(result.into());
+    Ok(())
+}
+
+/// Format a timestamp in a time zone as RFC 3339.
+pub fn format_rfc3339(a: &mut [Var]) -> Result<(), VmError> {
+    let ts = arg_ts(a, 0)?;
+    let dt = arg_zone(a, 1)?
+        .at(ts)
+        .ok_or_else(|| VmError::new("raw::fatal::out_of_range"))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::UString(dt.to_rfc3339().into());
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::time::cal::fields", fields);
+    putnfp("raw::time::cal::from_fields", from_fields);
+    putnfp("raw::time::cal::add", add);
+    putnfp("raw::time::cal::offset", offset);
+    putnfp("raw::time::cal::parse<rfc3339>", parse_rfc3339);
+    putnfp("raw::time::cal::parse", parse);
+    putnfp("raw::time::cal::format", format);
+    putnfp("raw::time::cal::format<rfc3339>", format_rfc3339);
+}
@@ -0,0 +1,236 @@
+//!
+//! Timers of `libraw`.
+//!
+//! Timers are fired by one OS thread named "timer", which sleeps until the earliest deadline.
+//! A timer calls a VM function by name with a vector of arguments once after a delay, or
+//! periodically. Callbacks are executed one by one on another OS thread named "timer-callback", so
+//! a long callback delays other callbacks, but not the timer thread, and a periodic timer whose
+//! last callback hasn't finished skips the tick. Like spawned threads, an abort in a callback only
+//! ends the callback, unless aborts are configured to exit the process.
+//!
+//! The timer thread is also used to wake tasks which are sleeping.
+//!
+
+use crate::{
+    context::{getfp, putnfp, ExecUnit, Thread},
+    error::VmError,
+    executor,
+    isa::{FuncPtr, VirtFuncPtr},
+    libraw::iohmgr::{FakeHasher, IdGen},
+    vmem::Var,
+};
+use once_cell::sync::Lazy;
+use parking_lot::{const_mutex, Condvar, Mutex};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+/// What a timer does when it fires.
+enum Action {
+    /// Call a VM function, and fire again after the period if it's `Some`.
+    Call {
+        fp: VirtFuncPtr,
+        args: Vec<Var>,
+        period: Option<Duration>,
+    },
+    /// Call a native closure once.
+    Wake(Box<dyn FnOnce() + Send>),
+}
+
+/// State of the timer thread.
+#[derive(Default)]
+struct State {
+    /// Deadlines and IDs of timers, with the earliest on the top. Cancelled timers are skipped
+    /// when they are popped.
+    heap: BinaryHeap<Reverse<(Instant, u64)>>,
+    /// Active timers, and their next deadlines.
+    timers: HashMap<u64, (Instant, Action), FakeHasher>,
+    /// Timers whose callbacks are queued or running.
+    busy: HashSet<u64, FakeHasher>,
+}
+
+/// A callback sent to the callback thread.
+struct Callback {
+    id: u64,
+    fp: VirtFuncPtr,
+    args: Vec<Var>,
+}
+
+/// The timer thread.
+#[derive(Default)]
+struct Timers {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+impl Timers {
+    /// Create the timers, and start the timer thread and the callback thread.
+    fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("timer".to_owned())
+            .spawn(move || TIMERS.work(tx))
+            .expect("failed to start the timer thread");
+        std::thread::Builder::new()
+            .name("timer-callback".to_owned())
+            .spawn(move || TIMERS.call(rx))
+            .expect("failed to start the timer callback thread");
+        Self::default()
+    }
+    /// Add a timer.
+    fn add(&self, deadline: Instant, action: Action) -> u64 {
+        // IDs of timers are never reused, so a cancelled timer left in the heap can't be mistaken.
+        let id = IDGEN.lock().next();
+        let mut state = self.state.lock();
+        state.heap.push(Reverse((deadline, id)));
+        state.timers.insert(id, (deadline, action));
+        self.changed.notify_one();
+        id
+    }
+    /// Cancel a timer. Returns `false` if the timer doesn't exist or has fired.
+    fn cancel(&self, id: u64) -> bool {
+        self.state.lock().timers.remove(&id).is_some()
+    }
+    /// Fire timers when their deadlines are reached, forever. Callbacks are sent to `tx`.
+    fn work(&self, tx: Sender<Callback>) {
+        loop {
+            let action = {
+                let mut state = self.state.lock();
+                loop {
+                    let (deadline, id) = match state.heap.peek() {
+                        Some(Reverse(x)) => *x,
+                        None => {
+                            self.changed.wait(&mut state);
+                            continue;
+                        }
+                    };
+                    if deadline > Instant::now() {
+                        self.changed.wait_until(&mut state, deadline);
+                        continue;
+                    }
+                    state.heap.pop();
+                    match state.timers.get(&id) {
+                        Some((x, _)) if *x == deadline => (),
+                        _ => continue,
+                    }
+                    let (_, action) = state.timers.remove(&id).unwrap();
+                    if let Action::Call {
+                        fp,
+                        args,
+                        period: Some(period),
+                    } = &action
+                    {
+                        let next = (deadline + *period).max(Instant::now());
+                        let again = Action::Call {
+                            fp: fp.clone(),
+                            args: args.clone(),
+                            period: Some(*period),
+                        };
+                        state.heap.push(Reverse((next, id)));
+                        state.timers.insert(id, (next, again));
+                    }
+                    if let Action::Call { .. } = action {
+                        if !state.busy.insert(id) {
+                            continue;
+                        }
+                    }
+                    break (id, action);
+                }
+            };
+            match action {
+                (id, Action::Call { fp, args, .. }) => {
+                    tx.send(Callback { id, fp, args }).ok();
+                }
+                (_, Action::Wake(f)) => f(),
+            }
+        }
+    }
+    /// Execute callbacks from `rx` one by one, forever.
+    fn call(&self, rx: Receiver<Callback>) {
+        for Callback { id, fp, args } in rx {
+            let mut thread = Thread::new(fp);
+            for (i, x) in args.into_iter().enumerate() {
+                thread.sset(100 + i, x).ok();
+            }
+            catch_unwind(AssertUnwindSafe(|| executor::start_thread(thread))).ok();
+            self.state.lock().busy.remove(&id);
+        }
+    }
+}
+
+/// ID Generator for timers.
+static IDGEN: Mutex<IdGen> = const_mutex(IdGen::new());
+/// The timer thread.
+static TIMERS: Lazy<Timers> = Lazy::new(Timers::new);
+
+/// Call `f` on the timer thread after `delay`. `f` should return quickly.
+#[inline]
+pub fn wake_after(delay: Duration, f: impl FnOnce() + Send + 'static) {
+    TIMERS.add(Instant::now() + delay, Action::Wake(Box::new(f)));
+}
+
+/// Add a timer calling a function from the arguments, whose delay or period is at 0.
+fn add(a: &mut [Var], periodic: bool) -> Result<(), VmError> {
+    let delay = unsafe { a.get_unchecked(0) }
+        .as_u64()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    if periodic && delay == 0 {
+        return Err(VmError::new("raw::fatal::out_of_range"));
+    }
+    let delay = Duration::from_millis(delay);
+    let fp = unsafe { a.get_unchecked(1) }
+        .as_sr()
+        .ok_or_else(|| VmError::new("raw::fatal::not_a_buf"))?;
+    let fp = match getfp(&fp.borrow()?) {
+        Some(FuncPtr::Virtual(x)) => x,
+        _ => return Err(VmError::new("raw::fatal::segfault")),
+    };
+    let args = match unsafe { a.get_unchecked(2) } {
+        Var::Vector(x) => x.borrow()?.clone(),
+        _ => return Err(VmError::new("raw::fatal::not_a_buf")),
+    };
+    if args.len() > 50 {
+        return Err(VmError::new("raw::fatal::segfault"));
+    }
+    let action = Action::Call {
+        fp,
+        args,
+        period: periodic.then_some(delay),
+    };
+    let id = TIMERS.add(Instant::now() + delay, action);
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U64(id);
+    Ok(())
+}
+
+/// Call a function by name with a vector of arguments once after a delay in milliseconds. Returns
+/// the timer ID.
+pub fn after(a: &mut [Var]) -> Result<(), VmError> {
+    add(a, false)
+}
+
+/// Call a function by name with a vector of arguments every period in milliseconds, from one
+/// period later. Returns the timer ID.
+pub fn every(a: &mut [Var]) -> Result<(), VmError> {
+    add(a, true)
+}
+
+/// Cancel a timer. Returns 1 if the timer is cancelled, or 0 if it doesn't exist or has fired.
+pub fn cancel(a: &mut [Var]) -> Result<(), VmError> {
+    let id = unsafe { a.get_unchecked(0) }
+        .as_u64_strict()
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_integer"))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::U8(TIMERS.cancel(id) as u8);
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    IDGEN.lock().next();
+    putnfp("raw::time::timer::after", after);
+    putnfp("raw::time::timer::every", every);
+    putnfp("raw::time::timer::cancel", cancel);
+}
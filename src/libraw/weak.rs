@@ -0,0 +1,34 @@
+//!
+//! Weak reference library of `libraw`.
+//!
+
+use crate::{
+    context::putnfp,
+    error::VmError,
+    vmem::{Var, WeakRef},
+};
+
+/// Create a weak reference to a reference.
+pub fn downgrade(a: &mut [Var]) -> Result<(), VmError> {
+    let weak = WeakRef::downgrade(unsafe { a.get_unchecked(0) })
+        .ok_or_else(|| VmError::new("raw::fatal::not_an_object"))?;
+    *(unsafe { a.get_unchecked_mut(0) }) = Var::Weak(weak);
+    Ok(())
+}
+
+/// Get a strong reference from a weak reference, or a null reference if it is dead.
+pub fn upgrade(a: &mut [Var]) -> Result<(), VmError> {
+    let strong = match unsafe { a.get_unchecked(0) } {
+        Var::Weak(x) => x.upgrade(),
+        _ => return Err(VmError::new("raw::fatal::not_an_object")),
+    };
+    *(unsafe { a.get_unchecked_mut(0) }) = strong;
+    Ok(())
+}
+
+/// Initialize the library.
+#[inline(always)]
+pub fn init() {
+    putnfp("raw::weak::downgrade", downgrade);
+    putnfp("raw::weak::upgrade", upgrade);
+}
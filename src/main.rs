@@ -4,6 +4,7 @@
 //!
 
 pub mod context;
+pub mod error;
 /// euolaVM's core executing engines.
 pub mod executor;
 /// Data structure for an `Instruction` and function.
@@ -76,6 +77,49 @@ fn loads(v: &str, c: &str) {
     }
 }
 
+/// Set the memory limit from environment `EUOLA_VM_MEMORY_LIMIT`, in bytes.
+fn setlimit() {
+    let x = match env::var("EUOLA_VM_MEMORY_LIMIT") {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    match x.parse() {
+        Ok(0) => (),
+        Ok(y) => vmem::mem::set_limit(y),
+        Err(_) => eprintln!(
+            "{}environment `EUOLA_VM_MEMORY_LIMIT` is invalid, the memory is unlimited.",
+            Style::new().bold().fg(Yellow).paint("warning: "),
+        ),
+    }
+}
+
+/// Set the coroutine quantum from environment `EUOLA_VM_CORO_QUANTUM`, in instructions.
+fn setquantum() {
+    let x = match env::var("EUOLA_VM_CORO_QUANTUM") {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    match x.parse() {
+        Ok(y) => executor::reactor::set_quantum(y),
+        Err(_) => eprintln!(
+            "{}environment `EUOLA_VM_CORO_QUANTUM` is invalid, coroutines are not preempted.",
+            Style::new().bold().fg(Yellow).paint("warning: "),
+        ),
+    }
+}
+
+/// Make aborts in spawned threads exit the process if environment `EUOLA_VM_ABORT_PROCESS` is 1.
+fn setabort() {
+    match env::var("EUOLA_VM_ABORT_PROCESS").as_deref() {
+        Ok("1") => executor::set_abort_process(true),
+        Ok("0") | Err(_) => (),
+        Ok(_) => eprintln!(
+            "{}environment `EUOLA_VM_ABORT_PROCESS` is invalid, aborts only end their threads.",
+            Style::new().bold().fg(Yellow).paint("warning: "),
+        ),
+    }
+}
+
 /// Get symbol `_start`.
 fn getstart() -> isa::VirtFuncPtr {
     use context::getfp;
@@ -115,5 +159,8 @@ fn main() {
 
     libraw::init();
     os::init_pre();
+    setlimit();
+    setquantum();
+    setabort();
     executor::start(context::Thread::new(vfp));
 }
@@ -23,8 +23,8 @@ impl<T> ResolverOptionExt<T> for Option<T> {
 ///
 /// Load a file.
 ///
-/// NOTE: This shouldn't be `?`d from a VMInterface function, because the error may be unable
-/// to downcast to a `&str`. This may panic on interrupt handling.
+/// NOTE: This returns an `anyhow::Error`, so it should be converted with `VmError::host` before it
+/// is thrown from a native function.
 ///
 pub fn resolve(path: &str) -> Result<(), anyhow::Error> {
     let file_content = read_to_string(path)?;
@@ -282,7 +282,10 @@ pub fn resolve_fn(n: &str, c: &[SmallVec<[Box<str>; 4]>]) -> Result<(), anyhow::
                     result.push(Instruction::CallPtr(i.get(1).r()?.parse()?));
                 }
                 "~" => {
-                    result.push(Instruction::Int(Box::from(&i.get(1).r()?[..])));
+                    result.push(Instruction::Int(
+                        Box::from(&i.get(1).r()?[..]),
+                        i.get(2).map(|x| x.parse()).transpose()?,
+                    ));
                 }
                 "r" => {
                     result.push(Instruction::Ret);
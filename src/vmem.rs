@@ -2,19 +2,26 @@
 //! Data structures about VM variables, such as `Var`, and references.
 //!
 
+pub mod gc;
+pub mod mapped;
+pub mod mem;
+
 use crate::{
     context::{getfp, ExecUnit, Thread},
+    error::VmError,
     executor::{self, start},
     isa::FuncPtr,
 };
-use anyhow::anyhow;
 use dashmap::DashMap;
-use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use mapped::MappedRef;
+use mem::{Accounted, HeapGuard, Kind};
+use parking_lot::{RwLock, RwLockReadGuard};
 use std::{
     convert::TryFrom,
     fmt::{self, Display, Formatter},
     hash::{Hash, Hasher},
-    sync::Arc,
+    mem::size_of,
+    sync::{Arc, Weak},
 };
 
 /// A helper trait to create null references and judge if the reference is null.
@@ -53,84 +60,84 @@ macro_rules! impl_veclike {
             /// # Safety
             /// This is unsafe with the same reason to `BytesRef::get_unchecked`.
             #[inline]
-            pub unsafe fn set_unchecked(&self, a: usize, b: $b) -> Result<(), anyhow::Error> {
+            pub unsafe fn set_unchecked(&self, a: usize, b: $b) -> Result<(), VmError> {
                 match &self.0 {
                     Some(x) => {
                         *x.write().get_unchecked_mut(a) = b;
                         Ok(())
                     }
-                    None => Err(anyhow!("raw::fatal::argument_null")),
+                    None => Err(VmError::new("raw::fatal::argument_null")),
                 }
             }
             /// Set a value.
             #[inline]
-            pub fn set(&self, a: usize, b: $b) -> Result<(), anyhow::Error> {
+            pub fn set(&self, a: usize, b: $b) -> Result<(), VmError> {
                 match &self.0 {
                     Some(x) => match x.write().get_mut(a) {
                         Some(y) => {
                             *y = b;
                             Ok(())
                         }
-                        None => Err(anyhow!("raw::fatal::out_of_range")),
+                        None => Err(VmError::new("raw::fatal::out_of_range")),
                     },
-                    None => Err(anyhow!("raw::fatal::argument_null")),
+                    None => Err(VmError::new("raw::fatal::argument_null")),
                 }
             }
             /// Get a value.
             #[inline]
-            pub fn get(&self, a: usize) -> Result<$b, anyhow::Error> {
+            pub fn get(&self, a: usize) -> Result<$b, VmError> {
                 match &self.0 {
                     Some(x) => match x.read().get(a) {
                         Some(y) => Ok(y.to_owned()),
-                        None => Err(anyhow!("raw::fatal::out_of_range")),
+                        None => Err(VmError::new("raw::fatal::out_of_range")),
                     },
-                    None => Err(anyhow!("raw::fatal::argument_null")),
+                    None => Err(VmError::new("raw::fatal::argument_null")),
                 }
             }
             /// Resize the collection.
             #[inline]
-            pub fn resize(&self, s: usize) -> Result<(), anyhow::Error> {
+            pub fn resize(&self, s: usize) -> Result<(), VmError> {
                 self.0
                     .as_ref()
-                    .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
                     .write()
                     .resize(s, $c);
                 Ok(())
             }
             /// Push a value to the end of the collection.
             #[inline]
-            pub fn push(&self, s: $b) -> Result<(), anyhow::Error> {
+            pub fn push(&self, s: $b) -> Result<(), VmError> {
                 self.0
                     .as_ref()
-                    .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
                     .write()
                     .push(s);
                 Ok(())
             }
             /// Clear the collection.
             #[inline]
-            pub fn clear(&self) -> Result<(), anyhow::Error> {
+            pub fn clear(&self) -> Result<(), VmError> {
                 self.0
                     .as_ref()
-                    .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
                     .write()
                     .clear();
                 Ok(())
             }
             /// Get the length of the collection.
             #[inline]
-            pub fn len(&self) -> Result<usize, anyhow::Error> {
+            pub fn len(&self) -> Result<usize, VmError> {
                 Ok(self
                     .0
                     .as_ref()
-                    .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
+                    .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
                     .read()
                     .len())
             }
             /// Returns `Ok(true)` if this collection is empty, or `Ok(false)` for not empty, or
             /// `Err(_)` if any error are detected.
             #[inline]
-            pub fn is_empty(&self) -> Result<bool, anyhow::Error> {
+            pub fn is_empty(&self) -> Result<bool, VmError> {
                 if self.len()? == 0 {
                     Ok(true)
                 } else {
@@ -159,9 +166,18 @@ macro_rules! impl_var_as {
     };
 }
 
+/// Content of a VM object.
+type ObjectMap = DashMap<Box<str>, Var, ahash::RandomState>;
+
+/// Estimated size of an entry whose key is `key` in an object.
+#[inline]
+fn entry_size(key: &str) -> usize {
+    size_of::<(Box<str>, Var)>() + key.len()
+}
+
 /// A reference to a VM object.
 #[derive(Debug, Clone)]
-pub struct ObjectRef(Option<Arc<DashMap<Box<str>, Var, ahash::RandomState>>>);
+pub struct ObjectRef(Option<Arc<Accounted<ObjectMap>>>);
 impl_create_null!(ObjectRef);
 impl ObjectRef {
     /// Create a new non-null object with specified type.
@@ -169,28 +185,73 @@ impl ObjectRef {
     pub fn new(t: &str) -> Self {
         let unique_obj = DashMap::with_capacity_and_hasher(8, ahash::RandomState::default());
         unique_obj.insert(Box::from("type"), Var::UString(StringRef::from(t)));
-        Self(Some(Arc::new(unique_obj)))
+        let size = unique_obj.capacity() * size_of::<(Box<str>, Var)>() + "type".len();
+        let inner = Arc::new(Accounted::new(Kind::Object, unique_obj, size));
+        gc::track_object(&inner);
+        Self(Some(inner))
     }
     #[inline]
-    pub fn get(&self, id: &str) -> Result<Var, anyhow::Error> {
+    pub fn get(&self, id: &str) -> Result<Var, VmError> {
         match self
             .0
             .as_ref()
-            .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
+            .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
             .get(id)
         {
             Some(x) => Ok(x.to_owned()),
-            None => Err(anyhow!("raw::fatal::out_of_range")),
+            None => Err(VmError::new("raw::fatal::out_of_range")),
         }
     }
     #[inline]
-    pub fn set(&self, id: &str, val: Var) -> Result<(), anyhow::Error> {
-        self.0
+    pub fn set(&self, id: &str, val: Var) -> Result<(), VmError> {
+        let x = self
+            .0
             .as_ref()
-            .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
-            .insert(Box::from(id), val);
+            .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?;
+        if x.insert(Box::from(id), val).is_none() {
+            x.grow(entry_size(id));
+        }
         Ok(())
     }
+    /// Run the finalizer of this object if it has one. The finalizer is removed before it is
+    /// called, so it runs at most once.
+    pub(crate) fn finalize(&self) {
+        let x = match self.get("finalize") {
+            Ok(y) => y,
+            Err(_) => return,
+        };
+        let x = match x.as_sr() {
+            Some(y) => y,
+            None => return,
+        };
+        let x = match x.borrow() {
+            Ok(y) => y,
+            Err(_) => return,
+        };
+        let x = match getfp(&x[..]) {
+            Some(y) => y,
+            None => return,
+        };
+        let inner = self.0.as_ref().unwrap();
+        if inner.remove("finalize").is_some() {
+            inner.shrink(entry_size("finalize"));
+        }
+        match x {
+            FuncPtr::Virtual(y) => {
+                let mut t = Thread::new(y);
+                t.sset(100, Var::Object(self.clone())).unwrap();
+                start(t);
+            }
+            FuncPtr::Native(y) => {
+                // NOTE: This will be recursive called, so there shouldn't store too much on
+                // the stack to avoid stack overflowing. That's why there should use `Vec`, not
+                // array or `SmallVec` although the length of `a` is fixed.
+                let mut a = vec![Var::U8(0); 50];
+                *(unsafe { a.get_unchecked_mut(0) }) = Var::Object(self.clone());
+                y(&mut a[..]).ok();
+            }
+        }
+    }
 }
 impl PartialEq for ObjectRef {
     fn eq(&self, other: &Self) -> bool {
@@ -260,56 +321,24 @@ impl Hash for ObjectRef {
 
 impl Drop for ObjectRef {
     fn drop(&mut self) {
-        if let Ok(x) = self.get("finalize") {
-            if Arc::strong_count(self.0.as_ref().unwrap()) > 1 {
-                return;
-            }
-            let x = match x.as_sr() {
-                Some(y) => y,
-                None => return,
-            };
-            let x = match x.borrow() {
-                Ok(y) => y,
-                Err(_) => return,
-            };
-            let x = match getfp(&x[..]) {
-                Some(y) => y,
-                None => return,
-            };
-            self.0.as_ref().unwrap().remove("finalize");
-            match x {
-                FuncPtr::Virtual(y) => {
-                    let mut t = Thread::new(y);
-                    t.sset(100, Var::Object(self.clone())).unwrap();
-                    start(t);
-                }
-                FuncPtr::Native(y) => {
-                    // NOTE: This will be recursive called, so there shouldn't store too much on
-                    // the stack to avoid stack overflowing. That's why there should use `Vec`, not
-                    // array or `SmallVec` although the length of `a` is fixed.
-                    let mut a = vec![Var::U8(0); 50];
-                    *(unsafe { a.get_unchecked_mut(0) }) = Var::Object(self.clone());
-                    y(&mut a[..]).ok();
-                }
-            }
+        match &self.0 {
+            Some(x) if Arc::strong_count(x) == 1 => self.finalize(),
+            _ => (),
         }
     }
 }
 
 /// A reference to a VM vector.
 #[derive(Debug, Clone)]
-pub struct VectorRef(Option<Arc<RwLock<Vec<Var>>>>);
+pub struct VectorRef(Option<Arc<Accounted<RwLock<Vec<Var>>>>>);
 impl_create_null!(VectorRef);
 impl From<&BytesRef> for VectorRef {
     #[inline]
     fn from(r: &BytesRef) -> Self {
         match &r.0 {
             Some(x) => {
-                let inner = RwLock::new(Vec::with_capacity(x.read().len()));
-                x.read()
-                    .iter()
-                    .for_each(|y| inner.write().push(Var::U8(*y)));
-                Self(Some(Arc::new(inner)))
+                let inner = x.read().iter().map(|y| Var::U8(*y)).collect();
+                Self::track(inner)
             }
             None => Self(None),
         }
@@ -318,36 +347,43 @@ impl From<&BytesRef> for VectorRef {
 impl From<Vec<Var>> for VectorRef {
     #[inline]
     fn from(v: Vec<Var>) -> Self {
-        Self(Some(Arc::new(RwLock::new(v))))
+        Self::track(v)
     }
 }
 impl Eq for VectorRef {}
 impl From<RwLock<Vec<Var>>> for VectorRef {
     #[inline]
     fn from(v: RwLock<Vec<Var>>) -> Self {
-        Self(Some(Arc::new(v)))
+        Self::track(v.into_inner())
     }
 }
 impl_veclike!(VectorRef, Var, Var::U8(0));
 impl VectorRef {
+    /// Create a new vector that is tracked by the garbage collector.
+    #[inline]
+    fn track(v: Vec<Var>) -> Self {
+        let inner = Arc::new(Accounted::measured(Kind::Vector, v));
+        gc::track_vector(&inner);
+        Self(Some(inner))
+    }
     #[inline]
     pub fn empty() -> Self {
-        Self(Some(Arc::new(RwLock::new(Vec::with_capacity(4)))))
+        Self::track(Vec::with_capacity(4))
     }
     /// Borrow this reference.
     #[inline]
-    pub fn borrow(&self) -> Result<RwLockReadGuard<Vec<Var>>, anyhow::Error> {
+    pub fn borrow(&self) -> Result<RwLockReadGuard<Vec<Var>>, VmError> {
         match &self.0 {
             Some(x) => Ok(x.read()),
-            None => Err(anyhow!("raw::fatal::argument_null")),
+            None => Err(VmError::new("raw::fatal::argument_null")),
         }
     }
     /// Borrow this reference as mut.
     #[inline]
-    pub fn borrow_mut(&self) -> Result<RwLockWriteGuard<Vec<Var>>, anyhow::Error> {
+    pub fn borrow_mut(&self) -> Result<HeapGuard<Vec<Var>>, VmError> {
         match &self.0 {
             Some(x) => Ok(x.write()),
-            None => Err(anyhow!("raw::fatal::argument_null")),
+            None => Err(VmError::new("raw::fatal::argument_null")),
         }
     }
 }
@@ -372,7 +408,7 @@ impl Hash for VectorRef {
 
 /// A reference to a VM bytes vector.
 #[derive(Debug, Clone)]
-pub struct BytesRef(pub Option<Arc<RwLock<Vec<u8>>>>);
+pub struct BytesRef(pub Option<Arc<Accounted<RwLock<Vec<u8>>>>>);
 impl_create_null!(BytesRef);
 impl PartialEq for BytesRef {
     fn eq(&self, other: &Self) -> bool {
@@ -396,20 +432,20 @@ impl Hash for BytesRef {
 impl From<&str> for BytesRef {
     #[inline]
     fn from(s: &str) -> Self {
-        Self(Some(Arc::new(RwLock::new(Vec::from(s)))))
+        Self::track(Vec::from(s))
     }
 }
 impl From<String> for BytesRef {
     #[inline]
     fn from(s: String) -> Self {
-        Self(Some(Arc::new(RwLock::new(s.into()))))
+        Self::track(s.into())
     }
 }
 impl From<&StringRef> for BytesRef {
     #[inline]
     fn from(s: &StringRef) -> Self {
         match &s.0 {
-            Some(x) => Self(Some(Arc::new(RwLock::new(x.read().to_owned().into())))),
+            Some(x) => Self::track(x.read().to_owned().into()),
             None => Self(None),
         }
     }
@@ -417,7 +453,7 @@ impl From<&StringRef> for BytesRef {
 impl From<Vec<u8>> for BytesRef {
     #[inline]
     fn from(b: Vec<u8>) -> Self {
-        Self(Some(Arc::new(RwLock::new(b))))
+        Self::track(b)
     }
 }
 impl Default for BytesRef {
@@ -427,25 +463,30 @@ impl Default for BytesRef {
     }
 }
 impl BytesRef {
+    /// Create a new bytes vector that is accounted.
+    #[inline]
+    fn track(v: Vec<u8>) -> Self {
+        Self(Some(Arc::new(Accounted::measured(Kind::Bytes, v))))
+    }
     /// Create an empty one.
     #[inline]
     pub fn empty() -> Self {
-        Self(Some(Arc::new(RwLock::new(Vec::with_capacity(16)))))
+        Self::track(Vec::with_capacity(16))
     }
     /// Borrow this reference.
     #[inline]
-    pub fn borrow(&self) -> Result<RwLockReadGuard<Vec<u8>>, anyhow::Error> {
+    pub fn borrow(&self) -> Result<RwLockReadGuard<Vec<u8>>, VmError> {
         match &self.0 {
             Some(x) => Ok(x.read()),
-            None => Err(anyhow!("raw::fatal::argument_null")),
+            None => Err(VmError::new("raw::fatal::argument_null")),
         }
     }
     /// Borrow this reference as mut.
     #[inline]
-    pub fn borrow_mut(&self) -> Result<RwLockWriteGuard<Vec<u8>>, anyhow::Error> {
+    pub fn borrow_mut(&self) -> Result<HeapGuard<Vec<u8>>, VmError> {
         match &self.0 {
             Some(x) => Ok(x.write()),
-            None => Err(anyhow!("raw::fatal::argument_null")),
+            None => Err(VmError::new("raw::fatal::argument_null")),
         }
     }
 }
@@ -453,11 +494,11 @@ impl_veclike!(BytesRef, u8, 0);
 
 /// A reference to a VM UTF-8 String(UString).
 #[derive(Debug, Clone)]
-pub struct StringRef(pub Option<Arc<RwLock<String>>>);
+pub struct StringRef(pub Option<Arc<Accounted<RwLock<String>>>>);
 impl From<&str> for StringRef {
     #[inline]
     fn from(s: &str) -> Self {
-        Self(Some(Arc::new(RwLock::new(s.to_owned()))))
+        Self::track(s.to_owned())
     }
 }
 impl Eq for StringRef {}
@@ -482,29 +523,29 @@ impl Hash for StringRef {
 impl From<String> for StringRef {
     #[inline]
     fn from(s: String) -> Self {
-        Self(Some(Arc::new(RwLock::new(s))))
+        Self::track(s)
     }
 }
 impl From<RwLock<String>> for StringRef {
     #[inline]
     fn from(s: RwLock<String>) -> Self {
-        Self(Some(Arc::new(s)))
+        Self::track(s.into_inner())
     }
 }
 impl TryFrom<&BytesRef> for StringRef {
-    type Error = anyhow::Error;
+    type Error = VmError;
 
     #[inline]
-    fn try_from(b: &BytesRef) -> Result<Self, anyhow::Error> {
-        Ok(Self(Some(Arc::new(RwLock::new(match String::from_utf8(
+    fn try_from(b: &BytesRef) -> Result<Self, VmError> {
+        Ok(Self::track(match String::from_utf8(
             b.0.as_ref()
-                .ok_or_else(|| anyhow!("raw::fatal::argument_null"))?
+                .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
                 .read()
                 .to_owned(),
         ) {
             Ok(x) => Ok(x),
-            Err(_) => Err(anyhow!("raw::fatal::not_valid_utf8")),
-        }?)))))
+            Err(_) => Err(VmError::new("raw::fatal::not_valid_utf8")),
+        }?))
     }
 }
 impl Display for StringRef {
@@ -524,28 +565,101 @@ impl Default for StringRef {
 }
 impl_create_null!(StringRef);
 impl StringRef {
+    /// Create a new string that is accounted.
+    #[inline]
+    fn track(s: String) -> Self {
+        Self(Some(Arc::new(Accounted::measured(Kind::String, s))))
+    }
     /// Create an empty `StringRef`.
     #[inline]
     pub fn empty() -> Self {
-        Self(Some(Arc::new(RwLock::new(String::new()))))
+        Self::track(String::new())
     }
     /// Borrow this reference.
     #[inline]
-    pub fn borrow(&self) -> Result<RwLockReadGuard<String>, anyhow::Error> {
+    pub fn borrow(&self) -> Result<RwLockReadGuard<String>, VmError> {
         match &self.0 {
             Some(x) => Ok(x.read()),
-            None => Err(anyhow!("raw::fatal::argument_null")),
+            None => Err(VmError::new("raw::fatal::argument_null")),
         }
     }
     /// Borrow this reference as a mutable reference.
     #[inline]
-    pub fn borrow_mut(&self) -> Result<RwLockWriteGuard<String>, anyhow::Error> {
+    pub fn borrow_mut(&self) -> Result<HeapGuard<String>, VmError> {
         match &self.0 {
             Some(x) => Ok(x.write()),
-            None => Err(anyhow!("raw::fatal::argument_null")),
+            None => Err(VmError::new("raw::fatal::argument_null")),
+        }
+    }
+}
+
+/// A weak reference to a VM reference. This doesn't keep the referenced value alive, so it can be
+/// used to avoid cycles.
+#[derive(Debug, Clone)]
+pub enum WeakRef {
+    Object(Weak<Accounted<ObjectMap>>),
+    Vector(Weak<Accounted<RwLock<Vec<Var>>>>),
+    Bytes(Weak<Accounted<RwLock<Vec<u8>>>>),
+    UString(Weak<Accounted<RwLock<String>>>),
+}
+impl WeakRef {
+    /// Create a weak reference to a reference. A weak reference to a null reference is always
+    /// dead. Returns `None` if `v` is not a reference.
+    #[inline]
+    pub fn downgrade(v: &Var) -> Option<Self> {
+        Some(match v {
+            Var::Object(x) => Self::Object(x.0.as_ref().map_or_else(Weak::new, Arc::downgrade)),
+            Var::Vector(x) => Self::Vector(x.0.as_ref().map_or_else(Weak::new, Arc::downgrade)),
+            Var::Bytes(x) => Self::Bytes(x.0.as_ref().map_or_else(Weak::new, Arc::downgrade)),
+            Var::UString(x) => Self::UString(x.0.as_ref().map_or_else(Weak::new, Arc::downgrade)),
+            Var::Weak(x) => x.clone(),
+            _ => return None,
+        })
+    }
+    /// Get a strong reference. If the referenced value has been freed, this returns a null
+    /// reference.
+    #[inline]
+    pub fn upgrade(&self) -> Var {
+        match self {
+            Self::Object(x) => Var::Object(ObjectRef(x.upgrade())),
+            Self::Vector(x) => Var::Vector(VectorRef(x.upgrade())),
+            Self::Bytes(x) => Var::Bytes(BytesRef(x.upgrade())),
+            Self::UString(x) => Var::UString(StringRef(x.upgrade())),
+        }
+    }
+    /// Judge if the referenced value has been freed.
+    #[inline]
+    pub fn is_dead(&self) -> bool {
+        match self {
+            Self::Object(x) => x.strong_count() == 0,
+            Self::Vector(x) => x.strong_count() == 0,
+            Self::Bytes(x) => x.strong_count() == 0,
+            Self::UString(x) => x.strong_count() == 0,
+        }
+    }
+    /// Address of the referenced value.
+    #[inline]
+    fn addr(&self) -> usize {
+        match self {
+            Self::Object(x) => x.as_ptr() as *const u8 as usize,
+            Self::Vector(x) => x.as_ptr() as *const u8 as usize,
+            Self::Bytes(x) => x.as_ptr() as *const u8 as usize,
+            Self::UString(x) => x.as_ptr() as *const u8 as usize,
         }
     }
 }
+impl PartialEq for WeakRef {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other) && self.addr() == other.addr()
+    }
+}
+impl Eq for WeakRef {}
+impl Hash for WeakRef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        self.addr().hash(state);
+    }
+}
 
 /// A value that is used in euolaVM.
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
@@ -576,11 +690,15 @@ pub enum Var {
     Vector(VectorRef),
     /// A general type that organizes data as a `Key-Value Pair`.
     Object(ObjectRef),
+    /// A weak reference to one of the reference types.
+    Weak(WeakRef),
+    /// A memory-mapped file, which acts like `Bytes`.
+    Mapped(MappedRef),
 }
 impl Var {
     /// Get type ID of this value.
     #[inline]
-    pub fn typeid(&self) -> Result<String, anyhow::Error> {
+    pub fn typeid(&self) -> Result<String, VmError> {
         Ok(match self {
             Self::I8(_) => "primitive::i8".to_owned(),
             Self::U8(_) => "primitive::u8".to_owned(),
@@ -594,13 +712,15 @@ impl Var {
             Self::Bytes(_) => "raw::bytes".to_owned(),
             Self::UString(_) => "raw::str".to_owned(),
             Self::Vector(_) => "raw::vec".to_owned(),
+            Self::Weak(_) => "raw::weak".to_owned(),
+            Self::Mapped(_) => "raw::fs::mmap".to_owned(),
             Self::Object(x) => match &x.0 {
                 Some(y) => match y.get("type") {
                     Some(z) => match &*z {
                         Self::UString(a) => a.to_string(),
-                        _ => return Err(anyhow!("raw::fatal::metadata_disorder")),
+                        _ => return Err(VmError::new("raw::fatal::metadata_disorder")),
                     },
-                    None => return Err(anyhow!("raw::fatal::metadata_disorder")),
+                    None => return Err(VmError::new("raw::fatal::metadata_disorder")),
                 },
                 None => "raw::null".to_owned(),
             },
@@ -617,40 +737,50 @@ impl Var {
     impl_var_as!(as_usize, usize);
     /// Get by offset.
     #[inline]
-    pub fn offset_get(&self, o: usize) -> Result<Var, anyhow::Error> {
+    pub fn offset_get(&self, o: usize) -> Result<Var, VmError> {
         match self {
             Self::Bytes(x) => match x.get(o) {
                 Ok(y) => Ok(Var::U8(y)),
                 Err(y) => Err(y),
             },
             Self::Vector(x) => x.get(o),
-            _ => Err(anyhow!("raw::fatal::not_a_raw_collection")),
+            Self::Mapped(x) => x.get(o).map(Var::U8),
+            _ => Err(VmError::new("raw::fatal::not_a_raw_collection")),
         }
     }
     /// Set by offset.
     #[inline]
-    pub fn offset_set(&self, o: usize, val: Var) -> Result<(), anyhow::Error> {
+    pub fn offset_set(&self, o: usize, val: Var) -> Result<(), VmError> {
         match self {
             Self::Bytes(x) => x.set(
                 o,
                 match val {
                     Self::U8(y) => y,
                     Self::I8(y) => y as u8,
-                    _ => return Err(anyhow!("raw::fatal::out_of_range")),
+                    _ => return Err(VmError::new("raw::fatal::out_of_range")),
                 },
             ),
             Self::Vector(x) => x.set(o, val),
-            _ => Err(anyhow!("raw::fatal::not_a_raw_collection")),
+            Self::Mapped(x) => x.set(
+                o,
+                match val {
+                    Self::U8(y) => y,
+                    Self::I8(y) => y as u8,
+                    _ => return Err(VmError::new("raw::fatal::out_of_range")),
+                },
+            ),
+            _ => Err(VmError::new("raw::fatal::not_a_raw_collection")),
         }
     }
     /// Get length. Only suitable for `Vector` or `Bytes`.
     #[inline]
-    pub fn rcl(&self) -> Result<usize, anyhow::Error> {
+    pub fn rcl(&self) -> Result<usize, VmError> {
         match self {
             Self::UString(x) => Ok(x.borrow()?.len()),
             Self::Bytes(x) => Ok(x.len()?),
             Self::Vector(x) => Ok(x.len()?),
-            _ => Err(anyhow!("raw::fatal::not_a_raw_collection")),
+            Self::Mapped(x) => x.len(),
+            _ => Err(VmError::new("raw::fatal::not_a_raw_collection")),
         }
     }
     /// Convert to StringRef.
@@ -682,20 +812,22 @@ impl Var {
     }
     /// Judge if this is null.
     #[inline]
-    pub fn is_null(&self) -> Result<bool, anyhow::Error> {
+    pub fn is_null(&self) -> Result<bool, VmError> {
         match self {
             Self::UString(x) => Ok(x.is_null()),
             Self::Bytes(x) => Ok(x.is_null()),
             Self::Vector(x) => Ok(x.is_null()),
             Self::Object(x) => Ok(x.is_null()),
+            Self::Weak(x) => Ok(x.is_dead()),
+            Self::Mapped(x) => Ok(x.is_null()),
             Self::Usize(x) => Ok(*x == 0),
-            _ => Err(anyhow!("raw::fatal::not_an_object")),
+            _ => Err(VmError::new("raw::fatal::not_an_object")),
         }
     }
     impl_var_as!(as_f64, f64);
     /// Convert to I32.
     #[inline]
-    pub fn as_i32(&self) -> Result<i32, anyhow::Error> {
+    pub fn as_i32(&self) -> Result<i32, VmError> {
         match self {
             Self::I8(x) => Ok(*x as i32),
             Self::U8(x) => Ok(*x as i32),
@@ -706,7 +838,7 @@ impl Var {
             Self::I64(x) => Ok(*x as i32),
             Self::U64(x) => Ok(*x as i32),
             Self::Usize(x) => Ok(*x as i32),
-            _ => Err(anyhow!("raw::fatal::not_an_integer")),
+            _ => Err(VmError::new("raw::fatal::not_an_integer")),
         }
     }
     /// Convert to strict u64.
@@ -0,0 +1,262 @@
+//!
+//! Cycle collector of VM references.
+//!
+//! References are counted, so any garbage that doesn't contain a cycle is freed immediately. The
+//! cycle collector finds garbage cycles made of objects and vectors, which are the only
+//! references that can contain other references. It uses trial deletion: a reference is garbage
+//! if all of its strong counts come from other garbage references, because then nothing outside
+//! the garbage, such as a register, a static or a `libraw` table, can reach it.
+//!
+//! When garbage is found, finalizers of garbage objects are called in the order the objects were
+//! created. A finalizer may make its object reachable again, so the garbage is checked again after
+//! finalizers are called, and only the references that are still garbage are cleared, which breaks
+//! the cycles and frees them.
+//!
+//! Collecting doesn't stop the world. References which are being moved by other threads are
+//! always reachable from their threads, but a collection is most precise when it's triggered at a
+//! point where other threads don't touch shared references.
+//!
+
+use super::{mem::Accounted, ObjectMap, ObjectRef, Var, VectorRef};
+use once_cell::sync::Lazy;
+use parking_lot::{const_mutex, Mutex, RwLock};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
+
+/// A reference tracked by the collector.
+enum Tracked {
+    Object(Weak<Accounted<ObjectMap>>),
+    Vector(Weak<Accounted<RwLock<Vec<Var>>>>),
+}
+impl Tracked {
+    /// Upgrade this to a `Node`, or `None` if it is already freed.
+    #[inline]
+    fn upgrade(&self) -> Option<Node> {
+        match self {
+            Self::Object(x) => x.upgrade().map(Node::Object),
+            Self::Vector(x) => x.upgrade().map(Node::Vector),
+        }
+    }
+    /// Judge if the reference is still alive.
+    #[inline]
+    fn is_alive(&self) -> bool {
+        match self {
+            Self::Object(x) => x.strong_count() != 0,
+            Self::Vector(x) => x.strong_count() != 0,
+        }
+    }
+}
+
+/// A reference being scanned by the collector.
+enum Node {
+    Object(Arc<Accounted<ObjectMap>>),
+    Vector(Arc<Accounted<RwLock<Vec<Var>>>>),
+}
+impl Node {
+    /// Address of the referenced content, which identifies the reference.
+    #[inline]
+    fn addr(&self) -> usize {
+        match self {
+            Self::Object(x) => Arc::as_ptr(x) as *const u8 as usize,
+            Self::Vector(x) => Arc::as_ptr(x) as *const u8 as usize,
+        }
+    }
+    /// Strong count of the reference, excluding the one held by the collector.
+    #[inline]
+    fn refs(&self) -> usize {
+        match self {
+            Self::Object(x) => Arc::strong_count(x) - 1,
+            Self::Vector(x) => Arc::strong_count(x) - 1,
+        }
+    }
+    /// Call `f` with the address of every reference contained directly.
+    fn children(&self, mut f: impl FnMut(usize)) {
+        let mut visit = |v: &Var| {
+            if let Some(x) = child_addr(v) {
+                f(x)
+            }
+        };
+        match self {
+            Self::Object(x) => x.iter().for_each(|y| visit(y.value())),
+            Self::Vector(x) => x.read().iter().for_each(visit),
+        }
+    }
+    /// Drop all references contained directly. Contents are dropped after the locks are released,
+    /// because dropping them may call finalizers which access this reference.
+    fn clear(&self) {
+        match self {
+            Self::Object(x) => {
+                let keys: Vec<Box<str>> = x.iter().map(|y| y.key().to_owned()).collect();
+                let mut contents = Vec::with_capacity(keys.len());
+                for i in keys {
+                    if let Some(y) = x.remove(&i) {
+                        contents.push(y);
+                    }
+                }
+                x.resize(0);
+                drop(contents);
+            }
+            Self::Vector(x) => {
+                let contents = std::mem::take(&mut *x.write());
+                drop(contents);
+            }
+        }
+    }
+}
+
+/// Get the address of a reference which may form a cycle.
+#[inline]
+fn child_addr(v: &Var) -> Option<usize> {
+    match v {
+        Var::Object(ObjectRef(Some(x))) => Some(Arc::as_ptr(x) as *const u8 as usize),
+        Var::Vector(VectorRef(Some(x))) => Some(Arc::as_ptr(x) as *const u8 as usize),
+        _ => None,
+    }
+}
+
+/// Statistics of the collector.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    /// Count of alive objects.
+    pub objects: u64,
+    /// Count of alive vectors.
+    pub vectors: u64,
+    /// Count of finished collections.
+    pub collections: u64,
+    /// Count of references freed by all collections.
+    pub collected: u64,
+}
+
+/// All tracked references, in the order they were created.
+static HEAP: Lazy<Mutex<Heap>> = Lazy::new(|| Mutex::new(Heap::new()));
+/// Lock that makes collections never run at the same time.
+static COLLECTING: Mutex<()> = const_mutex(());
+
+/// All tracked references.
+struct Heap {
+    /// Tracked references. Freed references are removed lazily.
+    tracked: Vec<Tracked>,
+    /// When `tracked` reaches this length, freed references are removed.
+    prune_at: usize,
+    /// Count of finished collections.
+    collections: u64,
+    /// Count of references freed by all collections.
+    collected: u64,
+}
+impl Heap {
+    /// Create an empty heap.
+    #[inline]
+    fn new() -> Self {
+        Self {
+            tracked: Vec::with_capacity(1024),
+            prune_at: 1024,
+            collections: 0,
+            collected: 0,
+        }
+    }
+    /// Track a reference.
+    #[inline]
+    fn push(&mut self, t: Tracked) {
+        if self.tracked.len() >= self.prune_at {
+            self.tracked.retain(Tracked::is_alive);
+            self.prune_at = (self.tracked.len() * 2).max(1024);
+        }
+        self.tracked.push(t);
+    }
+}
+
+/// Track a newly created object.
+#[inline]
+pub(super) fn track_object(x: &Arc<Accounted<ObjectMap>>) {
+    HEAP.lock().push(Tracked::Object(Arc::downgrade(x)));
+}
+
+/// Track a newly created vector.
+#[inline]
+pub(super) fn track_vector(x: &Arc<Accounted<RwLock<Vec<Var>>>>) {
+    HEAP.lock().push(Tracked::Vector(Arc::downgrade(x)));
+}
+
+/// Find indexes of garbage nodes with trial deletion.
+fn garbage(nodes: &[Node]) -> Vec<usize> {
+    let index: HashMap<usize, usize, ahash::RandomState> = nodes
+        .iter()
+        .enumerate()
+        .map(|(c, i)| (i.addr(), c))
+        .collect();
+    let mut refs: Vec<usize> = nodes.iter().map(Node::refs).collect();
+    for i in nodes {
+        i.children(|x| {
+            if let Some(y) = index.get(&x) {
+                refs[*y] = refs[*y].saturating_sub(1);
+            }
+        });
+    }
+    let mut reachable = vec![false; nodes.len()];
+    let mut pending: Vec<usize> = (0..nodes.len()).filter(|x| refs[*x] != 0).collect();
+    while let Some(i) = pending.pop() {
+        if reachable[i] {
+            continue;
+        }
+        reachable[i] = true;
+        nodes[i].children(|x| {
+            if let Some(y) = index.get(&x) {
+                if !reachable[*y] {
+                    pending.push(*y);
+                }
+            }
+        });
+    }
+    (0..nodes.len()).filter(|x| !reachable[*x]).collect()
+}
+
+/// Collect garbage cycles. Returns the count of freed references. If a collection is running, such
+/// as when this is called by a finalizer, this returns 0 immediately.
+pub fn collect() -> u64 {
+    let _collecting = match COLLECTING.try_lock() {
+        Some(x) => x,
+        None => return 0,
+    };
+    let nodes: Vec<Node> = {
+        let mut heap = HEAP.lock();
+        heap.tracked.retain(Tracked::is_alive);
+        heap.tracked.iter().filter_map(Tracked::upgrade).collect()
+    };
+    let found = garbage(&nodes);
+    if found.is_empty() {
+        HEAP.lock().collections += 1;
+        return 0;
+    }
+    for i in found.iter() {
+        if let Node::Object(x) = &nodes[*i] {
+            ObjectRef(Some(x.clone())).finalize();
+        }
+    }
+    let found = garbage(&nodes);
+    found.iter().for_each(|x| nodes[*x].clear());
+    drop(nodes);
+    let mut heap = HEAP.lock();
+    heap.collections += 1;
+    heap.collected += found.len() as u64;
+    found.len() as u64
+}
+
+/// Get statistics of the collector.
+pub fn stats() -> Stats {
+    let heap = HEAP.lock();
+    let mut result = Stats {
+        collections: heap.collections,
+        collected: heap.collected,
+        ..Stats::default()
+    };
+    for i in heap.tracked.iter() {
+        match i {
+            Tracked::Object(x) if x.strong_count() != 0 => result.objects += 1,
+            Tracked::Vector(x) if x.strong_count() != 0 => result.vectors += 1,
+            _ => (),
+        }
+    }
+    result
+}
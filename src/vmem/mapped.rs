@@ -0,0 +1,237 @@
+//!
+//! Memory-mapped files.
+//!
+//! A mapped file acts like `Bytes` for `OffsetGet`, `OffsetSet` and `GetLength`, but its length is
+//! fixed to the length of the file when it's mapped. Accesses are bound-checked, and accesses after
+//! the file is unmapped throw `raw::fs::unmapped` instead of touching the memory. The mapping is
+//! shared with the file, so `raw::fs::set_len` refuses to truncate a file below the length of a
+//! live mapping of it. Truncating the file from other processes while it's mapped is still unsafe,
+//! and may kill the process.
+//!
+
+use super::CreateNull;
+use crate::error::VmError;
+use memmap2::{Mmap, MmapMut};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::HashMap,
+    fs::File,
+    hash::{Hash, Hasher},
+    io,
+    sync::{Arc, Weak},
+};
+
+/// Identity of a file, which is its device and inode numbers.
+type FileId = (u64, u64);
+
+/// A mapping which is not kept alive.
+type WeakMapping = Weak<RwLock<Option<Mapping>>>;
+
+/// Mappings of files, so files are not truncated below their live mappings. Mapping a file and
+/// changing its length are done with this locked.
+static MAPPINGS: Lazy<Mutex<HashMap<FileId, Vec<WeakMapping>>>> = Lazy::new(Default::default);
+
+/// Get the identity of a file.
+#[cfg(unix)]
+fn file_id(file: &File) -> io::Result<Option<FileId>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = file.metadata()?;
+    Ok(Some((metadata.dev(), metadata.ino())))
+}
+
+/// Get the identity of a file. Other systems refuse to truncate mapped files by themselves.
+#[cfg(not(unix))]
+fn file_id(_: &File) -> io::Result<Option<FileId>> {
+    Ok(None)
+}
+
+/// Truncate or extend a file to `len`. Truncating a file below the length of a live mapping of it
+/// fails with `io::ErrorKind::InvalidInput`.
+pub fn set_len(file: &File, len: u64) -> io::Result<()> {
+    let mut mappings = MAPPINGS.lock();
+    if let Some(id) = file_id(file)? {
+        let mapped = match mappings.get_mut(&id) {
+            Some(x) => {
+                x.retain(|y| y.strong_count() > 0);
+                x.iter()
+                    .filter_map(Weak::upgrade)
+                    .filter_map(|y| y.read().as_ref().map(|z| z.bytes().len()))
+                    .max()
+            }
+            None => None,
+        };
+        match mapped {
+            Some(x) if len < x as u64 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the file is mapped beyond the length",
+                ));
+            }
+            Some(_) => (),
+            None => {
+                mappings.remove(&id);
+            }
+        }
+    }
+    file.set_len(len)
+}
+
+/// A mapping of a file.
+#[derive(Debug)]
+enum Mapping {
+    ReadOnly(Mmap),
+    ReadWrite(MmapMut),
+}
+impl Mapping {
+    /// Get the mapped bytes.
+    #[inline]
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Self::ReadOnly(x) => x,
+            Self::ReadWrite(x) => x,
+        }
+    }
+}
+
+/// A reference to a memory-mapped file. The mapping is `None` after it's unmapped.
+#[derive(Debug, Clone)]
+pub struct MappedRef(Option<Arc<RwLock<Option<Mapping>>>>);
+impl CreateNull for MappedRef {
+    #[inline]
+    fn null() -> Self {
+        Self(None)
+    }
+    #[inline]
+    fn is_null(&self) -> bool {
+        self.0.is_none()
+    }
+}
+impl PartialEq for MappedRef {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(x), Some(y)) => Arc::ptr_eq(x, y),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+impl Eq for MappedRef {}
+impl Hash for MappedRef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0
+            .as_ref()
+            .map_or(0, |x| Arc::as_ptr(x) as *const u8 as usize)
+            .hash(state);
+    }
+}
+impl MappedRef {
+    /// Map a file. If `writable` is `true`, the file must be opened for reading and writing, and
+    /// writes are shared with the file.
+    pub fn map(file: &File, writable: bool) -> io::Result<Self> {
+        let mut mappings = MAPPINGS.lock();
+        let id = file_id(file)?;
+        // SAFETY: The mapping is only accessed with bound checks. Truncation of the file by
+        // `set_len` is refused, and truncation by others can't be prevented, which is documented.
+        let mapping = match writable {
+            true => Mapping::ReadWrite(unsafe { MmapMut::map_mut(file)? }),
+            false => Mapping::ReadOnly(unsafe { Mmap::map(file)? }),
+        };
+        let result = Arc::new(RwLock::new(Some(mapping)));
+        if let Some(x) = id {
+            let live = mappings.entry(x).or_default();
+            live.retain(|y| y.strong_count() > 0);
+            live.push(Arc::downgrade(&result));
+        }
+        Ok(Self(Some(result)))
+    }
+    /// Run `f` with the mapping.
+    #[inline]
+    fn with<T>(&self, f: impl FnOnce(&Mapping) -> Result<T, VmError>) -> Result<T, VmError> {
+        let inner = self
+            .0
+            .as_ref()
+            .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
+            .read();
+        f(inner.as_ref().ok_or_else(unmapped)?)
+    }
+    /// Get a byte.
+    #[inline]
+    pub fn get(&self, o: usize) -> Result<u8, VmError> {
+        self.with(|x| {
+            x.bytes()
+                .get(o)
+                .copied()
+                .ok_or_else(|| VmError::new("raw::fatal::out_of_range"))
+        })
+    }
+    /// Set a byte. Throws `raw::fs::read_only` if the file is mapped read-only.
+    #[inline]
+    pub fn set(&self, o: usize, val: u8) -> Result<(), VmError> {
+        let mut inner = self
+            .0
+            .as_ref()
+            .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
+            .write();
+        match inner.as_mut().ok_or_else(unmapped)? {
+            Mapping::ReadWrite(x) => match x.get_mut(o) {
+                Some(y) => {
+                    *y = val;
+                    Ok(())
+                }
+                None => Err(VmError::new("raw::fatal::out_of_range")),
+            },
+            Mapping::ReadOnly(_) => {
+                Err(VmError::new("raw::fs::read_only").with_message("the file is mapped read-only"))
+            }
+        }
+    }
+    /// Get the length.
+    #[inline]
+    pub fn len(&self) -> Result<usize, VmError> {
+        self.with(|x| Ok(x.bytes().len()))
+    }
+    /// Judge if the length is 0.
+    #[inline]
+    pub fn is_empty(&self) -> Result<bool, VmError> {
+        self.len().map(|x| x == 0)
+    }
+    /// Copy a range of bytes. The range is clamped to the length.
+    #[inline]
+    pub fn copy(&self, start: usize, len: usize) -> Result<Vec<u8>, VmError> {
+        self.with(|x| {
+            let bytes = x.bytes();
+            let start = start.min(bytes.len());
+            let end = start.saturating_add(len).min(bytes.len());
+            Ok(bytes[start..end].to_vec())
+        })
+    }
+    /// Flush writes to the file. This does nothing if the file is mapped read-only.
+    #[inline]
+    pub fn flush(&self) -> Result<std::io::Result<()>, VmError> {
+        self.with(|x| {
+            Ok(match x {
+                Mapping::ReadWrite(y) => y.flush(),
+                Mapping::ReadOnly(_) => Ok(()),
+            })
+        })
+    }
+    /// Unmap the file. Later accesses throw `raw::fs::unmapped`, and other references to this
+    /// mapping are affected too. Unmapping a mapping twice does nothing.
+    #[inline]
+    pub fn unmap(&self) -> Result<(), VmError> {
+        self.0
+            .as_ref()
+            .ok_or_else(|| VmError::new("raw::fatal::argument_null"))?
+            .write()
+            .take();
+        Ok(())
+    }
+}
+
+/// Create the error of accessing an unmapped file.
+#[inline]
+fn unmapped() -> VmError {
+    VmError::new("raw::fs::unmapped").with_message("the file has been unmapped")
+}
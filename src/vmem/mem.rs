@@ -0,0 +1,277 @@
+//!
+//! Memory accounting of the VM heap.
+//!
+//! Every reference and every `libraw` table is wrapped in an `Accounted`, which remembers how many
+//! bytes it has charged. The charge is updated when the content is modified through `Accounted`
+//! and given back when the content is freed. Sizes are estimated from capacities, so they don't
+//! include allocator overhead.
+//!
+//! When the usage exceeds the limit, the allocation still succeeds, because most allocations
+//! can't fail, but the thread that allocated gets a pending `raw::fatal::out_of_memory`, which
+//! is thrown before it executes the next instruction.
+//!
+
+use crate::error::VmError;
+use parking_lot::{RwLock, RwLockWriteGuard};
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    fmt::{self, Debug, Formatter},
+    mem::size_of,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Kinds of accounted memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Object,
+    Vector,
+    Bytes,
+    String,
+    HashMap,
+    Deque,
+    IntVec,
+    IoHandle,
+    Channel,
+}
+impl Kind {
+    /// All kinds.
+    pub const ALL: [Kind; 9] = [
+        Self::Object,
+        Self::Vector,
+        Self::Bytes,
+        Self::String,
+        Self::HashMap,
+        Self::Deque,
+        Self::IntVec,
+        Self::IoHandle,
+        Self::Channel,
+    ];
+
+    /// Name of this kind, which is used as the field name of `raw::mem::usage`.
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Object => "object",
+            Self::Vector => "vector",
+            Self::Bytes => "bytes",
+            Self::String => "string",
+            Self::HashMap => "hashmap",
+            Self::Deque => "deque",
+            Self::IntVec => "intvec",
+            Self::IoHandle => "iohmgr",
+            Self::Channel => "channel",
+        }
+    }
+}
+
+/// Usage of each kind, indexed by `Kind as usize`.
+static USAGE: [AtomicUsize; 9] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+/// Total usage.
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+/// Limit of the total usage. `usize::MAX` means unlimited.
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+std::thread_local! {
+    /// If this thread has allocated after the limit is exceeded.
+    static PENDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Charge `n` bytes.
+#[inline]
+pub fn charge(kind: Kind, n: usize) {
+    if n == 0 {
+        return;
+    }
+    USAGE[kind as usize].fetch_add(n, Ordering::Relaxed);
+    let total = TOTAL.fetch_add(n, Ordering::Relaxed) + n;
+    if total > LIMIT.load(Ordering::Relaxed) {
+        PENDING.with(|x| x.set(true));
+    }
+}
+
+/// Give back `n` bytes.
+#[inline]
+pub fn uncharge(kind: Kind, n: usize) {
+    if n == 0 {
+        return;
+    }
+    USAGE[kind as usize].fetch_sub(n, Ordering::Relaxed);
+    TOTAL.fetch_sub(n, Ordering::Relaxed);
+}
+
+/// Throw `raw::fatal::out_of_memory` if this thread has allocated after the limit is exceeded,
+/// and the usage still exceeds the limit.
+#[inline(always)]
+pub fn check() -> Result<(), VmError> {
+    if PENDING.with(|x| x.replace(false)) && usage() > limit() {
+        Err(VmError::new("raw::fatal::out_of_memory"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Get the total usage.
+#[inline]
+pub fn usage() -> usize {
+    TOTAL.load(Ordering::Relaxed)
+}
+
+/// Get the usage of a kind.
+#[inline]
+pub fn usage_of(kind: Kind) -> usize {
+    USAGE[kind as usize].load(Ordering::Relaxed)
+}
+
+/// Get the limit. `usize::MAX` means unlimited.
+#[inline]
+pub fn limit() -> usize {
+    LIMIT.load(Ordering::Relaxed)
+}
+
+/// Set the limit. `usize::MAX` means unlimited.
+#[inline]
+pub fn set_limit(n: usize) {
+    LIMIT.store(n, Ordering::Relaxed);
+}
+
+/// A content whose size can be measured.
+pub trait Measure {
+    /// Get the size of memory owned by this content, excluding itself.
+    fn heap_size(&self) -> usize;
+}
+impl<T> Measure for Vec<T> {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>()
+    }
+}
+impl<T> Measure for VecDeque<T> {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>()
+    }
+}
+impl Measure for String {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+/// An accounted content. This derefs to the content, and gives back its charge when dropped.
+pub struct Accounted<T> {
+    inner: T,
+    kind: Kind,
+    charged: AtomicUsize,
+}
+impl<T> Accounted<T> {
+    /// Create an accounted content that charges `size` bytes. The size of `Accounted` itself is
+    /// always charged.
+    #[inline]
+    pub fn new(kind: Kind, inner: T, size: usize) -> Self {
+        let size = size + size_of::<Self>();
+        charge(kind, size);
+        Self {
+            inner,
+            kind,
+            charged: AtomicUsize::new(size),
+        }
+    }
+    /// Charge `n` more bytes.
+    #[inline]
+    pub fn grow(&self, n: usize) {
+        self.charged.fetch_add(n, Ordering::Relaxed);
+        charge(self.kind, n);
+    }
+    /// Give back `n` bytes.
+    #[inline]
+    pub fn shrink(&self, n: usize) {
+        self.charged.fetch_sub(n, Ordering::Relaxed);
+        uncharge(self.kind, n);
+    }
+    /// Update the charge to `size` bytes, plus the size of `Accounted` itself.
+    #[inline]
+    pub fn resize(&self, size: usize) {
+        let size = size + size_of::<Self>();
+        let old = self.charged.swap(size, Ordering::Relaxed);
+        if size > old {
+            charge(self.kind, size - old);
+        } else {
+            uncharge(self.kind, old - size);
+        }
+    }
+}
+impl<T: Measure> Accounted<RwLock<T>> {
+    /// Create an accounted content that charges its measured size.
+    #[inline]
+    pub fn measured(kind: Kind, inner: T) -> Self {
+        let size = inner.heap_size();
+        Self::new(kind, RwLock::new(inner), size)
+    }
+    /// Lock the content for writing. The charge is updated when the guard is dropped.
+    #[inline]
+    pub fn write(&self) -> HeapGuard<'_, T> {
+        HeapGuard {
+            guard: self.inner.write(),
+            owner: self,
+        }
+    }
+}
+impl<T> Deref for Accounted<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+impl<T> Drop for Accounted<T> {
+    #[inline]
+    fn drop(&mut self) {
+        uncharge(self.kind, *self.charged.get_mut());
+    }
+}
+impl<T: Debug> Debug for Accounted<T> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+/// A write guard of an accounted content, which updates the charge when it is dropped.
+pub struct HeapGuard<'a, T: Measure> {
+    guard: RwLockWriteGuard<'a, T>,
+    owner: &'a Accounted<RwLock<T>>,
+}
+impl<T: Measure> Deref for HeapGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+impl<T: Measure> DerefMut for HeapGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+impl<T: Measure> Drop for HeapGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.owner.resize(self.guard.heap_size());
+    }
+}